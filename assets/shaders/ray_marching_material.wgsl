@@ -8,10 +8,20 @@ struct Camera {
     min_dist: f32,
     max_dist: f32,
     tan_fov: f32,
+    projection: u32,
+    fisheye_angle: f32,
 };
 
+// Must match the PROJECTION_* constants in ray_marching_material.rs
+const PROJECTION_PERSPECTIVE: u32 = 0u;
+const PROJECTION_FISHEYE: u32 = 1u;
+const PROJECTION_EQUIRECTANGULAR: u32 = 2u;
+const PROJECTION_CUBEMAP: u32 = 3u;
+
+const PI: f32 = 3.14159265;
+
 struct Scene {
-    spheres: array<Sphere>,
+    shapes: array<Shape>,
 }
 
 struct Globals {
@@ -38,6 +48,27 @@ var<uniform> camera: Camera;
 @group(2) @binding(1)
 var<storage, read> scene: Scene;
 
+@group(2) @binding(2)
+var<storage, read> materials: array<Material>;
+
+@group(2) @binding(3)
+var<uniform> lighting: Lighting;
+
+@group(2) @binding(4)
+var<storage, read> lights: array<Light>;
+
+@group(2) @binding(5)
+var<storage, read> bvh: Bvh;
+
+@group(2) @binding(6)
+var<uniform> honeycomb: Honeycomb;
+
+@group(2) @binding(7)
+var<storage, read> quotient: Quotient;
+
+@group(2) @binding(8)
+var<uniform> model_view: ModelView;
+
 struct Vertex {
     @location(0) position: vec3<f32>,
     @location(1) normal: vec3<f32>,
@@ -85,142 +116,1048 @@ fn hsv_to_rgb(h: f32, s: f32, v: f32) -> vec3<f32> {
     return rgb + m;
 }
 
+// -- Ambient geometry --
+// Points live in R^4: on the hyperboloid for H^3, the unit sphere for S^3 and the w = 1 plane for
+// E^3. Exactly one GEOMETRY_* def is set by RayMarchingMaterial::specialize.
+
+#ifdef GEOMETRY_EUCLIDEAN
+const GEO_K: f32 = 0.0;
+
+fn geo_dot(u: vec4<f32>, v: vec4<f32>) -> f32 {
+    return dot(u.xyz, v.xyz);
+}
+
+fn geo_cos(t: f32) -> f32 {
+    return 1.0;
+}
+
+fn geo_sin(t: f32) -> f32 {
+    return t;
+}
+
+// Inverse of geo_sin
+fn geo_asin(x: f32) -> f32 {
+    return x;
+}
+
+fn geo_normalize(v: vec4<f32>) -> vec4<f32> {
+    return v / length(v.xyz);
+}
+
+fn geo_normalize_point(p: vec4<f32>) -> vec4<f32> {
+    return vec4(p.xyz, 1.0);
+}
+
+fn geo_tangent(p: vec4<f32>, v: vec4<f32>) -> vec4<f32> {
+    return vec4(v.xyz, 0.0);
+}
+
+fn geo_direction(p: vec4<f32>, q: vec4<f32>) -> vec4<f32> {
+    return geo_normalize(vec4(q.xyz - p.xyz, 0.0));
+}
+
+fn geo_dist(p: vec4<f32>, q: vec4<f32>) -> f32 {
+    return length(q.xyz - p.xyz);
+}
+
+// Fourth local coordinate of p in a frame centred on `centre`, see to_local.
+fn geo_local_w(p: vec4<f32>, centre: vec4<f32>) -> f32 {
+    return 1.0;
+}
+
+fn geo_origin_dist(q: vec4<f32>) -> f32 {
+    return length(q.xyz);
+}
+
+fn geo_ideal_direction(p: vec4<f32>, ideal: vec4<f32>) -> vec4<f32> {
+    return vec4(normalize(ideal.xyz), 0.0);
+}
+
+// Planes are stored as unit normals, Euclidean ones keep their offset from the origin in n.w.
+fn geo_plane_dot(p: vec4<f32>, n: vec4<f32>) -> f32 {
+    return dot(p, n);
+}
+
+fn geo_reflect(p: vec4<f32>, n: vec4<f32>) -> vec4<f32> {
+    return p - 2.0 * geo_plane_dot(p, n) * vec4(n.xyz, 0.0);
+}
+
+fn torus_sdf(q: vec4<f32>, major_radius: f32, minor_radius: f32) -> f32 {
+    return length(vec2(length(q.xz) - major_radius, q.y)) - minor_radius;
+}
+
+// Horospheres are planes in E^3.
+fn horosphere_sdf(q: vec4<f32>) -> f32 {
+    return q.y;
+}
+
+fn pattern_coords(pos: vec4<f32>) -> vec3<f32> {
+    return pos.xyz;
+}
+#endif
+
+#ifdef GEOMETRY_SPHERICAL
+const GEO_K: f32 = 1.0;
+
+fn geo_dot(u: vec4<f32>, v: vec4<f32>) -> f32 {
+    return dot(u, v);
+}
+
+fn geo_cos(t: f32) -> f32 {
+    return cos(t);
+}
+
+fn geo_sin(t: f32) -> f32 {
+    return sin(t);
+}
+
+fn geo_asin(x: f32) -> f32 {
+    return asin(clamp(x, -1.0, 1.0));
+}
+
+fn geo_normalize(v: vec4<f32>) -> vec4<f32> {
+    return normalize(v);
+}
+
+fn geo_normalize_point(p: vec4<f32>) -> vec4<f32> {
+    return normalize(p);
+}
+
+fn geo_tangent(p: vec4<f32>, v: vec4<f32>) -> vec4<f32> {
+    return v - dot(p, v) * p;
+}
+
+fn geo_direction(p: vec4<f32>, q: vec4<f32>) -> vec4<f32> {
+    return geo_normalize(geo_tangent(p, q));
+}
+
+fn geo_dist(p: vec4<f32>, q: vec4<f32>) -> f32 {
+    // From the chord, acos loses too much precision near zero.
+    return 2.0 * asin(min(0.5 * length(q - p), 1.0));
+}
+
+fn geo_local_w(p: vec4<f32>, centre: vec4<f32>) -> f32 {
+    return dot(p, centre);
+}
+
+fn geo_origin_dist(q: vec4<f32>) -> f32 {
+    return acos(clamp(q.w, -1.0, 1.0));
+}
+
+// S^3 has no ideal points, directional lights sit at the point a quarter turn away instead.
+fn geo_ideal_direction(p: vec4<f32>, ideal: vec4<f32>) -> vec4<f32> {
+    return geo_direction(p, vec4(normalize(ideal.xyz), 0.0));
+}
+
+fn geo_plane_dot(p: vec4<f32>, n: vec4<f32>) -> f32 {
+    return dot(p, n);
+}
+
+fn geo_reflect(p: vec4<f32>, n: vec4<f32>) -> vec4<f32> {
+    return p - 2.0 * geo_plane_dot(p, n) * n;
+}
+
+fn torus_sdf(q: vec4<f32>, major_radius: f32, minor_radius: f32) -> f32 {
+    let c = q.w * cos(major_radius) + length(q.xz) * sin(major_radius);
+    return acos(clamp(c, -1.0, 1.0)) - minor_radius;
+}
+
+// S^3 has no horospheres, the nearest analogue is the great sphere through the origin.
+fn horosphere_sdf(q: vec4<f32>) -> f32 {
+    return geo_asin(q.y);
+}
+
+fn pattern_coords(pos: vec4<f32>) -> vec3<f32> {
+    return pos.xyz;
+}
+#endif
+
+#ifdef GEOMETRY_HYPERBOLIC
+const GEO_K: f32 = -1.0;
+
 fn hyp_dot(p1: vec4<f32>, p2: vec4<f32>) -> f32 {
     return dot(p1.xyz, p2.xyz) - p1.w * p2.w;
 }
 
-fn hyp_normalize(p: vec4<f32>) -> vec4<f32> {
-    return p / sqrt(abs(hyp_dot(p, p)));
+fn geo_dot(u: vec4<f32>, v: vec4<f32>) -> f32 {
+    return hyp_dot(u, v);
+}
+
+fn geo_cos(t: f32) -> f32 {
+    return cosh(t);
 }
 
-fn hyp_flow(p: vec4<f32>, v: vec4<f32>, hyp_dist: f32) -> vec4<f32> {
-    return hyp_dist * p + sqrt(hyp_dist * hyp_dist - 1.0) * v; 
+fn geo_sin(t: f32) -> f32 {
+    return sinh(t);
 }
 
-fn hyp_dist(p1: vec4<f32>, p2: vec4<f32>) -> f32 {
-    return acosh(-1.0 * hyp_dot(p1, p2));
+fn geo_asin(x: f32) -> f32 {
+    return asinh(x);
 }
 
-// fn dist(p1: vec4<f32>, p2: vec4<f32>) -> f32 {
-//     let d = p1 - p2;
-//     return acosh(-1.0 * dot(d, d));
-// }
+fn geo_normalize(v: vec4<f32>) -> vec4<f32> {
+    return v / sqrt(abs(hyp_dot(v, v)));
+}
 
-// fn march_ray(origin: vec4<f32>, direction: vec4<f32>, distance: f32) -> vec4<f32> {
-//     return origin + distance * direction;
-// }
+fn geo_normalize_point(p: vec4<f32>) -> vec4<f32> {
+    return geo_normalize(p);
+}
 
-struct Sphere {
-    centre: vec4<f32>,
-    radius: f32,
+fn geo_tangent(p: vec4<f32>, v: vec4<f32>) -> vec4<f32> {
+    return v + hyp_dot(p, v) * p;
+}
+
+fn geo_direction(p: vec4<f32>, q: vec4<f32>) -> vec4<f32> {
+    return geo_normalize(geo_tangent(p, q));
+}
+
+fn geo_dist(p: vec4<f32>, q: vec4<f32>) -> f32 {
+    // From the chord, acosh loses too much precision near zero.
+    return 2.0 * asinh(0.5 * sqrt(max(hyp_dot(q - p, q - p), 0.0)));
+}
+
+fn geo_local_w(p: vec4<f32>, centre: vec4<f32>) -> f32 {
+    return -1.0 * hyp_dot(p, centre);
+}
+
+fn geo_origin_dist(q: vec4<f32>) -> f32 {
+    return acosh(max(q.w, 1.0));
+}
+
+fn geo_ideal_direction(p: vec4<f32>, ideal: vec4<f32>) -> vec4<f32> {
+    return geo_direction(p, ideal);
+}
+
+fn geo_plane_dot(p: vec4<f32>, n: vec4<f32>) -> f32 {
+    return hyp_dot(p, n);
+}
+
+fn geo_reflect(p: vec4<f32>, n: vec4<f32>) -> vec4<f32> {
+    return p - 2.0 * geo_plane_dot(p, n) * n;
+}
+
+// The closest point of the core circle lies in the plane through q and the local up axis.
+fn torus_sdf(q: vec4<f32>, major_radius: f32, minor_radius: f32) -> f32 {
+    let c = q.w * cosh(major_radius) - length(q.xz) * sinh(major_radius);
+    return acosh(max(c, 1.0)) - minor_radius;
+}
+
+fn horosphere_sdf(q: vec4<f32>) -> f32 {
+    return log(q.w + q.y);
+}
+
+// Upper half-space coordinates with the height replaced by distance above the floor horosphere,
+// so patterns lie flat on the floor and have a constant hyperbolic size.
+fn pattern_coords(pos: vec4<f32>) -> vec3<f32> {
+    let h = pos.w + pos.y;
+    return vec3(pos.x / h, log(h), pos.z / h);
+}
+#endif
+
+// Point reached after travelling t along the geodesic from p with unit velocity v.
+fn geo_flow(p: vec4<f32>, v: vec4<f32>, t: f32) -> vec4<f32> {
+    return geo_cos(t) * p + geo_sin(t) * v;
+}
+
+// Velocity of that geodesic at time t.
+fn geo_velocity(p: vec4<f32>, v: vec4<f32>, t: f32) -> vec4<f32> {
+    return -1.0 * GEO_K * geo_sin(t) * p + geo_cos(t) * v;
+}
+
+// Must match the SHAPE_* constants in ray_marching_material.rs
+const SHAPE_SPHERE: u32 = 0u;
+const SHAPE_BOX: u32 = 1u;
+const SHAPE_CYLINDER: u32 = 2u;
+const SHAPE_TORUS: u32 = 3u;
+const SHAPE_PLANE: u32 = 4u;
+const SHAPE_HOROSPHERE: u32 = 5u;
+const SHAPE_EQUIDISTANT: u32 = 6u;
+const SHAPE_MANDELBULB: u32 = 7u;
+
+struct Shape {
+    position: vec4<f32>,
+    right: vec4<f32>,
+    up: vec4<f32>,
+    forward: vec4<f32>,
+    params: vec4<f32>,
+    kind: u32,
     material_id: u32,
 }
 
+// Must match the PATTERN_* constants in ray_marching_material.rs
+const PATTERN_NONE: u32 = 0u;
+const PATTERN_CHECKER: u32 = 1u;
+const PATTERN_STRIPES: u32 = 2u;
+const PATTERN_RAINBOW: u32 = 3u;
+
+struct Material {
+    base_color: vec4<f32>,
+    secondary_color: vec4<f32>,
+    emissive: vec4<f32>,
+    metallic: f32,
+    roughness: f32,
+    pattern: u32,
+    pattern_scale: f32,
+}
+
+// Must match the LIGHT_* constants in lighting.rs
+const LIGHT_DIRECTIONAL: u32 = 0u;
+const LIGHT_POINT: u32 = 1u;
+
+struct Lighting {
+    ambient: vec4<f32>,
+    light_count: u32,
+    shadow_max_steps: u32,
+    ao_samples: u32,
+    shadow_softness: f32,
+    ao_step: f32,
+    ao_strength: f32,
+    normal_epsilon: f32,
+}
+
+// Point lights store a point on the hyperboloid, directional lights a light-like ideal point.
+struct Light {
+    position: vec4<f32>,
+    color: vec4<f32>,
+    kind: u32,
+}
+
 struct SDFResult {
-    pos: vec4<f32>,
-    normal: vec4<f32>,
     distance: f32,
     material_id: u32,
 }
 
-fn sphere_sdf(sphere: Sphere, p: vec4<f32>) -> f32 {
-    return hyp_dist(p, sphere.centre) - sphere.radius;
+// Coordinates of p in the shape's frame, so the shape can be evaluated at the origin.
+fn to_local(shape: Shape, p: vec4<f32>) -> vec4<f32> {
+    let d = p - shape.position;
+    return vec4(
+        geo_dot(d, shape.right),
+        geo_dot(d, shape.up),
+        geo_dot(d, shape.forward),
+        geo_local_w(p, shape.position),
+    );
 }
 
-fn scene_sdf(pos: vec4<f32>) -> SDFResult {
-    var min_dist: f32 = camera.max_dist;
+fn sphere_sdf(q: vec4<f32>, radius: f32) -> f32 {
+    return geo_origin_dist(q) - radius;
+}
 
-    // -- Spheres --
-    var sphere_dist: f32;
-    var min_sphere: u32 = 4294967295u;
-    for (var i: u32 = 0; i < arrayLength(&scene.spheres); i++) {
-        sphere_dist = sphere_sdf(scene.spheres[i], pos);
-        
-        if sphere_dist < min_dist {
-            min_dist = sphere_dist;
-            min_sphere = i;
+// Signed distance to the slab between the planes perpendicular to an axis at distance +-h,
+// where x is the local coordinate along that axis.
+fn slab_sdf(x: f32, w: f32, h: f32) -> f32 {
+    return geo_asin(abs(x) * geo_cos(h) - w * geo_sin(h));
+}
+
+fn box_sdf(q: vec4<f32>, h: vec3<f32>) -> f32 {
+    let d = vec3(slab_sdf(q.x, q.w, h.x), slab_sdf(q.y, q.w, h.y), slab_sdf(q.z, q.w, h.z));
+    return max(d.x, max(d.y, d.z));
+}
+
+fn cylinder_sdf(q: vec4<f32>, radius: f32, half_length: f32) -> f32 {
+    let radial = geo_asin(length(q.xy)) - radius;
+    let cap = slab_sdf(q.z, q.w, half_length);
+    return max(radial, cap);
+}
+
+fn plane_sdf(q: vec4<f32>) -> f32 {
+    return geo_asin(q.y);
+}
+
+fn equidistant_sdf(q: vec4<f32>, distance: f32) -> f32 {
+    return abs(geo_asin(q.y)) - distance;
+}
+
+// Normal coordinates around the origin: the tangent vector whose geodesic ends at q. Euclidean
+// distances in this chart never exceed hyperbolic ones, so Euclidean distance estimates stay safe.
+fn local_chart(q: vec4<f32>) -> vec3<f32> {
+    let r = length(q.xyz);
+    if r < 0.000001 {
+        return q.xyz;
+    }
+    return q.xyz * (geo_origin_dist(q) / r);
+}
+
+fn mandelbulb_de(position: vec3<f32>, power: f32, max_iterations: u32, bailout: f32) -> f32 {
+    // The bulb fits well inside this bound, and the estimate below overshoots far away from it.
+    let bound = length(position);
+    if bound > 2.0 {
+        return bound - 1.5;
+    }
+
+    var z = position;
+    var dr = 1.0;
+    var r = 0.0;
+    for (var i: u32 = 0u; i < max_iterations; i = i + 1u) {
+        r = length(z);
+        if (r > bailout) {
+            break;
         }
+
+        // Convert to polar coordinates
+        var theta = acos(z.z / r);
+        var phi = atan2(z.y, z.x);
+        dr = pow(r, power - 1.0) * power * dr + 1.0;
+
+        // Scale and rotate the point
+        let zr = pow(r, power);
+        theta = theta * power;
+        phi = phi * power;
+
+        // Convert back to Cartesian coordinates
+        z = zr * vec3<f32>(sin(theta) * cos(phi), sin(phi) * sin(theta), cos(theta));
+        z = z + position;
     }
+    return 0.5 * log(r) * r / dr;
+}
 
-    if min_sphere < 4294967295u {
-        let sphere = scene.spheres[min_sphere];
+fn mandelbulb_sdf(q: vec4<f32>, params: vec4<f32>) -> f32 {
+    let scale = params.w;
+    return scale * mandelbulb_de(local_chart(q) / scale, params.x, u32(params.y), params.z);
+}
 
-        let normal = -1.0 * project_to_tangent(pos, sphere.centre - pos);
-        let material_id = sphere.material_id;
+fn shape_sdf(shape: Shape, p: vec4<f32>) -> f32 {
+    let q = to_local(shape, p);
+    switch shape.kind {
+        case SHAPE_SPHERE: {
+            return sphere_sdf(q, shape.params.x);
+        }
+        case SHAPE_BOX: {
+            return box_sdf(q, shape.params.xyz);
+        }
+        case SHAPE_CYLINDER: {
+            return cylinder_sdf(q, shape.params.x, shape.params.y);
+        }
+        case SHAPE_TORUS: {
+            return torus_sdf(q, shape.params.x, shape.params.y);
+        }
+        case SHAPE_PLANE: {
+            return plane_sdf(q);
+        }
+        case SHAPE_HOROSPHERE: {
+            return horosphere_sdf(q);
+        }
+        case SHAPE_EQUIDISTANT: {
+            return equidistant_sdf(q, shape.params.x);
+        }
+        case SHAPE_MANDELBULB: {
+            return mandelbulb_sdf(q, shape.params);
+        }
+        default: {
+            return camera.max_dist;
+        }
+    }
+}
+
+// See PreparedRMBvhNode in bvh.rs
+struct BvhNode {
+    center: vec4<f32>,
+    radius: f32,
+    first: u32,
+    count: u32,
+}
 
+struct Bvh {
+    unbounded_count: u32,
+    nodes: array<BvhNode>,
+}
+
+const BVH_STACK_SIZE: u32 = 32u;
+// Must match BVH_EMPTY in bvh.rs
+const BVH_EMPTY: u32 = 0xffffffffu;
+
+fn nearest_shape(first: u32, end: u32, pos: vec4<f32>, result: SDFResult) -> SDFResult {
+    var nearest = result;
+    for (var i: u32 = first; i < end; i++) {
+        let shape_dist = shape_sdf(scene.shapes[i], pos);
+
+        if shape_dist < nearest.distance {
+            nearest = SDFResult(shape_dist, scene.shapes[i].material_id);
+        }
     }
+    return nearest;
+}
 
-    // -- Horocycle surface --
-    let d_y = log(pos.w + pos.y);
-    if d_y < min_dist {
-        material_id = 2u;
-        min_dist = d_y;
+// Must match the HONEYCOMB_* constants in honeycomb.rs
+const HONEYCOMB_EDGES: u32 = 1u;
+const HONEYCOMB_VERTICES: u32 = 2u;
+const HONEYCOMB_CELLS: u32 = 4u;
+const HONEYCOMB_MAX_FOLDS: u32 = 64u;
+
+// See PreparedRMHoneycomb in honeycomb.rs
+struct Honeycomb {
+    mirrors: array<vec4<f32>, 4>,
+    edge_normals: array<vec4<f32>, 2>,
+    vertex: vec4<f32>,
+    edge_radius: f32,
+    vertex_radius: f32,
+    cell_gap: f32,
+    parts: u32,
+    material_id: u32,
+    cell_material_id: u32,
+}
+
+// Reflects pos into the chamber around the origin. The honeycomb is symmetric under each
+// reflection, so distances to it can be measured from the folded point.
+fn fold_honeycomb(pos: vec4<f32>) -> vec4<f32> {
+    var p = pos;
+    for (var i: u32 = 0u; i < HONEYCOMB_MAX_FOLDS; i++) {
+        var folded = false;
+        for (var j: u32 = 0u; j < 4u; j++) {
+            if geo_plane_dot(p, honeycomb.mirrors[j]) > 0.0 {
+                p = geo_reflect(p, honeycomb.mirrors[j]);
+                folded = true;
+            }
+        }
+        if !folded {
+            break;
+        }
     }
+    return geo_normalize_point(p);
+}
+
+fn honeycomb_sdf(pos: vec4<f32>, result: SDFResult) -> SDFResult {
+    var nearest = result;
+    if honeycomb.parts == 0u {
+        return nearest;
+    }
+
+    let p = fold_honeycomb(pos);
 
-    return SDFResult(min_dist, material_id);
+    if (honeycomb.parts & HONEYCOMB_EDGES) != 0u {
+        let s = vec2(
+            geo_plane_dot(p, honeycomb.edge_normals[0]),
+            geo_plane_dot(p, honeycomb.edge_normals[1]),
+        );
+        let d = geo_asin(length(s)) - honeycomb.edge_radius;
+        if d < nearest.distance {
+            nearest = SDFResult(d, honeycomb.material_id);
+        }
+    }
+    if (honeycomb.parts & HONEYCOMB_VERTICES) != 0u {
+        let d = geo_dist(p, honeycomb.vertex) - honeycomb.vertex_radius;
+        if d < nearest.distance {
+            nearest = SDFResult(d, honeycomb.material_id);
+        }
+    }
+    if (honeycomb.parts & HONEYCOMB_CELLS) != 0u {
+        let d = geo_asin(geo_plane_dot(p, honeycomb.mirrors[3])) + honeycomb.cell_gap;
+        if d < nearest.distance {
+            nearest = SDFResult(d, honeycomb.cell_material_id);
+        }
+    }
+
+    return nearest;
+}
+
+// Must match QUOTIENT_MAX_WRAPS in quotient.rs
+const QUOTIENT_MAX_WRAPS: u32 = 4u;
+
+// See RMQuotientFace in quotient.rs
+struct QuotientFace {
+    plane: vec4<f32>,
+    pairing: mat4x4<f32>,
 }
 
-fn project_to_tangent(p: vec4<f32>, q: vec4<f32>) -> vec4<f32> {
-    let v = q + hyp_dot(p, q) * p;
-    return hyp_normalize(v);
+// See PreparedRMQuotient in quotient.rs, no faces means the whole of space.
+struct Quotient {
+    face_count: u32,
+    faces: array<QuotientFace>,
+}
+
+// Index of the face pos lies farthest outside of, or face_count if it is inside them all.
+fn quotient_exit_face(pos: vec4<f32>) -> u32 {
+    var exit = quotient.face_count;
+    var farthest = 0.0;
+    for (var i: u32 = 0u; i < quotient.face_count; i++) {
+        let s = geo_plane_dot(pos, quotient.faces[i].plane);
+        if s > farthest {
+            farthest = s;
+            exit = i;
+        }
+    }
+    return exit;
+}
+
+// Distance along the geodesic from pos with unit velocity dir until it leaves the polyhedron.
+fn quotient_exit_distance(pos: vec4<f32>, dir: vec4<f32>) -> f32 {
+    var t = camera.max_dist;
+    for (var i: u32 = 0u; i < quotient.face_count; i++) {
+        // Along the geodesic the plane's dot product is a cosh(t) + b sinh(t).
+        let a = geo_plane_dot(pos, quotient.faces[i].plane);
+        let b = geo_plane_dot(dir, quotient.faces[i].plane);
+        if b > -a {
+            t = min(t, max(atanh(-a / b), 0.0));
+        }
+    }
+    return t;
+}
+
+// A geodesic that is sent back into the polyhedron whenever it leaves a quotient, restarting
+// from where it re-enters. `t` is measured from `origin`, not from where the ray first started.
+struct QuotientRay {
+    origin: vec4<f32>,
+    direction: vec4<f32>,
+    pos: vec4<f32>,
+    t: f32,
+}
+
+fn quotient_ray(origin: vec4<f32>, direction: vec4<f32>) -> QuotientRay {
+    return QuotientRay(origin, direction, origin, 0.0);
+}
+
+// How far to march when the scene is `distance` away. Never past a face, what lies beyond it is
+// drawn from its partner.
+fn quotient_step(ray: QuotientRay, distance: f32) -> f32 {
+    if quotient.face_count == 0u {
+        return distance;
+    }
+    let exit = quotient_exit_distance(ray.pos, geo_velocity(ray.origin, ray.direction, ray.t));
+    return min(distance, exit + camera.min_dist);
+}
+
+// Moves `step` further along, through the face pairings of any face the ray crosses.
+fn quotient_advance(ray: QuotientRay, step: f32) -> QuotientRay {
+    var next = ray;
+    next.t += step;
+    next.pos = geo_flow(ray.origin, ray.direction, next.t);
+
+    var velocity = geo_velocity(ray.origin, ray.direction, next.t);
+    var wrapped = false;
+    for (var j: u32 = 0u; j < QUOTIENT_MAX_WRAPS; j++) {
+        let face = quotient_exit_face(next.pos);
+        if face == quotient.face_count {
+            break;
+        }
+        next.pos = quotient.faces[face].pairing * next.pos;
+        velocity = quotient.faces[face].pairing * velocity;
+        wrapped = true;
+    }
+    if wrapped {
+        next = quotient_ray(next.pos, velocity);
+    }
+    return next;
+}
+
+fn scene_sdf(pos: vec4<f32>) -> SDFResult {
+    // -- Horocycle surface, always material slot 0. Quotients have no room for it --
+    var result = SDFResult(camera.max_dist, 0u);
+    if quotient.face_count == 0u {
+        result.distance = min(camera.max_dist, horosphere_sdf(pos));
+    }
+
+    // -- Shapes outside the hierarchy --
+    result = nearest_shape(0u, bvh.unbounded_count, pos, result);
+
+    // -- Honeycomb filling the whole space --
+    result = honeycomb_sdf(pos, result);
+
+    // -- Hierarchy, skipping balls farther away than the closest shape so far --
+    var stack: array<u32, BVH_STACK_SIZE>;
+    stack[0] = 0u;
+    var top = 1u;
+    while top > 0u {
+        top -= 1u;
+        let node = bvh.nodes[stack[top]];
+        if geo_dist(pos, node.center) - node.radius >= result.distance {
+            continue;
+        }
+
+        if node.count > 0u {
+            result = nearest_shape(node.first, node.first + node.count, pos, result);
+        } else if node.first != BVH_EMPTY && top + 2u <= BVH_STACK_SIZE {
+            stack[top] = node.first;
+            stack[top + 1u] = node.first + 1u;
+            top += 2u;
+        }
+    }
+
+    return result;
 }
 
 fn material_to_col(material_id: u32, pos: vec4<f32>) -> vec4<f32> {
-    switch material_id {
-        case 1u: {
-            return vec4(0.0, 0.4, 1.0, 1.0);
+    let material = materials[material_id];
+    let coords = pattern_coords(pos) * material.pattern_scale;
+
+    var base = material.base_color;
+    switch material.pattern {
+        case PATTERN_CHECKER: {
+            let cell = floor(coords);
+            if (i32(cell.x + cell.y + cell.z) & 1) == 1 {
+                base = material.secondary_color;
+            }
         }
-        case 2u: {
-            return vec4(1.0, 1.0, 0.0, 1.0);
+        case PATTERN_STRIPES: {
+            if fract(coords.y) >= 0.5 {
+                base = material.secondary_color;
+            }
         }
-        case 3u: {
-            return vec4(0.1, 0.1, 0.3, 1.0);
+        case PATTERN_RAINBOW: {
+            base = vec4(hsv_to_rgb(fract(coords.x + coords.y + coords.z) * 360.0, 1.0, 1.0), 1.0);
+        }
+        default: {}
+    }
+
+    return base;
+}
+
+struct TangentBasis {
+    e0: vec4<f32>,
+    e1: vec4<f32>,
+    e2: vec4<f32>,
+}
+
+// Orthonormal basis of the tangent space at p, from Gram-Schmidt on the projected coordinate axes.
+fn tangent_basis(p: vec4<f32>) -> TangentBasis {
+    let e0 = geo_normalize(geo_tangent(p, vec4(1.0, 0.0, 0.0, 0.0)));
+    let v1 = geo_tangent(p, vec4(0.0, 1.0, 0.0, 0.0));
+    let e1 = geo_normalize(v1 - geo_dot(v1, e0) * e0);
+    let v2 = geo_tangent(p, vec4(0.0, 0.0, 1.0, 0.0));
+    let e2 = geo_normalize(v2 - geo_dot(v2, e0) * e0 - geo_dot(v2, e1) * e1);
+    return TangentBasis(e0, e1, e2);
+}
+
+// Tetrahedral estimate of the SDF gradient, taken along geodesics in the tangent space at p.
+fn estimate_normal(p: vec4<f32>) -> vec4<f32> {
+    let basis = tangent_basis(p);
+    let h = lighting.normal_epsilon;
+    let k0 = geo_normalize(basis.e0 - basis.e1 - basis.e2);
+    let k1 = geo_normalize(-1.0 * basis.e0 - basis.e1 + basis.e2);
+    let k2 = geo_normalize(-1.0 * basis.e0 + basis.e1 - basis.e2);
+    let k3 = geo_normalize(basis.e0 + basis.e1 + basis.e2);
+
+    let n = k0 * scene_sdf(geo_flow(p, k0, h)).distance
+        + k1 * scene_sdf(geo_flow(p, k1, h)).distance
+        + k2 * scene_sdf(geo_flow(p, k2, h)).distance
+        + k3 * scene_sdf(geo_flow(p, k3, h)).distance;
+
+    return geo_normalize(n);
+}
+
+// Shadow rays toward ideal points stop here, well before cosh overflows.
+const SHADOW_MAX_DIST: f32 = 20.0;
+
+fn soft_shadow(p: vec4<f32>, dir: vec4<f32>, max_t: f32) -> f32 {
+    var res = 1.0;
+    var t = 10.0 * lighting.normal_epsilon;
+    var ray = quotient_advance(quotient_ray(p, dir), t);
+    for (var i: u32 = 0; i < lighting.shadow_max_steps; i++) {
+        if t >= max_t {
+            break;
         }
-        case 4u: {
-            return vec4(0.5, 0.0, 0.0, 1.0);
+
+        let d = scene_sdf(ray.pos).distance;
+        if d < camera.min_dist {
+            return 0.0;
         }
-        case 5u: {
-            return vec4(0.0, 5.0, 0.0, 1.0);
+
+        res = min(res, lighting.shadow_softness * d / t);
+        let advance = quotient_step(ray, max(d, camera.min_dist));
+        t += advance;
+        ray = quotient_advance(ray, advance);
+    }
+    return clamp(res, 0.0, 1.0);
+}
+
+fn ambient_occlusion(p: vec4<f32>, n: vec4<f32>) -> f32 {
+    var occlusion = 0.0;
+    var weight = 1.0;
+    for (var i: u32 = 1; i <= lighting.ao_samples; i++) {
+        let h = lighting.ao_step * f32(i);
+        let d = scene_sdf(quotient_advance(quotient_ray(p, n), h).pos).distance;
+        occlusion += (h - d) * weight;
+        weight *= 0.5;
+    }
+    return clamp(1.0 - lighting.ao_strength * occlusion, 0.0, 1.0);
+}
+
+// `view` is the unit tangent at `pos` pointing back along the ray.
+fn shade(pos: vec4<f32>, view: vec4<f32>, material_id: u32) -> vec3<f32> {
+    let material = materials[material_id];
+    let albedo = material_to_col(material_id, pos).rgb;
+    let n = estimate_normal(pos);
+
+    var ao = 1.0;
+    if lighting.ao_samples > 0u {
+        ao = ambient_occlusion(pos, n);
+    }
+
+    let diffuse_col = albedo * (1.0 - material.metallic);
+    let specular_col = mix(vec3(0.04), albedo, material.metallic);
+    let shininess = exp2(10.0 * (1.0 - material.roughness) + 1.0);
+
+    var color = lighting.ambient.rgb * albedo * ao;
+
+    for (var i: u32 = 0; i < lighting.light_count; i++) {
+        let light = lights[i];
+
+        var l = geo_ideal_direction(pos, light.position);
+        var max_t = SHADOW_MAX_DIST;
+        var attenuation = 1.0;
+        if light.kind == LIGHT_POINT {
+            l = geo_direction(pos, light.position);
+            max_t = geo_dist(pos, light.position);
+            let s = geo_sin(max_t);
+            attenuation = 1.0 / max(s * s, 0.0001);
         }
-        case 6u: {
-            return vec4(0.0, 0.0, 0.5, 1.0);
+
+        let n_dot_l = geo_dot(n, l);
+        if n_dot_l <= 0.0 {
+            continue;
         }
-        default: {
-            return vec4(0.9, 0.9, 0.9, 1.0);
+
+        var shadow = 1.0;
+        if lighting.shadow_max_steps > 0u {
+            shadow = soft_shadow(pos, l, max_t);
         }
+
+        let half_dir = geo_normalize(l + view);
+        let specular = pow(max(geo_dot(n, half_dir), 0.0), shininess);
+
+        color += light.color.rgb * attenuation * shadow * n_dot_l * (diffuse_col + specular_col * specular);
     }
+
+    return color + material.emissive.rgb;
 }
 
 fn ray_march(ray_origin: vec4<f32>, ray_direction: vec4<f32>) -> vec4<f32> {
+    // `dist` is measured from the camera, however many times the ray has wrapped.
+    var ray = quotient_ray(ray_origin, ray_direction);
     var dist: f32 = 0.0;
-    var current_pos: vec4<f32> = ray_origin;
     var current_sdf: SDFResult;
     for (var i: u32 = 0; i < camera.max_steps; i++) {
-        current_sdf = scene_sdf(current_pos);
+        current_sdf = scene_sdf(ray.pos);
         
         if current_sdf.distance < 0.00000001 {
-            return material_to_col(current_sdf.material_id, current_pos);
+            let velocity = geo_velocity(ray.origin, ray.direction, ray.t);
+            return vec4(shade(ray.pos, -1.0 * velocity, current_sdf.material_id), 1.0);
         }
 
-        dist += max(current_sdf.distance, camera.min_dist);
+        let advance = quotient_step(ray, max(current_sdf.distance, camera.min_dist));
+        dist += advance;
 
         if dist >= camera.max_dist {
             return vec4(1.0, 0.0, 1.0, 1.0);
         }
 
-        current_pos = hyp_flow(ray_origin, ray_direction, cosh(dist));
+        ray = quotient_advance(ray, advance);
     }
 
     return vec4(vec3(0.0), 1.0);
 }
 
+// Must match the MODEL_* constants in model_view.rs
+const MODEL_NONE: u32 = 0u;
+const MODEL_POINCARE: u32 = 1u;
+const MODEL_KLEIN: u32 = 2u;
+const MODEL_UPPER_HALF_SPACE: u32 = 3u;
+const MODEL_MAX_DIST: f32 = 6.0;
+const UPPER_HALF_SPACE_EXTENT: f32 = 4.0;
+const UPPER_HALF_SPACE_MIN_HEIGHT: f32 = 0.01;
+const FRUSTUM_SEGMENTS: u32 = 8u;
+
+// Must match the MODEL_* colours in cpu_renderer/model_view.rs
+const MODEL_BACKGROUND: vec3<f32> = vec3(0.02, 0.02, 0.02);
+const MODEL_BOUNDARY: vec3<f32> = vec3(0.15, 0.15, 0.25);
+const MODEL_MARKER: vec3<f32> = vec3(1.0, 0.2, 0.2);
+
+// See PreparedRMModelView in model_view.rs
+struct ModelView {
+    eye: vec4<f32>,
+    forward: vec4<f32>,
+    right: vec4<f32>,
+    up: vec4<f32>,
+    frustum_starts: array<vec4<f32>, FRUSTUM_SEGMENTS>,
+    frustum_ends: array<vec4<f32>, FRUSTUM_SEGMENTS>,
+    camera_position: vec4<f32>,
+    camera_radius: f32,
+    edge_radius: f32,
+    model: u32,
+    show_camera: u32,
+}
+
+#ifdef GEOMETRY_HYPERBOLIC
+// See RMHyperbolicModel::to_hyperboloid.
+fn from_model(q: vec3<f32>) -> vec4<f32> {
+    let r2 = dot(q, q);
+    if model_view.model == MODEL_POINCARE {
+        return vec4(2.0 * q, 1.0 + r2) / (1.0 - r2);
+    }
+    if model_view.model == MODEL_KLEIN {
+        return vec4(q, 1.0) / sqrt(1.0 - r2);
+    }
+    let h2 = q.x * q.x + q.z * q.z;
+    let y2 = q.y * q.y;
+    return vec4(2.0 * q.x, 1.0 - y2 - h2, 2.0 * q.z, 1.0 + y2 + h2) / (2.0 * q.y);
+}
+
+// How far the ray can go from q with the scene `distance` away, see RMHyperbolicModel::step.
+fn model_step(q: vec3<f32>, distance: f32) -> f32 {
+    let r = length(q);
+    if model_view.model == MODEL_POINCARE {
+        return tanh(atanh(r) + 0.5 * distance) - r;
+    }
+    if model_view.model == MODEL_KLEIN {
+        return tanh(atanh(r) + distance) - r;
+    }
+    return q.y * (1.0 - exp(-distance));
+}
+
+// Where the ray enters and leaves the part of the model that is drawn, missing it if it leaves
+// before it enters. See RMHyperbolicModel::clip.
+fn model_clip(eye: vec3<f32>, dir: vec3<f32>) -> vec2<f32> {
+    if model_view.model == MODEL_UPPER_HALF_SPACE {
+        let e = UPPER_HALF_SPACE_EXTENT;
+        let a = (vec3(-e, UPPER_HALF_SPACE_MIN_HEIGHT, -e) - eye) / dir;
+        let b = (vec3(e) - eye) / dir;
+        let near = min(a, b);
+        let far = max(a, b);
+        return vec2(max(max(near.x, near.y), near.z), min(min(far.x, far.y), far.z));
+    }
+
+    var edge = tanh(MODEL_MAX_DIST);
+    if model_view.model == MODEL_POINCARE {
+        edge = tanh(0.5 * MODEL_MAX_DIST);
+    }
+    let b = dot(eye, dir);
+    let disc = b * b - dot(eye, eye) + edge * edge;
+    if disc < 0.0 {
+        return vec2(1.0, 0.0);
+    }
+    return vec2(-b - sqrt(disc), -b + sqrt(disc));
+}
+
+fn segment_sdf(p: vec4<f32>, a: vec4<f32>, b: vec4<f32>) -> f32 {
+    let len = geo_dist(a, b);
+    if len < 0.000001 {
+        return geo_dist(p, a);
+    }
+    let v = geo_direction(a, b);
+
+    // Along the geodesic, -<p, a cosh t + v sinh t> is the cosh of the distance to p.
+    let x = -1.0 * hyp_dot(p, a);
+    let y = hyp_dot(p, v);
+    let t = clamp(atanh(clamp(y / x, -0.999999, 0.999999)), 0.0, len);
+    return acosh(max(x * cosh(t) - y * sinh(t), 1.0));
+}
+
+fn marker_sdf(p: vec4<f32>) -> f32 {
+    var d = geo_dist(p, model_view.camera_position) - model_view.camera_radius;
+    for (var i: u32 = 0u; i < FRUSTUM_SEGMENTS; i++) {
+        d = min(d, segment_sdf(p, model_view.frustum_starts[i], model_view.frustum_ends[i]) - model_view.edge_radius);
+    }
+    return d;
+}
+
+// Marches a straight ray through the model, lighting what it hits as usual in H^3.
+fn model_view_fragment(uv_coords: vec2<f32>) -> vec4<f32> {
+    let eye = model_view.eye.xyz;
+    let dir = normalize((model_view.forward + uv_coords.x * model_view.right + uv_coords.y * model_view.up).xyz);
+
+    // Where the ray crosses the edge of the part of the model that is drawn.
+    let clip = model_clip(eye, dir);
+    if clip.x >= clip.y || clip.y <= 0.0 {
+        return vec4(MODEL_BACKGROUND, 1.0);
+    }
+    let exit = clip.y;
+    var s = max(clip.x, 0.0);
+
+    for (var i: u32 = 0; i < camera.max_steps; i++) {
+        if s >= exit {
+            return vec4(MODEL_BOUNDARY, 1.0);
+        }
+
+        let q = eye + s * dir;
+        let p = from_model(q);
+        let hit = scene_sdf(p);
+        var marker = camera.max_dist;
+        if model_view.show_camera != 0u {
+            marker = marker_sdf(p);
+        }
+        let d = min(hit.distance, marker);
+
+        if d < camera.min_dist {
+            if marker < hit.distance {
+                return vec4(MODEL_MARKER, 1.0);
+            }
+            // Back towards the viewer, along the ray as it runs through H^3.
+            let behind = from_model(q - model_step(q, 0.001) * dir);
+            return vec4(shade(p, geo_direction(p, behind), hit.material_id), 1.0);
+        }
+
+        s += max(model_step(q, d), 0.000001);
+    }
+
+    return vec4(vec3(0.0), 1.0);
+}
+#endif
+
+// Direction out of the camera through uv_coords, in its right, up and forward axes. Zero where
+// the projection doesn't cover the screen. See RMProjection.
+fn view_direction(uv_coords: vec2<f32>) -> vec3<f32> {
+    if camera.projection == PROJECTION_PERSPECTIVE {
+        return vec3(uv_coords, 1.0);
+    }
+
+    // From -1 to 1 across the screen either way.
+    let s = uv_coords / (camera.tan_fov * vec2(camera.aspect_ratio, 1.0));
+
+    if camera.projection == PROJECTION_FISHEYE {
+        let v = uv_coords / camera.tan_fov * camera.fisheye_angle;
+        let angle = length(v);
+        if angle > PI {
+            return vec3(0.0);
+        }
+        if angle < 0.000001 {
+            return vec3(0.0, 0.0, 1.0);
+        }
+        return vec3(sin(angle) * v / angle, cos(angle));
+    }
+
+    if camera.projection == PROJECTION_EQUIRECTANGULAR {
+        let longitude = s.x * PI;
+        let latitude = s.y * PI / 2.0;
+        return vec3(cos(latitude) * sin(longitude), sin(latitude), cos(latitude) * cos(longitude));
+    }
+
+    // Cube map, the face under s and where on it from -1 to 1.
+    let column = clamp(floor((s.x + 1.0) * 1.5), 0.0, 2.0);
+    let a = ((s.x + 1.0) * 1.5 - column) * 2.0 - 1.0;
+    var b = s.y * 2.0 + 1.0;
+    var face = u32(column) + 3u;
+    if s.y > 0.0 {
+        b = s.y * 2.0 - 1.0;
+        face = u32(column);
+    }
+    var direction = vec3(-a, b, -1.0);
+    switch face {
+        case 0u: { direction = vec3(1.0, b, -a); }
+        case 1u: { direction = vec3(-1.0, b, a); }
+        case 2u: { direction = vec3(a, 1.0, -b); }
+        case 3u: { direction = vec3(a, -1.0, b); }
+        case 4u: { direction = vec3(a, b, 1.0); }
+        default: {}
+    }
+    return direction;
+}
+
 @fragment
 fn fragment(in: FragmentIn) -> @location(0) vec4<f32> {
+#ifdef GEOMETRY_HYPERBOLIC
+    if model_view.model != MODEL_NONE {
+        return model_view_fragment(in.uv_coords);
+    }
+#endif
+
+    let local = view_direction(in.uv_coords);
+    if all(local == vec3(0.0)) {
+        return vec4(vec3(0.0), 1.0);
+    }
+
     var camera_origin = camera.position;
-    var ray_direction = camera.forward * 1.0 + (in.uv_coords.x * camera.right) + (in.uv_coords.y * camera.up);
-    ray_direction = hyp_normalize(ray_direction);
+    var ray_direction = camera.forward * local.z + (local.x * camera.right) + (local.y * camera.up);
+    ray_direction = geo_normalize(ray_direction);
 
     var color = ray_march(camera_origin, ray_direction);
 
-    return vec4(color.x, color.y, color.z * 0.1 * f32(arrayLength(&scene.spheres)), 1.0);
+    return vec4(color.x, color.y, color.z, 1.0);
 }
@@ -0,0 +1,250 @@
+use bevy::{
+    math::{Vec3, Vec4},
+    render::render_resource::ShaderType,
+};
+
+use crate::{
+    geometries::{Geometry, ORIGIN},
+    ray_marching_material::{
+        PreparedRMShape, SHAPE_BOX, SHAPE_CYLINDER, SHAPE_MANDELBULB, SHAPE_SPHERE, SHAPE_TORUS,
+    },
+};
+
+/// Shapes per leaf, traversal overhead outweighs culling below this.
+const LEAF_SIZE: usize = 4;
+
+// Must match BVH_EMPTY in the shader
+/// `first` of the leaf standing in for a tree without shapes, which has no children to visit.
+pub const BVH_EMPTY: u32 = u32::MAX;
+
+/// A ball containing every shape below it. Leaves own `count` shapes starting at `first`,
+/// interior nodes have `count == 0` and their children at `first` and `first + 1`. The only
+/// node with `count == 0` and `first == BVH_EMPTY` is the root of an empty tree.
+#[derive(ShaderType, Clone, Debug, PartialEq)]
+pub struct PreparedRMBvhNode {
+    pub center: Vec4,
+    pub radius: f32,
+    pub first: u32,
+    pub count: u32,
+}
+
+/// Hierarchy over the shapes buffer. The first `unbounded_count` shapes (planes, horospheres...)
+/// are outside the tree and always evaluated, see `scene_sdf` in the shader.
+#[derive(ShaderType, Clone, Debug)]
+pub struct PreparedRMBvh {
+    pub unbounded_count: u32,
+    #[size(runtime)]
+    pub nodes: Vec<PreparedRMBvhNode>,
+}
+
+impl Default for PreparedRMBvh {
+    fn default() -> Self {
+        Self {
+            unbounded_count: 0,
+            nodes: vec![EMPTY_LEAF],
+        }
+    }
+}
+
+// Storage buffers cannot be empty, so a tree without shapes is a single leaf owning none.
+const EMPTY_LEAF: PreparedRMBvhNode = PreparedRMBvhNode {
+    center: ORIGIN,
+    radius: 0.0,
+    first: BVH_EMPTY,
+    count: 0,
+};
+
+/// Distance from the shape's position containing all of it, or `None` for unbounded shapes.
+pub fn bounding_radius(geometry: Geometry, shape: &PreparedRMShape) -> Option<f32> {
+    let p = shape.params;
+    match shape.kind {
+        SHAPE_SPHERE => Some(p.x),
+        SHAPE_BOX => box_corner_distance(geometry, p.truncate()),
+        // The rim of each cap is the farthest point, triangle inequality to it via the axis.
+        SHAPE_CYLINDER => Some(p.x + p.y),
+        SHAPE_TORUS => Some(p.x + p.y),
+        // The distance estimate bounds the bulb by radius 1.5 in the scaled chart.
+        SHAPE_MANDELBULB => Some(1.5 * p.w),
+        _ => None,
+    }
+}
+
+// The box is a Euclidean box in the projective (Klein or gnomonic) chart, with half extents
+// tanh(h) or tan(h). Hyperbolic boxes whose corners leave the Klein ball are unbounded.
+fn box_corner_distance(geometry: Geometry, h: Vec3) -> Option<f32> {
+    match geometry {
+        Geometry::Euclidean => Some(h.length()),
+        Geometry::Spherical => {
+            let k = Vec3::new(h.x.tan(), h.y.tan(), h.z.tan()).length();
+            Some(k.atan())
+        }
+        Geometry::Hyperbolic => {
+            let k = Vec3::new(h.x.tanh(), h.y.tanh(), h.z.tanh()).length();
+            (k < 1.0).then(|| k.atanh())
+        }
+    }
+}
+
+/// Reorders `shapes` into unbounded shapes followed by the leaves of a tree built over the rest.
+pub fn build(geometry: Geometry, shapes: Vec<PreparedRMShape>) -> (Vec<PreparedRMShape>, PreparedRMBvh) {
+    let (ordered, bounded): (Vec<_>, Vec<_>) = shapes.into_iter()
+        .map(|shape| (bounding_radius(geometry, &shape), shape))
+        .partition(|(radius, _)| radius.is_none());
+
+    let unbounded_count = ordered.len() as u32;
+    let mut bounded: Vec<_> = bounded.into_iter()
+        .map(|(radius, shape)| (radius.unwrap_or_default(), shape))
+        .collect();
+
+    let mut nodes = vec![EMPTY_LEAF];
+    if !bounded.is_empty() {
+        build_node(geometry, &mut bounded, unbounded_count, 0, &mut nodes);
+    }
+
+    let shapes = ordered.into_iter()
+        .map(|(_, shape)| shape)
+        .chain(bounded.into_iter().map(|(_, shape)| shape))
+        .collect();
+
+    (shapes, PreparedRMBvh { unbounded_count, nodes })
+}
+
+fn build_node(
+    geometry: Geometry,
+    shapes: &mut [(f32, PreparedRMShape)],
+    first: u32,
+    index: usize,
+    nodes: &mut Vec<PreparedRMBvhNode>,
+) {
+    let centers: Vec<Vec4> = shapes.iter().map(|(_, s)| s.position).collect();
+    let center = geometry.centroid(&centers);
+
+    if shapes.len() <= LEAF_SIZE {
+        let radius = shapes.iter()
+            .map(|(r, s)| geometry.distance(center, s.position) + r)
+            .fold(0.0, f32::max);
+        nodes[index] = PreparedRMBvhNode {
+            center,
+            radius,
+            first,
+            count: shapes.len() as u32,
+        };
+        return;
+    }
+
+    // Split at the median along the ambient axis the shapes are most spread out on.
+    let min = centers.iter().copied().fold(Vec4::INFINITY, Vec4::min);
+    let max = centers.iter().copied().fold(Vec4::NEG_INFINITY, Vec4::max);
+    let extent = (max - min).truncate();
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+    shapes.sort_by(|(_, a), (_, b)| a.position[axis].total_cmp(&b.position[axis]));
+
+    let children = nodes.len();
+    nodes.push(EMPTY_LEAF);
+    nodes.push(EMPTY_LEAF);
+
+    let (left, right) = shapes.split_at_mut(shapes.len() / 2);
+    let left_len = left.len() as u32;
+    build_node(geometry, left, first, children, nodes);
+    build_node(geometry, right, first + left_len, children + 1, nodes);
+
+    let radius = [&nodes[children], &nodes[children + 1]].iter()
+        .map(|child| geometry.distance(center, child.center) + child.radius)
+        .fold(0.0, f32::max);
+    nodes[index] = PreparedRMBvhNode {
+        center,
+        radius,
+        first: children as u32,
+        count: 0,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cpu_renderer::sdf,
+        geometries::HypTransform,
+        ray_marching_material::{RMShape, SHAPE_PLANE},
+    };
+
+    fn grid_of_spheres(geometry: Geometry, n: usize) -> Vec<PreparedRMShape> {
+        (0..n)
+            .map(|i| {
+                let direction = Vec3::new((i % 7) as f32 - 3.0, (i % 5) as f32 - 2.0, (i % 3) as f32 + 0.5);
+                let transform = HypTransform::default()
+                    .translate_in(geometry, direction, 0.2 * (i % 11) as f32 + 0.1)
+                    .clone();
+                PreparedRMShape::new(&transform, &RMShape::Sphere { radius: 0.05 }, i as u32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_nodes_contain_their_shapes() {
+        for geometry in Geometry::ALL {
+            let mut shapes = grid_of_spheres(geometry, 50);
+            shapes.push(PreparedRMShape::new(&HypTransform::default(), &RMShape::Plane, 0));
+
+            let (shapes, bvh) = build(geometry, shapes);
+
+            assert_eq!(bvh.unbounded_count, 1);
+            assert_eq!(shapes[0].kind, SHAPE_PLANE);
+            assert_eq!(shapes.len(), 51);
+
+            // Walk from the root, checking every leaf shape against every ancestor's ball.
+            let mut stack = vec![(0, Vec::<usize>::new())];
+            let mut seen = 0;
+            while let Some((index, mut ancestors)) = stack.pop() {
+                ancestors.push(index);
+                let node = &bvh.nodes[index];
+                if node.count == 0 {
+                    stack.push((node.first as usize, ancestors.clone()));
+                    stack.push((node.first as usize + 1, ancestors));
+                    continue;
+                }
+                for shape in &shapes[node.first as usize..(node.first + node.count) as usize] {
+                    seen += 1;
+                    for &a in &ancestors {
+                        let ball = &bvh.nodes[a];
+                        let reach = geometry.distance(ball.center, shape.position) + 0.05;
+                        assert!(reach <= ball.radius + 1e-4, "{geometry:?}");
+                    }
+                }
+            }
+            assert_eq!(seen, 50);
+        }
+    }
+
+    #[test]
+    fn test_box_bound_reaches_corner() {
+        let h = Vec3::new(0.3, 0.2, 0.4);
+        let frame = PreparedRMShape::new(&HypTransform::default(), &RMShape::Plane, 0);
+        for (geometry, k) in [
+            (Geometry::Euclidean, h),
+            (Geometry::Spherical, Vec3::new(h.x.tan(), h.y.tan(), h.z.tan())),
+            (Geometry::Hyperbolic, Vec3::new(h.x.tanh(), h.y.tanh(), h.z.tanh())),
+        ] {
+            let radius = box_corner_distance(geometry, h).unwrap();
+            let corner = HypTransform::default()
+                .translate_in(geometry, k, radius)
+                .translation;
+            let q = sdf::to_local(geometry, &frame, corner);
+            assert!(sdf::box_sdf(geometry, q, h).abs() < 1e-4, "{geometry:?}");
+        }
+        assert_eq!(box_corner_distance(Geometry::Hyperbolic, Vec3::splat(2.0)), None);
+    }
+
+    #[test]
+    fn test_empty_scene_has_a_node() {
+        let (shapes, bvh) = build(Geometry::Hyperbolic, vec![]);
+        assert!(shapes.is_empty());
+        assert_eq!(bvh.nodes, vec![EMPTY_LEAF]);
+    }
+}
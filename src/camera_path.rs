@@ -0,0 +1,358 @@
+// Recorded camera fly-throughs. Keyframes store the full camera state, playback moves along the
+// geodesic between consecutive positions and slerps between the parallel transported frames.
+
+use std::{f32::consts::PI, path::PathBuf};
+
+use bevy::{asset::io::file::FileAssetReader, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    geometries::{Geometry, HypTransform},
+    input::{RMAction, RMActions},
+    ray_marching_material::{LocalOrient, RMCamera, RMCameraSettings},
+};
+
+/// Where Save Path and Load Path go, relative to the assets folder.
+pub const DEFAULT_CAMERA_PATH: &str = "paths/default.rmpath.ron";
+
+pub struct RMCameraPathPlugin;
+
+impl Plugin for RMCameraPathPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RMCameraPathPlayer>()
+            .add_systems(Update, (camera_path_shortcuts, play_camera_path).chain());
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RMCameraKeyframe {
+    /// Seconds from the start of the path.
+    pub time: f32,
+    pub transform: HypTransform,
+    pub orient: LocalOrient,
+    pub settings: RMCameraSettings,
+}
+
+/// Keyframes in increasing time order. Transforms are interpreted in `geometry`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RMCameraPath {
+    pub geometry: Geometry,
+    pub keyframes: Vec<RMCameraKeyframe>,
+}
+
+impl RMCameraPath {
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    /// Appends the camera's current state `spacing` seconds after the last keyframe.
+    pub fn push(&mut self, rm_camera: &RMCamera, spacing: f32) {
+        let time = self.keyframes.last().map_or(0.0, |k| k.time + spacing);
+        self.keyframes.push(RMCameraKeyframe {
+            time,
+            transform: rm_camera.transform.clone(),
+            orient: rm_camera.orient.clone(),
+            settings: rm_camera.settings.clone(),
+        });
+    }
+
+    /// Camera state at `time`, clamped to the ends of the path.
+    pub fn sample(&self, time: f32) -> Option<RMCameraKeyframe> {
+        let next = self.keyframes.iter().position(|k| k.time > time);
+        match next {
+            None => self.keyframes.last().cloned(),
+            Some(0) => self.keyframes.first().cloned(),
+            Some(i) => {
+                let (k0, k1) = (&self.keyframes[i - 1], &self.keyframes[i]);
+                let s = (time - k0.time) / (k1.time - k0.time);
+                Some(interpolate(self.geometry, k0, k1, s))
+            }
+        }
+    }
+
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(text)
+    }
+}
+
+/// Moves `transform` a fraction `s` of the way along the geodesic towards `target`,
+/// parallel transporting its frame.
+fn transport_towards(geometry: Geometry, transform: &HypTransform, target: Vec4, s: f32) -> HypTransform {
+    let mut transform = transform.clone();
+    let dist = geometry.distance(transform.translation, target);
+    if dist < 0.000001 {
+        return transform;
+    }
+
+    let d = geometry.direction(transform.translation, target);
+    let local = Vec3::new(
+        geometry.dot(d, transform.right),
+        geometry.dot(d, transform.up),
+        geometry.dot(d, transform.forward),
+    );
+    transform.translate_in(geometry, local, s * dist);
+    transform
+}
+
+pub fn interpolate(geometry: Geometry, k0: &RMCameraKeyframe, k1: &RMCameraKeyframe, s: f32) -> RMCameraKeyframe {
+    let p1 = k1.transform.translation;
+    let f0 = transport_towards(geometry, &k0.transform, p1, s);
+    // Bring the end frame back to the same point so both frames share a tangent space.
+    let f1 = transport_towards(geometry, &k1.transform, k0.transform.translation, 1.0 - s);
+
+    // Rotation taking f0 to f1, in f0's basis.
+    let basis = [f0.right, f0.up, f0.forward];
+    let coords = |v: Vec4| Vec3::from_array(basis.map(|b| geometry.dot(v, b)));
+    let rotation = Mat3::from_cols(coords(f1.right), coords(f1.up), coords(f1.forward));
+    let rotation = Quat::IDENTITY.slerp(Quat::from_mat3(&rotation).normalize(), s);
+
+    let [right, up, forward] = [Vec3::X, Vec3::Y, Vec3::Z].map(|axis| {
+        let c = rotation * axis;
+        geometry.normalize(c.x * f0.right + c.y * f0.up + c.z * f0.forward)
+    });
+
+    let mut orient = LocalOrient::default();
+    orient
+        .set_yaw(k0.orient.yaw() + s * wrap_angle(k1.orient.yaw() - k0.orient.yaw()))
+        .set_pitch(k0.orient.pitch() + s * (k1.orient.pitch() - k0.orient.pitch()));
+
+    RMCameraKeyframe {
+        time: k0.time + s * (k1.time - k0.time),
+        transform: HypTransform {
+            translation: f0.translation,
+            forward,
+            up,
+            right,
+        },
+        orient,
+        settings: RMCameraSettings {
+            max_dist: k0.settings.max_dist + s * (k1.settings.max_dist - k0.settings.max_dist),
+            min_dist: k0.settings.min_dist + s * (k1.settings.min_dist - k0.settings.min_dist),
+            tan_fov: k0.settings.tan_fov + s * (k1.settings.tan_fov - k0.settings.tan_fov),
+            fisheye_angle: k0.settings.fisheye_angle + s * (k1.settings.fisheye_angle - k0.settings.fisheye_angle),
+            ..k0.settings.clone()
+        },
+    }
+}
+
+// Shortest signed angle, so yaw interpolates across the wrap at ±TAU.
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+/// Recording and playback state.
+#[derive(Resource, Debug, Clone)]
+pub struct RMCameraPathPlayer {
+    pub path: RMCameraPath,
+    pub file: PathBuf,
+    /// Seconds between keyframes added with `K`.
+    pub keyframe_spacing: f32,
+    pub playhead: f32,
+    pub playing: bool,
+    pub looping: bool,
+}
+
+impl Default for RMCameraPathPlayer {
+    fn default() -> Self {
+        Self {
+            path: RMCameraPath::default(),
+            file: FileAssetReader::get_base_path().join("assets").join(DEFAULT_CAMERA_PATH),
+            keyframe_spacing: 2.0,
+            playhead: 0.0,
+            playing: false,
+            looping: false,
+        }
+    }
+}
+
+impl RMCameraPathPlayer {
+    pub fn add_keyframe(&mut self, rm_camera: &RMCamera, geometry: Geometry) {
+        if self.path.keyframes.is_empty() {
+            self.path.geometry = geometry;
+        } else if self.path.geometry != geometry {
+            warn!("camera path was recorded in {:?}, clear it first", self.path.geometry);
+            return;
+        }
+        let spacing = self.keyframe_spacing;
+        self.path.push(rm_camera, spacing);
+    }
+
+    pub fn play(&mut self, geometry: Geometry) {
+        if self.path.keyframes.is_empty() {
+            return;
+        }
+        if self.path.geometry != geometry {
+            warn!("camera path was recorded in {:?}, switch geometry to play it", self.path.geometry);
+            return;
+        }
+        self.playhead = 0.0;
+        self.playing = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn save(&self) {
+        let result = self.path.to_ron()
+            .map_err(|err| err.to_string())
+            .and_then(|text| {
+                if let Some(dir) = self.file.parent() {
+                    std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+                }
+                std::fs::write(&self.file, text).map_err(|err| err.to_string())
+            });
+        match result {
+            Ok(()) => info!("saved camera path to {}", self.file.display()),
+            Err(err) => error!("could not save camera path to {}: {err}", self.file.display()),
+        }
+    }
+
+    pub fn load(&mut self) {
+        let result = std::fs::read_to_string(&self.file)
+            .map_err(|err| err.to_string())
+            .and_then(|text| RMCameraPath::from_ron(&text).map_err(|err| err.to_string()));
+        match result {
+            Ok(path) => {
+                self.path = path;
+                self.stop();
+            }
+            Err(err) => error!("could not load camera path from {}: {err}", self.file.display()),
+        }
+    }
+}
+
+/// Run condition, camera controls are disabled while a path plays.
+pub fn camera_path_playing(player: Res<RMCameraPathPlayer>) -> bool {
+    player.playing
+}
+
+fn camera_path_shortcuts(
+    actions: Res<RMActions>,
+    mut player: ResMut<RMCameraPathPlayer>,
+    rm_camera: Res<RMCamera>,
+    geometry: Res<Geometry>,
+) {
+    if actions.just_pressed(RMAction::AddKeyframe) && !player.playing {
+        player.add_keyframe(&rm_camera, *geometry);
+    }
+    if actions.just_pressed(RMAction::PlayPath) {
+        if player.playing {
+            player.stop();
+        } else {
+            player.play(*geometry);
+        }
+    }
+}
+
+fn play_camera_path(
+    mut player: ResMut<RMCameraPathPlayer>,
+    mut rm_camera: ResMut<RMCamera>,
+    time: Res<Time>,
+) {
+    if !player.playing {
+        return;
+    }
+
+    player.playhead += time.delta_secs();
+    let duration = player.path.duration();
+    if player.playhead > duration {
+        if player.looping && duration > 0.0 {
+            player.playhead %= duration;
+        } else {
+            player.playhead = duration;
+            player.stop();
+        }
+    }
+
+    if let Some(keyframe) = player.path.sample(player.playhead) {
+        apply_keyframe(&mut rm_camera, keyframe);
+    }
+}
+
+/// Moves the camera to a sampled keyframe, keeping the window's aspect ratio and the camera's
+/// pitch limits.
+pub fn apply_keyframe(rm_camera: &mut RMCamera, keyframe: RMCameraKeyframe) {
+    let aspect_ratio = rm_camera.settings.aspect_ratio;
+    rm_camera.transform = keyframe.transform;
+    rm_camera.orient
+        .set_yaw(keyframe.orient.yaw())
+        .set_pitch(keyframe.orient.pitch());
+    rm_camera.settings = RMCameraSettings {
+        aspect_ratio,
+        ..keyframe.settings
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THRESH: f32 = 1e-4;
+
+    fn keyframe(time: f32, transform: HypTransform) -> RMCameraKeyframe {
+        RMCameraKeyframe {
+            time,
+            transform,
+            orient: LocalOrient::default(),
+            settings: RMCameraSettings::default(),
+        }
+    }
+
+    #[test]
+    fn test_interpolation_follows_geodesic() {
+        for geometry in Geometry::ALL {
+            let start = HypTransform::default();
+            let end = start.clone().translate_in(geometry, Vec3::new(1.0, 0.5, 0.0), 1.2).clone();
+            let path = RMCameraPath {
+                geometry,
+                keyframes: vec![keyframe(0.0, start.clone()), keyframe(2.0, end.clone())],
+            };
+
+            let mid = path.sample(1.0).unwrap().transform;
+
+            assert!((geometry.distance(start.translation, mid.translation) - 0.6).abs() < THRESH);
+            assert!((geometry.distance(mid.translation, end.translation) - 0.6).abs() < THRESH);
+            // A pure translation keeps the frame parallel, so the end frame is reproduced.
+            let last = path.sample(2.0).unwrap().transform;
+            assert!((last.forward - end.forward).length() < THRESH, "{geometry:?}");
+        }
+    }
+
+    #[test]
+    fn test_rotation_is_slerped() {
+        let geometry = Geometry::Hyperbolic;
+        let start = HypTransform::default();
+        let mut end = start.clone();
+        // Quarter turn about up.
+        end.forward = start.right;
+        end.right = -1.0 * start.forward;
+
+        let mid = interpolate(geometry, &keyframe(0.0, start.clone()), &keyframe(1.0, end), 0.5).transform;
+
+        let angle = geometry.dot(mid.forward, start.forward).clamp(-1.0, 1.0).acos();
+        assert!((angle - PI / 4.0).abs() < THRESH);
+        assert!(geometry.dot(mid.forward, mid.right).abs() < THRESH);
+        assert!((mid.up - start.up).length() < THRESH);
+    }
+
+    #[test]
+    fn test_sample_clamps_and_round_trips() {
+        let mut path = RMCameraPath::default();
+        let mut rm_camera = RMCamera::default();
+        path.push(&rm_camera, 1.5);
+        rm_camera.transform.translate(Vec3::Z, 1.0);
+        path.push(&rm_camera, 1.5);
+
+        assert_eq!(path.duration(), 1.5);
+        assert!(path.sample(-1.0).unwrap().transform.translation == Vec4::W);
+
+        let parsed = RMCameraPath::from_ron(&path.to_ron().unwrap()).unwrap();
+        assert_eq!(parsed.keyframes.len(), 2);
+        assert_eq!(parsed.keyframes[1].time, 1.5);
+    }
+}
@@ -0,0 +1,706 @@
+// Headless reference implementation of the ray marcher in `assets/shaders/ray_marching_material.wgsl`.
+// It consumes the same prepared camera and scene buffers that `update_material` uploads, so scenes
+// can be regression tested without a GPU and GPU captures can be diffed against it.
+
+use std::{f32::consts::PI, thread};
+
+use bevy::{
+    asset::RenderAssetUsages,
+    color::Srgba,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+use crate::{
+    geometries::Geometry,
+    model_view::{PreparedRMModelView, RMModelView},
+    quotient::{exit_distance, exit_face, RMQuotientFace, QUOTIENT_MAX_WRAPS},
+    ray_marching_material::{
+        PreparedRMCamera, PreparedRMScene, RMCamera, PATTERN_CHECKER, PATTERN_RAINBOW,
+        PATTERN_STRIPES, PROJECTION_EQUIRECTANGULAR, PROJECTION_FISHEYE, PROJECTION_PERSPECTIVE,
+    },
+};
+
+pub mod sdf;
+use sdf::scene_sdf;
+
+pub mod shading;
+use shading::shade;
+
+pub mod model_view;
+use model_view::model_view_fragment;
+
+/// A linear colour buffer produced by [`render`], stored row-major from the top-left pixel.
+#[derive(Debug, Clone)]
+pub struct ReferenceImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<LinearRgba>,
+}
+
+impl ReferenceImage {
+    pub fn get(&self, x: u32, y: u32) -> LinearRgba {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Largest per-channel difference between two images of the same size.
+    pub fn max_abs_diff(&self, other: &ReferenceImage) -> Option<f32> {
+        if self.width != other.width || self.height != other.height {
+            return None;
+        }
+
+        let diff = self.pixels.iter()
+            .zip(other.pixels.iter())
+            .map(|(a, b)| {
+                (a.red - b.red).abs()
+                    .max((a.green - b.green).abs())
+                    .max((a.blue - b.blue).abs())
+                    .max((a.alpha - b.alpha).abs())
+            })
+            .fold(0.0, f32::max);
+
+        Some(diff)
+    }
+
+    /// Reads back a GPU capture so it can be compared with a reference render.
+    pub fn from_image(image: &Image) -> Option<Self> {
+        let width = image.width();
+        let height = image.height();
+        let format = image.texture_descriptor.format;
+
+        let pixels = match format {
+            TextureFormat::Rgba8UnormSrgb | TextureFormat::Bgra8UnormSrgb => image.data
+                .chunks_exact(4)
+                .map(|c| {
+                    let (r, b) = if format == TextureFormat::Bgra8UnormSrgb {
+                        (c[2], c[0])
+                    } else {
+                        (c[0], c[2])
+                    };
+                    LinearRgba::from(Srgba::rgba_u8(r, c[1], b, c[3]))
+                })
+                .collect(),
+            TextureFormat::Rgba16Float => image.data
+                .chunks_exact(8)
+                .map(|c| {
+                    let f = |i: usize| f16_to_f32(u16::from_le_bytes([c[i], c[i + 1]]));
+                    LinearRgba::new(f(0), f(2), f(4), f(6))
+                })
+                .collect(),
+            TextureFormat::Rgba32Float => image.data
+                .chunks_exact(16)
+                .map(|c| {
+                    let f = |i: usize| f32::from_le_bytes([c[i], c[i + 1], c[i + 2], c[i + 3]]);
+                    LinearRgba::new(f(0), f(4), f(8), f(12))
+                })
+                .collect(),
+            _ => return None,
+        };
+
+        Some(Self { width, height, pixels })
+    }
+
+    pub fn into_image(self) -> Image {
+        let data = self.pixels.iter()
+            .flat_map(|c| [c.red, c.green, c.blue, c.alpha])
+            .flat_map(f32::to_le_bytes)
+            .collect();
+
+        Image::new(
+            Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba32Float,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+    }
+}
+
+/// IEEE half precision, as `Rgba16Float` textures store it.
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+pub fn hsv_to_rgb(h: f32, s: f32, v: f32) -> Vec3 {
+    let c = v * s;
+    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+    let m = v - c;
+
+    let rgb = if h < 60.0 {
+        Vec3::new(c, x, 0.0)
+    } else if h < 120.0 {
+        Vec3::new(x, c, 0.0)
+    } else if h < 180.0 {
+        Vec3::new(0.0, c, x)
+    } else if h < 240.0 {
+        Vec3::new(0.0, x, c)
+    } else if h < 300.0 {
+        Vec3::new(x, 0.0, c)
+    } else {
+        Vec3::new(c, 0.0, x)
+    };
+
+    rgb + m
+}
+
+// WGSL's `fract` rounds towards negative infinity, unlike `f32::fract`.
+fn wgsl_fract(x: f32) -> f32 {
+    x - x.floor()
+}
+
+pub fn pattern_coords(geometry: Geometry, pos: Vec4) -> Vec3 {
+    match geometry {
+        Geometry::Hyperbolic => {
+            let h = pos.w + pos.y;
+            Vec3::new(pos.x / h, h.ln(), pos.z / h)
+        }
+        _ => pos.truncate(),
+    }
+}
+
+pub fn material_to_col(scene: &PreparedRMScene, material_id: u32, pos: Vec4) -> LinearRgba {
+    let material = &scene.materials.materials[material_id as usize];
+    let coords = pattern_coords(scene.geometry, pos) * material.pattern_scale;
+
+    let mut base = material.base_color;
+    match material.pattern {
+        PATTERN_CHECKER => {
+            let cell = coords.floor();
+            if ((cell.x + cell.y + cell.z) as i32 & 1) == 1 {
+                base = material.secondary_color;
+            }
+        }
+        PATTERN_STRIPES if wgsl_fract(coords.y) >= 0.5 => {
+            base = material.secondary_color;
+        }
+        PATTERN_RAINBOW => {
+            let hue = wgsl_fract(coords.x + coords.y + coords.z) * 360.0;
+            base = hsv_to_rgb(hue, 1.0, 1.0).extend(1.0);
+        }
+        _ => {}
+    }
+
+    LinearRgba::new(base.x, base.y, base.z, base.w)
+}
+
+/// Where a marched ray hit the scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub position: Vec4,
+    /// Unit tangent of the ray at `position`, after any wrapping through quotient faces.
+    pub velocity: Vec4,
+    /// Distance travelled from the ray's origin.
+    pub distance: f32,
+    pub material_id: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RayMarch {
+    Hit(RayHit),
+    /// Went further than `max_dist`.
+    Escaped,
+    /// Ran out of iterations before either.
+    Exhausted,
+}
+
+/// A geodesic that is sent back into the polyhedron whenever it leaves a quotient, restarting
+/// from where it re-enters. See `QuotientRay` in the shader.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuotientRay {
+    pub origin: Vec4,
+    pub direction: Vec4,
+    pub position: Vec4,
+    /// Distance from `origin`, not from where the ray first started.
+    pub t: f32,
+}
+
+impl QuotientRay {
+    pub fn new(origin: Vec4, direction: Vec4) -> Self {
+        Self { origin, direction, position: origin, t: 0.0 }
+    }
+
+    pub fn velocity(&self, geometry: Geometry) -> Vec4 {
+        geometry.velocity(self.origin, self.direction, self.t)
+    }
+
+    /// How far to march when the scene is `distance` away. Never past a face, what lies beyond
+    /// it is drawn from its partner.
+    pub fn step(&self, geometry: Geometry, faces: &[RMQuotientFace], distance: f32, camera: &PreparedRMCamera) -> f32 {
+        if faces.is_empty() {
+            return distance;
+        }
+        let exit = exit_distance(faces, self.position, self.velocity(geometry), camera.max_dist);
+        distance.min(exit + camera.min_dist)
+    }
+
+    /// Moves `step` further along, through the face pairings of any face it crosses.
+    pub fn advance(&mut self, geometry: Geometry, faces: &[RMQuotientFace], step: f32) {
+        self.t += step;
+        self.position = geometry.flow(self.origin, self.direction, self.t);
+
+        let mut velocity = self.velocity(geometry);
+        let mut wrapped = false;
+        for _ in 0..QUOTIENT_MAX_WRAPS {
+            let Some(face) = exit_face(faces, self.position) else {
+                break;
+            };
+            self.position = face.pairing * self.position;
+            velocity = face.pairing * velocity;
+            wrapped = true;
+        }
+        if wrapped {
+            *self = Self::new(self.position, velocity);
+        }
+    }
+}
+
+pub fn march(
+    camera: &PreparedRMCamera,
+    scene: &PreparedRMScene,
+    ray_origin: Vec4,
+    ray_direction: Vec4,
+) -> RayMarch {
+    let geometry = scene.geometry;
+    let faces = scene.quotient.faces();
+
+    // `dist` is measured from the camera, however many times the ray has wrapped.
+    let mut ray = QuotientRay::new(ray_origin, ray_direction);
+    let mut dist = 0.0;
+    for _ in 0..camera.max_iterations {
+        let current_sdf = scene_sdf(camera, scene, ray.position);
+
+        if current_sdf.distance < 0.00000001 {
+            return RayMarch::Hit(RayHit {
+                position: ray.position,
+                velocity: ray.velocity(geometry),
+                distance: dist,
+                material_id: current_sdf.material_id,
+            });
+        }
+
+        let advance = ray.step(geometry, faces, current_sdf.distance.max(camera.min_dist), camera);
+        dist += advance;
+
+        if dist >= camera.max_dist {
+            return RayMarch::Escaped;
+        }
+
+        ray.advance(geometry, faces, advance);
+    }
+
+    RayMarch::Exhausted
+}
+
+pub fn ray_march(
+    camera: &PreparedRMCamera,
+    scene: &PreparedRMScene,
+    ray_origin: Vec4,
+    ray_direction: Vec4,
+) -> LinearRgba {
+    match march(camera, scene, ray_origin, ray_direction) {
+        RayMarch::Hit(hit) => {
+            let col = shade(camera, scene, hit.position, -1.0 * hit.velocity, hit.material_id);
+            LinearRgba::new(col.x, col.y, col.z, 1.0)
+        }
+        RayMarch::Escaped => LinearRgba::new(1.0, 0.0, 1.0, 1.0),
+        RayMarch::Exhausted => LinearRgba::new(0.0, 0.0, 0.0, 1.0),
+    }
+}
+
+/// Equivalent of the vertex stage: maps a pixel centre to the `uv_coords` the fragment stage sees.
+pub fn pixel_to_uv(camera: &PreparedRMCamera, x: u32, y: u32, width: u32, height: u32) -> Vec2 {
+    screen_to_uv(
+        camera,
+        Vec2::new(x as f32 + 0.5, y as f32 + 0.5),
+        Vec2::new(width as f32, height as f32),
+    )
+}
+
+/// `uv_coords` at `position` on a screen of `size`, both measured from the top-left corner the
+/// way window cursor positions are.
+pub fn screen_to_uv(camera: &PreparedRMCamera, position: Vec2, size: Vec2) -> Vec2 {
+    // The quad's uv origin is the bottom-left corner, image rows start at the top.
+    let uv = Vec2::new(position.x / size.x, 1.0 - position.y / size.y);
+    let mut uv_coords = (uv * 2.0 - 1.0) * camera.tan_fov;
+    uv_coords.x *= camera.aspect_ratio;
+    uv_coords
+}
+
+/// Direction out of the camera through `uv_coords`, in its right, up and forward axes. `None`
+/// where the projection doesn't cover the screen, see `RMProjection`.
+pub fn view_direction(camera: &PreparedRMCamera, uv_coords: Vec2) -> Option<Vec3> {
+    if camera.projection == PROJECTION_PERSPECTIVE {
+        return Some(uv_coords.extend(1.0));
+    }
+
+    // From -1 to 1 across the screen either way.
+    let s = uv_coords / (camera.tan_fov * Vec2::new(camera.aspect_ratio, 1.0));
+
+    if camera.projection == PROJECTION_FISHEYE {
+        let v = uv_coords / camera.tan_fov * camera.fisheye_angle;
+        let angle = v.length();
+        if angle > PI {
+            return None;
+        }
+        if angle < 0.000001 {
+            return Some(Vec3::Z);
+        }
+        return Some((angle.sin() * v / angle).extend(angle.cos()));
+    }
+
+    if camera.projection == PROJECTION_EQUIRECTANGULAR {
+        let longitude = s.x * PI;
+        let latitude = s.y * PI / 2.0;
+        return Some(Vec3::new(
+            latitude.cos() * longitude.sin(),
+            latitude.sin(),
+            latitude.cos() * longitude.cos(),
+        ));
+    }
+
+    // Cube map, the face under s and where on it from -1 to 1.
+    let column = ((s.x + 1.0) * 1.5).floor().clamp(0.0, 2.0);
+    let a = ((s.x + 1.0) * 1.5 - column) * 2.0 - 1.0;
+    let (face, b) = if s.y > 0.0 {
+        (column as u32, s.y * 2.0 - 1.0)
+    } else {
+        (column as u32 + 3, s.y * 2.0 + 1.0)
+    };
+    Some(match face {
+        0 => Vec3::new(1.0, b, -a),
+        1 => Vec3::new(-1.0, b, a),
+        2 => Vec3::new(a, 1.0, -b),
+        3 => Vec3::new(a, -1.0, b),
+        4 => Vec3::new(a, b, 1.0),
+        _ => Vec3::new(-a, b, -1.0),
+    })
+}
+
+/// Origin and initial direction of the ray the fragment stage marches at `uv_coords`, if any.
+pub fn camera_ray(camera: &PreparedRMCamera, geometry: Geometry, uv_coords: Vec2) -> Option<(Vec4, Vec4)> {
+    let local = view_direction(camera, uv_coords)?;
+    let ray_direction = geometry.normalize(
+        camera.forward * local.z + (local.x * camera.right) + (local.y * camera.up),
+    );
+    Some((camera.position, ray_direction))
+}
+
+pub fn fragment(camera: &PreparedRMCamera, scene: &PreparedRMScene, uv_coords: Vec2) -> LinearRgba {
+    let Some((camera_origin, ray_direction)) = camera_ray(camera, scene.geometry, uv_coords) else {
+        return LinearRgba::new(0.0, 0.0, 0.0, 1.0);
+    };
+
+    let color = ray_march(camera, scene, camera_origin, ray_direction);
+
+    LinearRgba::new(color.red, color.green, color.blue, 1.0)
+}
+
+/// Renders `scene` as seen from `rm_camera` into a `width` x `height` buffer.
+/// The aspect ratio is taken from the buffer size, as `resize_event` does for the window.
+pub fn render(
+    rm_camera: &RMCamera,
+    scene: &PreparedRMScene,
+    width: u32,
+    height: u32,
+) -> ReferenceImage {
+    render_pixels(rm_camera, width, height, |camera, uv_coords| fragment(camera, scene, uv_coords))
+}
+
+/// Like [`render`], but drawing the model of H^3 `model_view` picks with `rm_camera` marked
+/// in it. Falls back to the camera's view when there is no model to draw.
+pub fn render_model_view(
+    model_view: &RMModelView,
+    rm_camera: &RMCamera,
+    scene: &PreparedRMScene,
+    width: u32,
+    height: u32,
+) -> ReferenceImage {
+    let Some(model) = model_view.active(scene.geometry) else {
+        return render(rm_camera, scene, width, height);
+    };
+
+    let mut rm_camera = rm_camera.clone();
+    rm_camera.settings.aspect_ratio = width as f32 / height as f32;
+    let view = PreparedRMModelView::new(model_view, &rm_camera, scene.geometry);
+
+    render_pixels(&rm_camera, width, height, |camera, uv_coords| {
+        model_view_fragment(&view, model, camera, scene, uv_coords)
+    })
+}
+
+fn render_pixels(
+    rm_camera: &RMCamera,
+    width: u32,
+    height: u32,
+    fragment: impl Fn(&PreparedRMCamera, Vec2) -> LinearRgba + Sync,
+) -> ReferenceImage {
+    let mut camera: PreparedRMCamera = rm_camera.into();
+    camera.aspect_ratio = width as f32 / height as f32;
+
+    let mut pixels = vec![LinearRgba::BLACK; (width * height) as usize];
+    if pixels.is_empty() {
+        return ReferenceImage { width, height, pixels };
+    }
+
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let rows_per_chunk = (height as usize).div_ceil(threads);
+
+    thread::scope(|s| {
+        for (chunk_index, chunk) in pixels.chunks_mut(rows_per_chunk * width as usize).enumerate() {
+            let camera = &camera;
+            let fragment = &fragment;
+            s.spawn(move || {
+                let first_row = chunk_index * rows_per_chunk;
+                for (i, pixel) in chunk.iter_mut().enumerate() {
+                    let x = (i % width as usize) as u32;
+                    let y = (first_row + i / width as usize) as u32;
+                    *pixel = fragment(camera, pixel_to_uv(camera, x, y, width, height));
+                }
+            });
+        }
+    });
+
+    ReferenceImage { width, height, pixels }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bvh,
+        geometries::{HypTransform, ORIGIN},
+        lighting::RMLighting,
+        quotient::RMFundamentalDomain,
+        ray_marching_material::{
+            PreparedRMShape, PreparedRMShapes, RMMaterial, RMMaterialTable, RMProjection, RMShape,
+        },
+    };
+
+    fn raised_camera() -> RMCamera {
+        raised_camera_in(Geometry::Hyperbolic)
+    }
+
+    fn raised_camera_in(geometry: Geometry) -> RMCamera {
+        let mut cam = RMCamera::default();
+        cam.transform.translate_in(geometry, Vec3::new(0.0, 1.0, 0.0), 0.5);
+        cam
+    }
+
+    const SPHERE_COLOR: LinearRgba = LinearRgba::rgb(0.0, 0.4, 1.0);
+
+    fn sphere_ahead(cam: &RMCamera, distance: f32, radius: f32) -> PreparedRMScene {
+        sphere_ahead_in(Geometry::Hyperbolic, cam, distance, radius)
+    }
+
+    fn sphere_ahead_in(geometry: Geometry, cam: &RMCamera, distance: f32, radius: f32) -> PreparedRMScene {
+        let transform = cam.transform.clone()
+            .translate_in(geometry, Vec3::Z, distance)
+            .clone();
+
+        let mut materials = RMMaterialTable::default();
+        let material_id = materials.slot(&RMMaterial::Flat(SPHERE_COLOR));
+
+        // Pure white ambient light and no lights, so hits come out in their albedo.
+        let lighting = RMLighting {
+            ambient: LinearRgba::WHITE,
+            directional_lights: vec![],
+            point_lights: vec![],
+            ambient_occlusion: false,
+            ..default()
+        };
+
+        let (shapes, bvh) = bvh::build(
+            geometry,
+            vec![PreparedRMShape::new(&transform, &RMShape::Sphere { radius }, material_id)],
+        );
+
+        PreparedRMScene {
+            shapes: PreparedRMShapes { shapes },
+            bvh,
+            materials: materials.into(),
+            lighting: (&lighting).into(),
+            lights: (&lighting).into(),
+            geometry,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn test_flow_matches_geodesic() {
+        for geometry in Geometry::ALL {
+            let t = HypTransform::default();
+            let moved = t.clone().translate_in(geometry, Vec3::Z, 0.8).translation;
+            let flowed = geometry.flow(t.translation, t.forward, 0.8);
+
+            assert!((moved - flowed).length() < 1e-5, "{geometry:?}");
+        }
+    }
+
+    #[test]
+    fn test_sphere_ahead_is_hit_in_each_geometry() {
+        for geometry in Geometry::ALL {
+            let cam = raised_camera_in(geometry);
+            let scene = sphere_ahead_in(geometry, &cam, 1.0, 0.3);
+            let prepared: PreparedRMCamera = (&cam).into();
+
+            let col = ray_march(&prepared, &scene, prepared.position, prepared.forward);
+
+            assert_eq!(col, SPHERE_COLOR, "{geometry:?}");
+        }
+    }
+
+    #[test]
+    fn test_sphere_ahead_is_hit() {
+        let cam = raised_camera();
+        let scene = sphere_ahead(&cam, 2.0, 0.5);
+        let prepared: PreparedRMCamera = (&cam).into();
+
+        let col = ray_march(&prepared, &scene, prepared.position, prepared.forward);
+
+        assert_eq!(col, SPHERE_COLOR);
+    }
+
+    #[test]
+    fn test_looking_down_hits_floor() {
+        let cam = raised_camera();
+        let prepared: PreparedRMCamera = (&cam).into();
+
+        let col = ray_march(
+            &prepared,
+            &sphere_ahead(&cam, 2.0, 0.5),
+            prepared.position,
+            -1.0 * prepared.up,
+        );
+
+        assert_eq!(col, LinearRgba::rgb(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_render_centre_pixel() {
+        let cam = raised_camera();
+        let scene = sphere_ahead(&cam, 2.0, 0.5);
+
+        let image = render(&cam, &scene, 5, 5);
+
+        assert_eq!(image.pixels.len(), 25);
+        assert_eq!(image.get(2, 2), SPHERE_COLOR);
+        assert_eq!(image.max_abs_diff(&image.clone()), Some(0.0));
+
+        let read_back = ReferenceImage::from_image(&image.clone().into_image()).unwrap();
+        assert_eq!(image.max_abs_diff(&read_back), Some(0.0));
+    }
+
+    #[test]
+    fn test_normal_points_away_from_sphere() {
+        let cam = raised_camera();
+        let scene = sphere_ahead(&cam, 0.0, 0.2);
+        let prepared: PreparedRMCamera = (&cam).into();
+
+        let surface = cam.transform.clone()
+            .translate(Vec3::new(1.0, 0.0, 1.0), 0.2)
+            .translation;
+        let expected = -1.0 * scene.geometry.direction(surface, prepared.position);
+        let n = shading::estimate_normal(&prepared, &scene, surface);
+
+        assert!(scene.geometry.dot(n, expected) > 0.999);
+    }
+
+    #[test]
+    fn test_shadows_reach_through_quotient_faces() {
+        let geometry = Geometry::Hyperbolic;
+        let camera: PreparedRMCamera = (&RMCamera::default()).into();
+        let domain = RMFundamentalDomain::seifert_weber();
+
+        // Just inside a face, looking out through it at a sphere drawn just inside its partner.
+        let face = &domain.faces[0];
+        let dir = face.plane.truncate().normalize().extend(0.0);
+        let to_face = exit_distance(&domain.faces, ORIGIN, dir, camera.max_dist);
+        let p = geometry.flow(ORIGIN, dir, to_face - 0.1);
+        let centre = face.pairing * geometry.flow(ORIGIN, dir, to_face + 0.3);
+        let mut transform = HypTransform::default();
+        let towards = transform.to_local_in(geometry, geometry.direction(ORIGIN, centre));
+        transform.translate_in(geometry, towards, geometry.distance(ORIGIN, centre));
+        assert!(geometry.distance(p, centre) > 1.0);
+
+        let (shapes, bvh) = bvh::build(
+            geometry,
+            vec![PreparedRMShape::new(&transform, &RMShape::Sphere { radius: 0.1 }, 0)],
+        );
+        let scene = PreparedRMScene {
+            shapes: PreparedRMShapes { shapes },
+            bvh,
+            lighting: (&RMLighting::default()).into(),
+            quotient: (&domain).into(),
+            geometry,
+            ..default()
+        };
+
+        let direction = geometry.velocity(ORIGIN, dir, to_face - 0.1);
+        assert_eq!(shading::soft_shadow(&camera, &scene, p, direction, 1.0), 0.0);
+        assert_eq!(shading::soft_shadow(&camera, &scene, p, -1.0 * direction, 1.0), 1.0);
+    }
+
+    #[test]
+    fn test_half_floats() {
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!((f16_to_f32(0x3555) - 1.0 / 3.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_projection_directions() {
+        let direction = |projection, aspect_ratio: f32, position: Vec2| {
+            let mut cam = RMCamera::default();
+            cam.settings.projection = projection;
+            cam.settings.aspect_ratio = aspect_ratio;
+            let camera: PreparedRMCamera = (&cam).into();
+            let size = Vec2::new(aspect_ratio, 1.0);
+            view_direction(&camera, screen_to_uv(&camera, position * size, size))
+                .map(|direction| direction.normalize())
+        };
+        let close = |a: Option<Vec3>, b: Vec3| a.is_some_and(|a| (a - b).length() < 1e-5);
+
+        for projection in [RMProjection::Perspective, RMProjection::Fisheye, RMProjection::Equirectangular] {
+            assert!(close(direction(projection, 1.0, Vec2::splat(0.5)), Vec3::Z), "{projection:?}");
+        }
+
+        // The default fisheye looks straight up at the top edge. On a wide screen the corners are
+        // more than half a turn round and show nothing.
+        assert!(close(direction(RMProjection::Fisheye, 1.0, Vec2::new(0.5, 0.0)), Vec3::Y));
+        assert!(direction(RMProjection::Fisheye, 1.0, Vec2::ZERO).is_some());
+        assert!(direction(RMProjection::Fisheye, 2.0, Vec2::ZERO).is_none());
+
+        // The left edge of a panorama looks back, a quarter of the way across looks left.
+        assert!(close(direction(RMProjection::Equirectangular, 2.0, Vec2::new(0.0, 0.5)), Vec3::NEG_Z));
+        assert!(close(direction(RMProjection::Equirectangular, 2.0, Vec2::new(0.25, 0.5)), Vec3::NEG_X));
+
+        // Centres of the cube map's faces, rows from the top.
+        let faces = [
+            (Vec2::new(1.0, 1.0), Vec3::X),
+            (Vec2::new(3.0, 1.0), Vec3::NEG_X),
+            (Vec2::new(5.0, 1.0), Vec3::Y),
+            (Vec2::new(1.0, 3.0), Vec3::NEG_Y),
+            (Vec2::new(3.0, 3.0), Vec3::Z),
+            (Vec2::new(5.0, 3.0), Vec3::NEG_Z),
+        ];
+        for (cell, expected) in faces {
+            let position = cell / Vec2::new(6.0, 4.0);
+            assert!(close(direction(RMProjection::Cubemap, 1.5, position), expected), "{expected}");
+        }
+    }
+}
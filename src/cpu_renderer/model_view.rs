@@ -0,0 +1,163 @@
+// CPU mirror of `model_view_fragment` in the shader, see `model_view`.
+
+use bevy::prelude::*;
+
+use crate::{
+    geometries::Geometry,
+    model_view::{segment_sdf, PreparedRMModelView, RMHyperbolicModel},
+    ray_marching_material::{PreparedRMCamera, PreparedRMScene},
+};
+
+use super::{sdf::scene_sdf, shading::shade};
+
+/// Seen past the edge of the model.
+pub const MODEL_BACKGROUND: LinearRgba = LinearRgba::rgb(0.02, 0.02, 0.02);
+/// Rays leaving the model without hitting anything show its boundary.
+pub const MODEL_BOUNDARY: LinearRgba = LinearRgba::rgb(0.15, 0.15, 0.25);
+pub const MODEL_MARKER: LinearRgba = LinearRgba::rgb(1.0, 0.2, 0.2);
+
+/// Distance in H^3 to the camera's ball and frustum.
+pub fn marker_sdf(view: &PreparedRMModelView, p: Vec4) -> f32 {
+    let mut d = Geometry::Hyperbolic.distance(p, view.camera_position) - view.camera_radius;
+    for (&a, &b) in view.frustum_starts.iter().zip(view.frustum_ends.iter()) {
+        d = d.min(segment_sdf(p, a, b) - view.edge_radius);
+    }
+    d
+}
+
+pub fn model_view_fragment(
+    view: &PreparedRMModelView,
+    model: RMHyperbolicModel,
+    camera: &PreparedRMCamera,
+    scene: &PreparedRMScene,
+    uv_coords: Vec2,
+) -> LinearRgba {
+    let eye = view.eye.truncate();
+    let dir = (view.forward + uv_coords.x * view.right + uv_coords.y * view.up).truncate().normalize();
+
+    // Where the ray crosses the edge of the part of the model that is drawn.
+    let Some((enter, exit)) = model.clip(eye, dir) else {
+        return MODEL_BACKGROUND;
+    };
+    let mut s = enter;
+
+    for _ in 0..camera.max_iterations {
+        if s >= exit {
+            return MODEL_BOUNDARY;
+        }
+
+        let q = eye + s * dir;
+        let p = model.to_hyperboloid(q);
+        let hit = scene_sdf(camera, scene, p);
+        let marker = if view.show_camera != 0 { marker_sdf(view, p) } else { camera.max_dist };
+        let d = hit.distance.min(marker);
+
+        if d < camera.min_dist {
+            if marker < hit.distance {
+                return MODEL_MARKER;
+            }
+            // Back towards the viewer, along the ray as it runs through H^3.
+            let behind = model.to_hyperboloid(q - model.step(q, 0.001) * dir);
+            let col = shade(camera, scene, p, Geometry::Hyperbolic.direction(p, behind), hit.material_id);
+            return LinearRgba::new(col.x, col.y, col.z, 1.0);
+        }
+
+        s += model.step(q, d).max(0.000001);
+    }
+
+    LinearRgba::new(0.0, 0.0, 0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bvh,
+        geometries::HypTransform,
+        lighting::RMLighting,
+        model_view::RMModelView,
+        ray_marching_material::{
+            PreparedRMShape, PreparedRMShapes, RMCamera, RMMaterial, RMMaterialTable, RMShape,
+        },
+    };
+
+    const SPHERE_COLOR: LinearRgba = LinearRgba::rgb(0.0, 0.4, 1.0);
+
+    /// A sphere at the origin, lit only by white ambient light so it comes out in its albedo.
+    fn sphere_at_origin() -> PreparedRMScene {
+        let mut materials = RMMaterialTable::default();
+        let material_id = materials.slot(&RMMaterial::Flat(SPHERE_COLOR));
+        let lighting = RMLighting {
+            ambient: LinearRgba::WHITE,
+            directional_lights: vec![],
+            point_lights: vec![],
+            ambient_occlusion: false,
+            ..default()
+        };
+        let (shapes, bvh) = bvh::build(
+            Geometry::Hyperbolic,
+            vec![PreparedRMShape::new(&HypTransform::default(), &RMShape::Sphere { radius: 0.3 }, material_id)],
+        );
+
+        PreparedRMScene {
+            shapes: PreparedRMShapes { shapes },
+            bvh,
+            materials: materials.into(),
+            lighting: (&lighting).into(),
+            lights: (&lighting).into(),
+            geometry: Geometry::Hyperbolic,
+            ..default()
+        }
+    }
+
+    fn view(model: RMHyperbolicModel, rm_camera: &RMCamera) -> PreparedRMModelView {
+        let model_view = RMModelView {
+            model: Some(model),
+            ..default()
+        };
+        PreparedRMModelView::new(&model_view, rm_camera, Geometry::Hyperbolic)
+    }
+
+    #[test]
+    fn test_centre_of_model_shows_sphere() {
+        // Well above the equator, out of the way with its frustum.
+        let mut rm_camera = RMCamera::default();
+        rm_camera.transform.translate_in(Geometry::Hyperbolic, Vec3::Y, 2.0);
+        let camera: PreparedRMCamera = (&rm_camera).into();
+        let scene = sphere_at_origin();
+
+        for model in RMHyperbolicModel::ALL {
+            let view = view(model, &rm_camera);
+            assert_eq!(model_view_fragment(&view, model, &camera, &scene, Vec2::ZERO), SPHERE_COLOR, "{model:?}");
+
+            // Far off to the side the ray misses the model altogether.
+            let outside = Vec2::splat(10.0 * camera.tan_fov);
+            assert_eq!(model_view_fragment(&view, model, &camera, &scene, outside), MODEL_BACKGROUND, "{model:?}");
+        }
+    }
+
+    #[test]
+    fn test_camera_is_marked() {
+        let mut rm_camera = RMCamera::default();
+        rm_camera.transform.translate_in(Geometry::Hyperbolic, Vec3::Y, 0.5);
+        let camera: PreparedRMCamera = (&rm_camera).into();
+        let scene = PreparedRMScene {
+            geometry: Geometry::Hyperbolic,
+            ..default()
+        };
+
+        for model in RMHyperbolicModel::ALL {
+            let view = view(model, &rm_camera);
+
+            // Aim straight at the camera's position in the model.
+            let to_camera = model.to_model(camera.position) - view.eye.truncate();
+            let along = to_camera.dot(view.forward.truncate());
+            let uv = Vec2::new(
+                to_camera.dot(view.right.truncate()) / view.right.length_squared(),
+                to_camera.dot(view.up.truncate()) / view.up.length_squared(),
+            ) / along;
+
+            assert_eq!(model_view_fragment(&view, model, &camera, &scene, uv), MODEL_MARKER, "{model:?}");
+        }
+    }
+}
@@ -0,0 +1,412 @@
+// CPU mirror of the distance functions in `assets/shaders/ray_marching_material.wgsl`.
+// Keep these in step with the shader: the reference renderer is only useful if both agree.
+
+use bevy::math::{Vec2, Vec3, Vec4, Vec4Swizzles};
+
+use crate::{
+    bvh::BVH_EMPTY,
+    geometries::{hyp_dot, Geometry, ORIGIN},
+    honeycomb::{
+        PreparedRMHoneycomb, HONEYCOMB_CELLS, HONEYCOMB_EDGES, HONEYCOMB_MAX_FOLDS,
+        HONEYCOMB_VERTICES,
+    },
+    ray_marching_material::{
+        PreparedRMCamera, PreparedRMShape, PreparedRMScene, SHAPE_BOX, SHAPE_CYLINDER,
+        SHAPE_EQUIDISTANT, SHAPE_HOROSPHERE, SHAPE_MANDELBULB, SHAPE_PLANE, SHAPE_SPHERE,
+        SHAPE_TORUS,
+    },
+};
+
+pub const BVH_STACK_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SDFResult {
+    pub distance: f32,
+    pub material_id: u32,
+}
+
+pub fn to_local(geometry: Geometry, shape: &PreparedRMShape, p: Vec4) -> Vec4 {
+    let d = p - shape.position;
+    Vec4::new(
+        geometry.dot(d, shape.right),
+        geometry.dot(d, shape.up),
+        geometry.dot(d, shape.forward),
+        local_w(geometry, p, shape.position),
+    )
+}
+
+fn local_w(geometry: Geometry, p: Vec4, centre: Vec4) -> f32 {
+    match geometry {
+        Geometry::Euclidean => 1.0,
+        Geometry::Spherical => p.dot(centre),
+        Geometry::Hyperbolic => -hyp_dot(p, centre),
+    }
+}
+
+pub fn sphere_sdf(geometry: Geometry, q: Vec4, radius: f32) -> f32 {
+    geometry.distance(ORIGIN, q) - radius
+}
+
+fn slab_sdf(geometry: Geometry, x: f32, w: f32, h: f32) -> f32 {
+    geometry.asin(x.abs() * geometry.cos(h) - w * geometry.sin(h))
+}
+
+pub fn box_sdf(geometry: Geometry, q: Vec4, h: Vec3) -> f32 {
+    slab_sdf(geometry, q.x, q.w, h.x)
+        .max(slab_sdf(geometry, q.y, q.w, h.y))
+        .max(slab_sdf(geometry, q.z, q.w, h.z))
+}
+
+pub fn cylinder_sdf(geometry: Geometry, q: Vec4, radius: f32, half_length: f32) -> f32 {
+    let radial = geometry.asin(q.xy().length()) - radius;
+    let cap = slab_sdf(geometry, q.z, q.w, half_length);
+    radial.max(cap)
+}
+
+pub fn torus_sdf(geometry: Geometry, q: Vec4, major_radius: f32, minor_radius: f32) -> f32 {
+    match geometry {
+        Geometry::Euclidean => {
+            Vec2::new(q.xz().length() - major_radius, q.y).length() - minor_radius
+        }
+        Geometry::Spherical => {
+            let c = q.w * major_radius.cos() + q.xz().length() * major_radius.sin();
+            c.clamp(-1.0, 1.0).acos() - minor_radius
+        }
+        Geometry::Hyperbolic => {
+            let c = q.w * major_radius.cosh() - q.xz().length() * major_radius.sinh();
+            c.max(1.0).acosh() - minor_radius
+        }
+    }
+}
+
+pub fn plane_sdf(geometry: Geometry, q: Vec4) -> f32 {
+    geometry.asin(q.y)
+}
+
+/// Horospheres degenerate to planes in E^3 and to great spheres in S^3.
+pub fn horosphere_sdf(geometry: Geometry, q: Vec4) -> f32 {
+    geometry.floor_distance(q)
+}
+
+pub fn equidistant_sdf(geometry: Geometry, q: Vec4, distance: f32) -> f32 {
+    geometry.asin(q.y).abs() - distance
+}
+
+pub fn local_chart(geometry: Geometry, q: Vec4) -> Vec3 {
+    let r = q.xyz().length();
+    if r < 0.000001 {
+        return q.xyz();
+    }
+    q.xyz() * (geometry.distance(ORIGIN, q) / r)
+}
+
+pub fn mandelbulb_de(position: Vec3, power: f32, max_iterations: u32, bailout: f32) -> f32 {
+    let bound = position.length();
+    if bound > 2.0 {
+        return bound - 1.5;
+    }
+
+    let mut z = position;
+    let mut dr = 1.0;
+    let mut r = 0.0;
+    for _ in 0..max_iterations {
+        r = z.length();
+        if r > bailout {
+            break;
+        }
+
+        let mut theta = (z.z / r).acos();
+        let mut phi = z.y.atan2(z.x);
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+
+        let zr = r.powf(power);
+        theta *= power;
+        phi *= power;
+
+        z = zr * Vec3::new(theta.sin() * phi.cos(), phi.sin() * theta.sin(), theta.cos());
+        z += position;
+    }
+    0.5 * r.ln() * r / dr
+}
+
+pub fn mandelbulb_sdf(geometry: Geometry, q: Vec4, params: Vec4) -> f32 {
+    let scale = params.w;
+    scale * mandelbulb_de(local_chart(geometry, q) / scale, params.x, params.y as u32, params.z)
+}
+
+pub fn shape_sdf(camera: &PreparedRMCamera, geometry: Geometry, shape: &PreparedRMShape, p: Vec4) -> f32 {
+    let q = to_local(geometry, shape, p);
+    match shape.kind {
+        SHAPE_SPHERE => sphere_sdf(geometry, q, shape.params.x),
+        SHAPE_BOX => box_sdf(geometry, q, shape.params.xyz()),
+        SHAPE_CYLINDER => cylinder_sdf(geometry, q, shape.params.x, shape.params.y),
+        SHAPE_TORUS => torus_sdf(geometry, q, shape.params.x, shape.params.y),
+        SHAPE_PLANE => plane_sdf(geometry, q),
+        SHAPE_HOROSPHERE => horosphere_sdf(geometry, q),
+        SHAPE_EQUIDISTANT => equidistant_sdf(geometry, q, shape.params.x),
+        SHAPE_MANDELBULB => mandelbulb_sdf(geometry, q, shape.params),
+        _ => camera.max_dist,
+    }
+}
+
+fn nearest_shape(
+    camera: &PreparedRMCamera,
+    geometry: Geometry,
+    shapes: &[PreparedRMShape],
+    pos: Vec4,
+    result: &mut SDFResult,
+) {
+    for shape in shapes {
+        let shape_dist = shape_sdf(camera, geometry, shape, pos);
+        if shape_dist < result.distance {
+            *result = SDFResult {
+                distance: shape_dist,
+                material_id: shape.material_id,
+            };
+        }
+    }
+}
+
+pub fn fold_honeycomb(geometry: Geometry, honeycomb: &PreparedRMHoneycomb, pos: Vec4) -> Vec4 {
+    let mut p = pos;
+    for _ in 0..HONEYCOMB_MAX_FOLDS {
+        let mut folded = false;
+        for &mirror in &honeycomb.mirrors {
+            if geometry.plane_dot(p, mirror) > 0.0 {
+                p = geometry.reflect(p, mirror);
+                folded = true;
+            }
+        }
+        if !folded {
+            break;
+        }
+    }
+    geometry.normalize_point(p)
+}
+
+pub fn honeycomb_sdf(
+    geometry: Geometry,
+    honeycomb: &PreparedRMHoneycomb,
+    pos: Vec4,
+    result: &mut SDFResult,
+) {
+    if honeycomb.parts == 0 {
+        return;
+    }
+
+    let p = fold_honeycomb(geometry, honeycomb, pos);
+    let mut nearest = |distance: f32, material_id: u32| {
+        if distance < result.distance {
+            *result = SDFResult { distance, material_id };
+        }
+    };
+
+    if honeycomb.parts & HONEYCOMB_EDGES != 0 {
+        let [a, b] = honeycomb.edge_normals;
+        let s = Vec2::new(geometry.plane_dot(p, a), geometry.plane_dot(p, b));
+        nearest(geometry.asin(s.length()) - honeycomb.edge_radius, honeycomb.material_id);
+    }
+    if honeycomb.parts & HONEYCOMB_VERTICES != 0 {
+        nearest(geometry.distance(p, honeycomb.vertex) - honeycomb.vertex_radius, honeycomb.material_id);
+    }
+    if honeycomb.parts & HONEYCOMB_CELLS != 0 {
+        let d = geometry.asin(geometry.plane_dot(p, honeycomb.mirrors[3])) + honeycomb.cell_gap;
+        nearest(d, honeycomb.cell_material_id);
+    }
+}
+
+pub fn scene_sdf(camera: &PreparedRMCamera, scene: &PreparedRMScene, pos: Vec4) -> SDFResult {
+    let geometry = scene.geometry;
+    let shapes = &scene.shapes.shapes;
+
+    // -- Horocycle surface, always material slot 0. Quotients have no room for it --
+    let mut result = SDFResult {
+        distance: camera.max_dist,
+        material_id: 0,
+    };
+    if scene.quotient.face_count == 0 {
+        result.distance = result.distance.min(horosphere_sdf(geometry, pos));
+    }
+
+    // -- Shapes outside the hierarchy --
+    let unbounded = scene.bvh.unbounded_count as usize;
+    nearest_shape(camera, geometry, &shapes[..unbounded], pos, &mut result);
+
+    // -- Honeycomb filling the whole space --
+    honeycomb_sdf(geometry, &scene.honeycomb, pos, &mut result);
+
+    // -- Hierarchy, skipping balls farther away than the closest shape so far --
+    let mut stack = [0u32; BVH_STACK_SIZE];
+    let mut top = 1;
+    while top > 0 {
+        top -= 1;
+        let node = &scene.bvh.nodes[stack[top] as usize];
+        if geometry.distance(pos, node.center) - node.radius >= result.distance {
+            continue;
+        }
+
+        if node.count > 0 {
+            let first = node.first as usize;
+            nearest_shape(camera, geometry, &shapes[first..first + node.count as usize], pos, &mut result);
+        } else if node.first != BVH_EMPTY && top + 2 <= BVH_STACK_SIZE {
+            stack[top] = node.first;
+            stack[top + 1] = node.first + 1;
+            top += 2;
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bvh,
+        geometries::HypTransform,
+        honeycomb::{honeycomb_geometry, RMHoneycomb, SCHLAFLI_SYMBOLS},
+        quotient::{RMFundamentalDomain, RMQuotient},
+        ray_marching_material::{PreparedRMShapes, RMCamera, RMMaterialTable, RMShape},
+    };
+
+    const THRESH: f32 = 1e-4;
+
+    const H: Geometry = Geometry::Hyperbolic;
+
+    fn local_at(v: Vec3, t: f32) -> Vec4 {
+        local_in(H, v, t)
+    }
+
+    fn local_in(geometry: Geometry, v: Vec3, t: f32) -> Vec4 {
+        let shape = PreparedRMShape::new(&HypTransform::default(), &RMShape::Plane, 0);
+        let p = HypTransform::default().translate_in(geometry, v, t).translation;
+        to_local(geometry, &shape, p)
+    }
+
+    #[test]
+    fn test_box_faces() {
+        let h = Vec3::new(0.5, 1.0, 1.5);
+        assert!(box_sdf(H, local_at(Vec3::X, 0.0), h) < 0.0);
+        assert!((box_sdf(H, local_at(Vec3::X, 2.0), h) - 1.5).abs() < THRESH);
+        assert!((box_sdf(H, local_at(Vec3::NEG_Y, 2.0), h) - 1.0).abs() < THRESH);
+    }
+
+    #[test]
+    fn test_cylinder_radius_and_caps() {
+        assert!((cylinder_sdf(H, local_at(Vec3::X, 1.0), 0.25, 2.0) - 0.75).abs() < THRESH);
+        assert!((cylinder_sdf(H, local_at(Vec3::Z, 3.0), 0.25, 2.0) - 1.0).abs() < THRESH);
+    }
+
+    #[test]
+    fn test_torus_core_circle() {
+        assert!((torus_sdf(H, local_at(Vec3::X, 2.0), 1.5, 0.2) - 0.3).abs() < THRESH);
+        assert!((torus_sdf(H, local_at(Vec3::NEG_Z, 1.0), 1.5, 0.2) - 0.3).abs() < THRESH);
+    }
+
+    #[test]
+    fn test_local_chart_preserves_distance() {
+        let q = local_at(Vec3::new(1.0, 2.0, -0.5), 1.3);
+        assert!((local_chart(H, q).length() - 1.3).abs() < THRESH);
+    }
+
+    #[test]
+    fn test_mandelbulb_bounds() {
+        let params = Vec4::new(8.0, 8.0, 2.0, 0.5);
+        assert!(mandelbulb_sdf(H, local_at(Vec3::X, 0.05), params) < 0.0);
+        let far = mandelbulb_sdf(H, local_at(Vec3::Y, 3.0), params);
+        assert!(far > 0.0 && far <= 3.0);
+    }
+
+    #[test]
+    fn test_planar_surfaces() {
+        assert!((plane_sdf(H, local_at(Vec3::Y, 0.7)) - 0.7).abs() < THRESH);
+        assert!((equidistant_sdf(H, local_at(Vec3::NEG_Y, 0.7), 0.2) - 0.5).abs() < THRESH);
+        assert!(horosphere_sdf(H, local_at(Vec3::X, 0.0)).abs() < THRESH);
+        assert!((horosphere_sdf(H, local_at(Vec3::Y, 0.7)) - 0.7).abs() < THRESH);
+    }
+
+    #[test]
+    fn test_shapes_in_each_geometry() {
+        for geometry in Geometry::ALL {
+            let q = local_in(geometry, Vec3::X, 0.5);
+            assert!((sphere_sdf(geometry, q, 0.2) - 0.3).abs() < THRESH, "{geometry:?}");
+            assert!((box_sdf(geometry, q, Vec3::splat(0.2)) - 0.3).abs() < THRESH, "{geometry:?}");
+            assert!((plane_sdf(geometry, local_in(geometry, Vec3::Y, 0.5)) - 0.5).abs() < THRESH);
+        }
+    }
+
+    #[test]
+    fn test_bvh_matches_brute_force() {
+        let camera: PreparedRMCamera = RMCamera::default().into();
+        let shapes: Vec<_> = (0..40)
+            .map(|i| {
+                let t = HypTransform::default()
+                    .translate(Vec3::new((i % 4) as f32 - 1.5, (i % 3) as f32, (i % 5) as f32 - 2.0), 0.1 * i as f32)
+                    .clone();
+                PreparedRMShape::new(&t, &RMShape::Sphere { radius: 0.1 }, i)
+            })
+            .collect();
+        let (ordered, bvh) = bvh::build(H, shapes.clone());
+        let scene = PreparedRMScene {
+            shapes: PreparedRMShapes { shapes: ordered },
+            bvh,
+            ..Default::default()
+        };
+
+        for i in 0..20 {
+            let pos = HypTransform::default()
+                .translate(Vec3::new(1.0, 0.5 + 0.1 * i as f32, -1.0), 0.15 * i as f32)
+                .translation;
+            let brute = shapes.iter()
+                .map(|s| shape_sdf(&camera, H, s, pos))
+                .fold(horosphere_sdf(H, pos).min(camera.max_dist), f32::min);
+
+            assert!((scene_sdf(&camera, &scene, pos).distance - brute).abs() < THRESH);
+        }
+    }
+
+    #[test]
+    fn test_quotient_without_bounded_shapes() {
+        // No floor and no tree, only the empty leaf at the origin.
+        let camera: PreparedRMCamera = RMCamera::default().into();
+        let domain = RMFundamentalDomain::new(&RMQuotient::SeifertWeber, H).unwrap();
+        let (shapes, bvh) = bvh::build(H, vec![]);
+        let scene = PreparedRMScene {
+            shapes: PreparedRMShapes { shapes },
+            bvh,
+            quotient: (&domain).into(),
+            ..Default::default()
+        };
+
+        for pos in [ORIGIN, local_at(Vec3::new(0.2, 0.1, -0.3), 0.05)] {
+            assert_eq!(scene_sdf(&camera, &scene, pos).distance, camera.max_dist);
+        }
+    }
+
+    #[test]
+    fn test_honeycomb_is_symmetric() {
+        for symbol in SCHLAFLI_SYMBOLS {
+            let geometry = honeycomb_geometry(symbol).unwrap();
+            let honeycomb = RMHoneycomb {
+                visible: true,
+                schlafli: symbol,
+                cells: true,
+                ..Default::default()
+            };
+            let prepared = PreparedRMHoneycomb::new(&honeycomb, geometry, &mut RMMaterialTable::default());
+            let sdf = |p: Vec4| {
+                let mut result = SDFResult { distance: f32::MAX, material_id: 0 };
+                honeycomb_sdf(geometry, &prepared, p, &mut result);
+                result.distance
+            };
+
+            let p = HypTransform::default()
+                .translate_in(geometry, Vec3::new(0.3, 0.7, -0.2), 0.4)
+                .translation;
+            let q = [3, 1, 0, 2, 3, 0].iter().fold(p, |q, &i| geometry.reflect(q, prepared.mirrors[i]));
+
+            assert!((sdf(p) - sdf(q)).abs() < THRESH, "{symbol:?}");
+            assert!((sdf(prepared.vertex) + honeycomb.vertex_radius).abs() < THRESH, "{symbol:?}");
+        }
+    }
+}
@@ -0,0 +1,148 @@
+// CPU mirror of the shading functions in `assets/shaders/ray_marching_material.wgsl`.
+
+use bevy::math::{Vec3, Vec4};
+
+use crate::{
+    geometries::Geometry,
+    lighting::LIGHT_POINT,
+    ray_marching_material::{PreparedRMCamera, PreparedRMScene},
+};
+
+use super::{material_to_col, sdf::scene_sdf, QuotientRay};
+
+pub const SHADOW_MAX_DIST: f32 = 20.0;
+
+pub fn tangent_basis(geometry: Geometry, p: Vec4) -> [Vec4; 3] {
+    let e0 = geometry.normalize(geometry.tangent(p, Vec4::X));
+    let v1 = geometry.tangent(p, Vec4::Y);
+    let e1 = geometry.normalize(v1 - geometry.dot(v1, e0) * e0);
+    let v2 = geometry.tangent(p, Vec4::Z);
+    let e2 = geometry.normalize(v2 - geometry.dot(v2, e0) * e0 - geometry.dot(v2, e1) * e1);
+    [e0, e1, e2]
+}
+
+/// Direction at p towards a directional light, see `geo_ideal_direction` in the shader.
+pub fn ideal_direction(geometry: Geometry, p: Vec4, ideal: Vec4) -> Vec4 {
+    match geometry {
+        Geometry::Euclidean => ideal.truncate().normalize().extend(0.0),
+        Geometry::Spherical => geometry.direction(p, ideal.truncate().normalize().extend(0.0)),
+        Geometry::Hyperbolic => geometry.direction(p, ideal),
+    }
+}
+
+pub fn estimate_normal(camera: &PreparedRMCamera, scene: &PreparedRMScene, p: Vec4) -> Vec4 {
+    let geometry = scene.geometry;
+    let [e0, e1, e2] = tangent_basis(geometry, p);
+    let h = scene.lighting.normal_epsilon;
+    let k0 = geometry.normalize(e0 - e1 - e2);
+    let k1 = geometry.normalize(-1.0 * e0 - e1 + e2);
+    let k2 = geometry.normalize(-1.0 * e0 + e1 - e2);
+    let k3 = geometry.normalize(e0 + e1 + e2);
+
+    let sample = |k: Vec4| k * scene_sdf(camera, scene, geometry.flow(p, k, h)).distance;
+
+    geometry.normalize(sample(k0) + sample(k1) + sample(k2) + sample(k3))
+}
+
+pub fn soft_shadow(
+    camera: &PreparedRMCamera,
+    scene: &PreparedRMScene,
+    p: Vec4,
+    dir: Vec4,
+    max_t: f32,
+) -> f32 {
+    let geometry = scene.geometry;
+    let faces = scene.quotient.faces();
+
+    let mut res: f32 = 1.0;
+    let mut t = 10.0 * scene.lighting.normal_epsilon;
+    let mut ray = QuotientRay::new(p, dir);
+    ray.advance(geometry, faces, t);
+    for _ in 0..scene.lighting.shadow_max_steps {
+        if t >= max_t {
+            break;
+        }
+
+        let d = scene_sdf(camera, scene, ray.position).distance;
+        if d < camera.min_dist {
+            return 0.0;
+        }
+
+        res = res.min(scene.lighting.shadow_softness * d / t);
+        let advance = ray.step(geometry, faces, d.max(camera.min_dist), camera);
+        t += advance;
+        ray.advance(geometry, faces, advance);
+    }
+    res.clamp(0.0, 1.0)
+}
+
+pub fn ambient_occlusion(camera: &PreparedRMCamera, scene: &PreparedRMScene, p: Vec4, n: Vec4) -> f32 {
+    let mut occlusion = 0.0;
+    let mut weight = 1.0;
+    for i in 1..=scene.lighting.ao_samples {
+        let h = scene.lighting.ao_step * i as f32;
+        let mut ray = QuotientRay::new(p, n);
+        ray.advance(scene.geometry, scene.quotient.faces(), h);
+        let d = scene_sdf(camera, scene, ray.position).distance;
+        occlusion += (h - d) * weight;
+        weight *= 0.5;
+    }
+    (1.0 - scene.lighting.ao_strength * occlusion).clamp(0.0, 1.0)
+}
+
+pub fn shade(
+    camera: &PreparedRMCamera,
+    scene: &PreparedRMScene,
+    pos: Vec4,
+    view: Vec4,
+    material_id: u32,
+) -> Vec3 {
+    let material = &scene.materials.materials[material_id as usize];
+    let col = material_to_col(scene, material_id, pos);
+    let albedo = Vec3::new(col.red, col.green, col.blue);
+    let n = estimate_normal(camera, scene, pos);
+    let lighting = &scene.lighting;
+    let geometry = scene.geometry;
+
+    let ao = if lighting.ao_samples > 0 {
+        ambient_occlusion(camera, scene, pos, n)
+    } else {
+        1.0
+    };
+
+    let diffuse_col = albedo * (1.0 - material.metallic);
+    let specular_col = Vec3::splat(0.04).lerp(albedo, material.metallic);
+    let shininess = (10.0 * (1.0 - material.roughness) + 1.0).exp2();
+
+    let mut color = lighting.ambient.truncate() * albedo * ao;
+
+    for light in scene.lights.lights.iter().take(lighting.light_count as usize) {
+        let mut l = ideal_direction(geometry, pos, light.position);
+        let mut max_t = SHADOW_MAX_DIST;
+        let mut attenuation = 1.0;
+        if light.kind == LIGHT_POINT {
+            l = geometry.direction(pos, light.position);
+            max_t = geometry.distance(pos, light.position);
+            let s = geometry.sin(max_t);
+            attenuation = 1.0 / (s * s).max(0.0001);
+        }
+
+        let n_dot_l = geometry.dot(n, l);
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+
+        let shadow = if lighting.shadow_max_steps > 0 {
+            soft_shadow(camera, scene, pos, l, max_t)
+        } else {
+            1.0
+        };
+
+        let half_dir = geometry.normalize(l + view);
+        let specular = geometry.dot(n, half_dir).max(0.0).powf(shininess);
+
+        color += light.color.truncate() * attenuation * shadow * n_dot_l * (diffuse_col + specular_col * specular);
+    }
+
+    color + material.emissive.truncate()
+}
@@ -0,0 +1,212 @@
+// Animated objects shown on top of whatever scene is loaded: a spinning cube of spheres with a
+// moon, a bouncing ball and markers along the camera's axes. They are ordinary entities, so leave out
+// `RMDemoPlugin` or despawn them to get rid of them.
+
+use bevy::prelude::*;
+
+use crate::{
+    geometries::{Geometry, HypTransform, Isometry},
+    physics::{RMPhysicsSet, RMRigidBody},
+    ray_marching_material::{RMCamera, RMClock, RMMaterial, RMRenderable, RMUploadSet},
+};
+
+pub struct RMDemoPlugin;
+
+impl Plugin for RMDemoPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_demo)
+            .add_systems(
+                PostUpdate,
+                (spin, orbit, follow_camera).after(RMPhysicsSet).before(RMUploadSet),
+            );
+    }
+}
+
+/// Turns the entity about its own position at `angular_velocity` radians per second, given in
+/// its local `right`, `up` and `forward` axes.
+#[derive(Component, Debug, Clone, Default)]
+pub struct Spin {
+    pub angular_velocity: Vec3,
+}
+
+/// Moves the entity around a circle of `radius` about its parent's position, or the origin,
+/// at `speed` radians per second. This overwrites its `HypTransform`, so give it a child to
+/// spin as well.
+#[derive(Component, Debug, Clone)]
+pub struct Orbit {
+    pub axis: Vec3,
+    pub radius: f32,
+    pub speed: f32,
+}
+
+/// Keeps the entity at `offset` from the camera, in the camera's local axes. It must not have
+/// a parent.
+#[derive(Component, Debug, Clone)]
+pub struct CameraGizmo {
+    pub offset: Vec3,
+}
+
+fn spawn_demo(mut commands: Commands, geometry: Res<Geometry>) {
+    let geometry = *geometry;
+
+    let cube = HypTransform::default()
+        .translate_in(geometry, Vec3::new(2.0, -1.0, 0.5), 2.0)
+        .clone();
+    let corner_material = RMMaterial::Flat(LinearRgba::rgb(0.1, 0.1, 0.3));
+
+    commands
+        .spawn((
+            cube,
+            Spin {
+                angular_velocity: Vec3::new(0.5, 0.1, 0.05),
+            },
+        ))
+        .with_children(|parent| {
+            for x in [-1.0, 1.0] {
+                for y in [-1.0, 1.0] {
+                    for z in [-1.0, 1.0] {
+                        parent.spawn((
+                            RMRenderable::sphere(0.075, corner_material.clone()),
+                            HypTransform::default()
+                                .translate_in(geometry, Vec3::new(x, y, z), 0.5)
+                                .clone(),
+                        ));
+                    }
+                }
+            }
+            parent.spawn((
+                RMRenderable::sphere(0.05, RMMaterial::Flat(LinearRgba::rgb(0.6, 0.6, 0.6))),
+                Orbit {
+                    axis: Vec3::new(0.2, 1.0, 0.0),
+                    radius: 1.2,
+                    speed: 0.8,
+                },
+            ));
+        });
+
+    commands.spawn((
+        RMRenderable::sphere(
+            0.1,
+            RMMaterial::Pbr {
+                base_color: LinearRgba::rgb(0.9, 0.6, 0.1),
+                metallic: 0.0,
+                roughness: 0.3,
+            },
+        ),
+        HypTransform::default()
+            .translate_in(geometry, Vec3::new(0.5, 1.0, 1.0), 1.0)
+            .clone(),
+        RMRigidBody {
+            restitution: 0.8,
+            ..default()
+        },
+    ));
+
+    for (offset, color) in [
+        (Vec3::Z, LinearRgba::rgb(0.5, 0.0, 0.0)),
+        (Vec3::X, LinearRgba::rgb(0.0, 5.0, 0.0)),
+        (Vec3::Y, LinearRgba::rgb(0.0, 0.0, 0.5)),
+    ] {
+        commands.spawn((
+            RMRenderable::sphere(0.05, RMMaterial::Flat(color)),
+            CameraGizmo { offset },
+        ));
+    }
+}
+
+fn spin(
+    clock: Res<RMClock>,
+    mut last: Local<Option<f32>>,
+    mut spinning: Query<(&Spin, &mut HypTransform)>,
+) {
+    let dt = clock.elapsed - last.unwrap_or(clock.elapsed);
+    *last = Some(clock.elapsed);
+    if dt == 0.0 {
+        return;
+    }
+
+    for (spin, mut transform) in spinning.iter_mut() {
+        // The frame's third column is `-forward`, see `Isometry`.
+        let w = spin.angular_velocity;
+        let rotation = Quat::from_scaled_axis(Vec3::new(w.x, w.y, -w.z) * dt);
+        *transform = (Isometry::from(&*transform) * Isometry::from_rotation(rotation)).into();
+    }
+}
+
+fn orbit(
+    clock: Res<RMClock>,
+    geometry: Res<Geometry>,
+    mut orbiting: Query<(&Orbit, &mut HypTransform)>,
+) {
+    for (orbit, mut transform) in orbiting.iter_mut() {
+        let axis = orbit.axis.normalize_or(Vec3::Y);
+        let angle = orbit.speed * clock.elapsed;
+        let direction = Quat::from_axis_angle(axis, angle) * axis.any_orthonormal_vector();
+        *transform = HypTransform::default()
+            .translate_in(*geometry, direction, orbit.radius)
+            .clone();
+    }
+}
+
+fn follow_camera(
+    rm_camera: Res<RMCamera>,
+    geometry: Res<Geometry>,
+    mut gizmos: Query<(&CameraGizmo, &mut HypTransform)>,
+) {
+    for (gizmo, mut transform) in gizmos.iter_mut() {
+        *transform = rm_camera.transform
+            .clone()
+            .translate_in(*geometry, gizmo.offset, gizmo.offset.length())
+            .clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    #[test]
+    fn test_spin_turns_in_place() {
+        let mut world = World::new();
+        world.init_resource::<RMClock>();
+        let start = HypTransform::default()
+            .translate_in(Geometry::Hyperbolic, Vec3::X, 0.7)
+            .clone();
+        let entity = world.spawn((start.clone(), Spin { angular_velocity: Vec3::Y })).id();
+
+        // The same schedule keeps the system's `Local` between runs.
+        let mut schedule = Schedule::default();
+        schedule.add_systems(spin);
+        schedule.run(&mut world);
+        world.resource_mut::<RMClock>().elapsed = std::f32::consts::FRAC_PI_2;
+        schedule.run(&mut world);
+
+        // A quarter turn about up swings forward round to the left, like `rotate_local_y`, and
+        // keeps the position.
+        let turned = world.get::<HypTransform>(entity).unwrap();
+        assert!((turned.translation - start.translation).length() < 1e-5);
+        assert!((turned.up - start.up).length() < 1e-5);
+        assert!((turned.forward + start.right).length() < 1e-4, "{:?}", turned.forward);
+        assert!((turned.right - start.forward).length() < 1e-4, "{:?}", turned.right);
+    }
+
+    #[test]
+    fn test_orbit_keeps_radius() {
+        let mut world = World::new();
+        world.init_resource::<RMClock>();
+        world.insert_resource(Geometry::Spherical);
+        let entity = world
+            .spawn((HypTransform::default(), Orbit { axis: Vec3::Z, radius: 0.4, speed: 2.0 }))
+            .id();
+
+        for elapsed in [0.0, 0.3, 1.7] {
+            world.resource_mut::<RMClock>().elapsed = elapsed;
+            world.run_system_once(orbit).unwrap();
+            let p = world.get::<HypTransform>(entity).unwrap().translation;
+            assert!((Geometry::Spherical.distance(Vec4::W, p) - 0.4).abs() < 1e-5);
+            assert!(p.z.abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::ops::Mul;
+
+use bevy::math::{Mat4, Quat, Vec3, Vec4};
+
+use super::{hyp_dot, HypTransform};
+
+/// An orientation preserving isometry of H^3, a matrix in SO(3,1) acting on the hyperboloid.
+/// Its columns are the images of the origin's frame: right, up, back (`-forward`) and the origin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Isometry(pub Mat4);
+
+impl Default for Isometry {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Isometry {
+    pub const IDENTITY: Self = Self(Mat4::IDENTITY);
+
+    /// The Minkowski metric, `hyp_dot(u, v) == u.dot(J * v)`.
+    pub const J: Mat4 = Mat4::from_diagonal(Vec4::new(1.0, 1.0, 1.0, -1.0));
+
+    /// Moves the origin a distance `t` along the unit vector `axis`, leaving the plane through
+    /// the origin orthogonal to `axis` parallel to itself.
+    pub fn from_boost(axis: Vec3, t: f32) -> Self {
+        let (c, s) = (t.cosh(), t.sinh());
+        let u = axis;
+        Self(Mat4::from_cols(
+            (Vec3::X + (c - 1.0) * u.x * u).extend(s * u.x),
+            (Vec3::Y + (c - 1.0) * u.y * u).extend(s * u.y),
+            (Vec3::Z + (c - 1.0) * u.z * u).extend(s * u.z),
+            (s * u).extend(c),
+        ))
+    }
+
+    /// Boost moving the origin by `v` in the tangent space there, so `|v|` is the distance.
+    pub fn from_translation(v: Vec3) -> Self {
+        let t = v.length();
+        if t < 0.000001 {
+            return Self::IDENTITY;
+        }
+        Self::from_boost(v / t, t)
+    }
+
+    /// Rotation about the origin.
+    pub fn from_rotation(rotation: Quat) -> Self {
+        Self(Mat4::from_quat(rotation))
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self(Self::J * self.0.transpose() * Self::J)
+    }
+
+    /// Image of the origin.
+    pub fn position(&self) -> Vec4 {
+        self.0.w_axis
+    }
+
+    /// Whether the matrix preserves `hyp_dot` and the upper sheet of the hyperboloid.
+    pub fn is_lorentz(&self, epsilon: f32) -> bool {
+        let m = self.0;
+        (m.transpose() * Self::J * m).abs_diff_eq(Self::J, epsilon) && m.w_axis.w > 0.0
+    }
+
+    /// Gram-Schmidt in the Minkowski metric, starting from the position. Repeatedly composing
+    /// isometries in `f32` drifts off SO(3,1), this pulls the matrix back onto it.
+    pub fn orthonormalize(&self) -> Self {
+        let m = self.0;
+        // Rescaling onto the hyperboloid magnifies drift far from the origin, lifting the
+        // spatial part straight up doesn't.
+        let xyz = m.w_axis.truncate();
+        let p = xyz.extend((1.0 + xyz.length_squared()).sqrt());
+
+        let mut basis: [Vec4; 3] = [m.x_axis, m.y_axis, m.z_axis];
+        for i in 0..3 {
+            let mut e = basis[i] + hyp_dot(basis[i], p) * p;
+            for b in &basis[..i] {
+                e -= hyp_dot(e, *b) * *b;
+            }
+            basis[i] = e / hyp_dot(e, e).sqrt();
+        }
+
+        Self(Mat4::from_cols(basis[0], basis[1], basis[2], p))
+    }
+}
+
+impl Mul for Isometry {
+    type Output = Isometry;
+
+    fn mul(self, rhs: Isometry) -> Isometry {
+        Isometry(self.0 * rhs.0)
+    }
+}
+
+impl Mul<Vec4> for Isometry {
+    type Output = Vec4;
+
+    fn mul(self, rhs: Vec4) -> Vec4 {
+        self.0 * rhs
+    }
+}
+
+impl From<&HypTransform> for Isometry {
+    fn from(transform: &HypTransform) -> Self {
+        Self(Mat4::from_cols(
+            transform.right,
+            transform.up,
+            -transform.forward,
+            transform.translation,
+        ))
+    }
+}
+
+impl From<Isometry> for HypTransform {
+    fn from(isometry: Isometry) -> Self {
+        let m = isometry.0;
+        Self {
+            translation: m.w_axis,
+            forward: -m.z_axis,
+            up: m.y_axis,
+            right: m.x_axis,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const THRESH: f32 = 1e-3;
+
+    fn direction() -> impl Strategy<Value = Vec3> {
+        prop::array::uniform3(-1.0f32..1.0)
+            .prop_map(Vec3::from_array)
+            .prop_filter("non-zero", |v| v.length() > 0.1)
+            .prop_map(Vec3::normalize)
+    }
+
+    fn isometry() -> impl Strategy<Value = Isometry> {
+        (direction(), 0.0f32..2.5, direction(), -3.0f32..3.0).prop_map(|(u, t, axis, angle)| {
+            Isometry::from_boost(u, t) * Isometry::from_rotation(Quat::from_axis_angle(axis, angle))
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_compositions_are_lorentz(a in isometry(), b in isometry()) {
+            prop_assert!((a * b).is_lorentz(THRESH));
+            prop_assert!((a * b).inverse().is_lorentz(THRESH));
+        }
+
+        #[test]
+        fn test_inverse_undoes(a in isometry()) {
+            prop_assert!((a * a.inverse()).0.abs_diff_eq(Mat4::IDENTITY, THRESH));
+            prop_assert!((a.inverse() * a).0.abs_diff_eq(Mat4::IDENTITY, THRESH));
+        }
+
+        #[test]
+        fn test_boost_distance(u in direction(), t in 0.0f32..3.0) {
+            let p = Isometry::from_boost(u, t).position();
+            prop_assert!((hyp_dot(p, p) + 1.0).abs() < THRESH);
+            prop_assert!((p.truncate().length().asinh() - t).abs() < THRESH);
+            prop_assert!((p.truncate().normalize_or_zero() - u).length() < THRESH || t < THRESH);
+        }
+
+        #[test]
+        fn test_boost_matches_translate(v in direction(), t in 0.0f32..2.0) {
+            let mut transform = HypTransform::default();
+            transform.translate(v, t);
+
+            // Local directions at the default transform, whose forward is -Z.
+            let moved = HypTransform::from(Isometry::from_translation(Vec3::new(v.x, v.y, -v.z) * t));
+
+            prop_assert!((moved.translation - transform.translation).length() < THRESH);
+            prop_assert!((moved.forward - transform.forward).length() < THRESH);
+            prop_assert!((moved.up - transform.up).length() < THRESH);
+        }
+
+        #[test]
+        fn test_transform_round_trip(a in isometry()) {
+            let transform = HypTransform::from(a);
+            prop_assert_eq!(Isometry::from(&transform), a);
+        }
+
+        #[test]
+        fn test_orthonormalize_repairs_drift(a in isometry(), noise in prop::array::uniform16(-1e-2f32..1e-2)) {
+            let drifted = Isometry(a.0 + Mat4::from_cols_array(&noise));
+            let repaired = drifted.orthonormalize();
+
+            prop_assert!(repaired.is_lorentz(THRESH));
+            prop_assert!((repaired.position() - a.position()).length() < 0.1 * a.position().w);
+            prop_assert!(a.orthonormalize().0.abs_diff_eq(a.0, THRESH * a.position().w));
+        }
+    }
+}
@@ -1,6 +1,10 @@
-use bevy::{math::{Vec3, Vec4, Vec4Swizzles}, prelude::Component};
+use bevy::{math::{Mat3, Mat4, Vec3, Vec4, Vec4Swizzles}, prelude::{Component, Resource}};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Component)]
+pub mod isometry;
+pub use isometry::Isometry;
+
+#[derive(Debug, Clone, PartialEq, Component, Serialize, Deserialize)]
 pub struct HypTransform {
     pub translation: Vec4,
     pub forward: Vec4,
@@ -54,7 +58,7 @@ impl HypTransform {
     }
 
     pub fn normal(&self) -> Vec4 {
-        hyp_normalize(self.translation.with_w(-1.0 * self.translation.w))
+        hyp_normalize(self.translation.with_w(-self.translation.w))
     }
 
     pub fn translate(&mut self, v: Vec3, t: f32) -> &mut Self {
@@ -80,8 +84,7 @@ impl HypTransform {
         let z = self.forward;
         let y = self.up;
 
-        let cos_t = theta.cos();
-        let sin_t = (1.0 - cos_t*cos_t).sqrt();
+        let (sin_t, cos_t) = theta.sin_cos();
 
         self.forward = cos_t * z + sin_t * y;
         self.up = cos_t * y - sin_t * z;
@@ -93,8 +96,7 @@ impl HypTransform {
         let z = self.forward;
         let x = self.right;
 
-        let cos_t = theta.cos();
-        let sin_t = (1.0 - cos_t*cos_t).sqrt();
+        let (sin_t, cos_t) = theta.sin_cos();
 
         self.forward = cos_t * z - sin_t * x;
         self.right = cos_t * x + sin_t * z;
@@ -102,15 +104,275 @@ impl HypTransform {
         self
     }
 
+    pub fn rotate_local_z(&mut self, theta: f32) -> &mut Self {
+        let y = self.up;
+        let x = self.right;
+
+        let (sin_t, cos_t) = theta.sin_cos();
+
+        self.up = cos_t * y - sin_t * x;
+        self.right = cos_t * x + sin_t * y;
+
+        self
+    }
+
     pub fn set_up(&mut self, up: Vec4) -> &mut Self {
+        self.set_up_in(Geometry::Hyperbolic, up)
+    }
+
+    /// Geodesic translation in the given geometry, see `translate`.
+    pub fn translate_in(&mut self, geometry: Geometry, v: Vec3, t: f32) -> &mut Self {
+        let v = geometry.normalize(v.x * self.right + v.y * self.up + v.z * self.forward);
+        let (cos_t, sin_t) = (geometry.cos(t), geometry.sin(t));
+
+        let p = self.translation;
+
+        self.translation = geometry.normalize_point(p * cos_t + v * sin_t);
+
+        let parallel_transport = |vec: Vec4| -> Vec4 {
+            vec + geometry.dot(vec, v) * (v * (cos_t - 1.0) - geometry.curvature() * p * sin_t)
+        };
+
+        self.forward = geometry.normalize(parallel_transport(self.forward));
+        self.up = geometry.normalize(parallel_transport(self.up));
+        self.right = geometry.normalize(parallel_transport(self.right));
+
+        self
+    }
+
+    pub fn set_up_in(&mut self, geometry: Geometry, up: Vec4) -> &mut Self {
         self.up = up;
-        self.forward = hyp_normalize(self.forward - hyp_dot(self.forward, up) * up);
-        self.right = hyp_normalize(self.right - hyp_dot(self.right, up) * up);
+        self.forward = geometry.normalize(self.forward - geometry.dot(self.forward, up) * up);
+        // Projecting right onto the new plane alone leaves it leaning towards forward.
+        let right = self.right - geometry.dot(self.right, up) * up;
+        self.right = geometry.normalize(right - geometry.dot(right, self.forward) * self.forward);
+
+        self
+    }
+
+    /// Components of the tangent vector `v` along `right`, `up` and `forward`.
+    pub fn to_local_in(&self, geometry: Geometry, v: Vec4) -> Vec3 {
+        Vec3::new(geometry.dot(v, self.right), geometry.dot(v, self.up), geometry.dot(v, self.forward))
+    }
+
+    /// Moves the transform to the point with the same normal coordinates about the origin in
+    /// another geometry, keeping its orientation relative to the geodesic through the origin.
+    pub fn reinterpret(&mut self, from: Geometry, to: Geometry) -> &mut Self {
+        let dist = from.distance(ORIGIN, self.translation);
+        if dist < 0.000001 {
+            return self;
+        }
+
+        let d = from.direction(self.translation, ORIGIN);
+        let local = self.to_local_in(from, d);
+
+        // At the origin tangent vectors are the same in every model.
+        self.translate_in(from, local, dist);
+        self.translation = ORIGIN;
+        self.translate_in(to, -local, dist)
+    }
+
+    /// Applies a linear isometry of the model, such as a Lorentz matrix in H^3.
+    pub fn apply_isometry(&mut self, isometry: &Mat4) -> &mut Self {
+        self.translation = *isometry * self.translation;
+        self.forward = *isometry * self.forward;
+        self.up = *isometry * self.up;
+        self.right = *isometry * self.right;
+
+        self
+    }
+
+    /// Makes the frame orthonormal again in H^3, see `Isometry::orthonormalize`.
+    pub fn orthonormalize(&mut self) -> &mut Self {
+        *self = Isometry::from(&*self).orthonormalize().into();
 
         self
     }
 }
 
+pub const ORIGIN: Vec4 = Vec4::W;
+
+/// The constant curvature space the scene is rendered in. Points are stored in R^4: on the
+/// hyperboloid for H^3, on the unit sphere for S^3 and in the `w = 1` plane for E^3.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Geometry {
+    Euclidean,
+    Spherical,
+    #[default]
+    Hyperbolic,
+}
+
+impl Geometry {
+    pub const ALL: [Geometry; 3] = [Geometry::Euclidean, Geometry::Spherical, Geometry::Hyperbolic];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Geometry::Euclidean => "Euclidean",
+            Geometry::Spherical => "Spherical",
+            Geometry::Hyperbolic => "Hyperbolic",
+        }
+    }
+
+    /// Shader def selecting the matching `geo_*` functions in the shader.
+    pub fn shader_def(&self) -> &'static str {
+        match self {
+            Geometry::Euclidean => "GEOMETRY_EUCLIDEAN",
+            Geometry::Spherical => "GEOMETRY_SPHERICAL",
+            Geometry::Hyperbolic => "GEOMETRY_HYPERBOLIC",
+        }
+    }
+
+    pub fn curvature(&self) -> f32 {
+        match self {
+            Geometry::Euclidean => 0.0,
+            Geometry::Spherical => 1.0,
+            Geometry::Hyperbolic => -1.0,
+        }
+    }
+
+    pub fn dot(&self, u: Vec4, v: Vec4) -> f32 {
+        match self {
+            Geometry::Euclidean => u.xyz().dot(v.xyz()),
+            Geometry::Spherical => u.dot(v),
+            Geometry::Hyperbolic => hyp_dot(u, v),
+        }
+    }
+
+    pub fn cos(&self, t: f32) -> f32 {
+        match self {
+            Geometry::Euclidean => 1.0,
+            Geometry::Spherical => t.cos(),
+            Geometry::Hyperbolic => t.cosh(),
+        }
+    }
+
+    pub fn sin(&self, t: f32) -> f32 {
+        match self {
+            Geometry::Euclidean => t,
+            Geometry::Spherical => t.sin(),
+            Geometry::Hyperbolic => t.sinh(),
+        }
+    }
+
+    /// Inverse of `sin`.
+    pub fn asin(&self, x: f32) -> f32 {
+        match self {
+            Geometry::Euclidean => x,
+            Geometry::Spherical => x.clamp(-1.0, 1.0).asin(),
+            Geometry::Hyperbolic => x.asinh(),
+        }
+    }
+
+    /// Normalizes a tangent vector.
+    pub fn normalize(&self, v: Vec4) -> Vec4 {
+        match self {
+            Geometry::Euclidean => v / v.xyz().length(),
+            Geometry::Spherical => v.normalize(),
+            Geometry::Hyperbolic => hyp_normalize(v),
+        }
+    }
+
+    /// Projects a point back onto the model after numerical drift.
+    pub fn normalize_point(&self, p: Vec4) -> Vec4 {
+        match self {
+            Geometry::Euclidean => p.with_w(1.0),
+            Geometry::Spherical => p.normalize(),
+            Geometry::Hyperbolic => hyp_normalize(p),
+        }
+    }
+
+    /// Projects v onto the tangent space at p.
+    pub fn tangent(&self, p: Vec4, v: Vec4) -> Vec4 {
+        match self {
+            Geometry::Euclidean => v.with_w(0.0),
+            Geometry::Spherical => v - p.dot(v) * p,
+            Geometry::Hyperbolic => v + hyp_dot(p, v) * p,
+        }
+    }
+
+    /// Unit tangent at p of the geodesic towards q.
+    pub fn direction(&self, p: Vec4, q: Vec4) -> Vec4 {
+        match self {
+            Geometry::Euclidean => self.normalize((q - p).with_w(0.0)),
+            _ => self.normalize(self.tangent(p, q)),
+        }
+    }
+
+    pub fn distance(&self, p: Vec4, q: Vec4) -> f32 {
+        match self {
+            Geometry::Euclidean => (q.xyz() - p.xyz()).length(),
+            // From the chord, acos and acosh lose too much precision near zero.
+            Geometry::Spherical => 2.0 * (0.5 * (q - p).length()).min(1.0).asin(),
+            Geometry::Hyperbolic => 2.0 * (0.5 * hyp_dot(q - p, q - p).max(0.0).sqrt()).asinh(),
+        }
+    }
+
+    /// Point reached after travelling t along the geodesic from p with unit velocity v.
+    pub fn flow(&self, p: Vec4, v: Vec4, t: f32) -> Vec4 {
+        self.cos(t) * p + self.sin(t) * v
+    }
+
+    /// Velocity of that geodesic at time t.
+    pub fn velocity(&self, p: Vec4, v: Vec4, t: f32) -> Vec4 {
+        -self.curvature() * self.sin(t) * p + self.cos(t) * v
+    }
+
+    /// `asin` of this is the signed distance from p to the plane with unit normal n. Euclidean
+    /// planes don't pass through the origin in general, their offset is stored in `n.w`.
+    pub fn plane_dot(&self, p: Vec4, n: Vec4) -> f32 {
+        match self {
+            Geometry::Hyperbolic => hyp_dot(p, n),
+            _ => p.dot(n),
+        }
+    }
+
+    /// Reflects p in the plane with unit normal n, see `plane_dot`.
+    pub fn reflect(&self, p: Vec4, n: Vec4) -> Vec4 {
+        let s = self.plane_dot(p, n);
+        match self {
+            Geometry::Euclidean => p - 2.0 * s * n.with_w(0.0),
+            _ => p - 2.0 * s * n,
+        }
+    }
+
+    /// Signed distance above the floor: the horosphere `w + y = 1` in H^3 and the plane or great
+    /// sphere `y = 0` otherwise.
+    pub fn floor_distance(&self, p: Vec4) -> f32 {
+        match self {
+            Geometry::Hyperbolic => (p.w + p.y).ln(),
+            _ => self.asin(p.y),
+        }
+    }
+
+    /// Unit normal of the level set of `floor_distance` through p.
+    pub fn up(&self, p: Vec4) -> Vec4 {
+        match self {
+            Geometry::Hyperbolic => -1.0 * self.direction(p, Vec4::new(0.0, -1.0, 0.0, 1.0)),
+            _ => self.normalize(self.tangent(p, Vec4::Y)),
+        }
+    }
+
+    /// A point central to `points`, not necessarily the Riemannian centre of mass.
+    pub fn centroid(&self, points: &[Vec4]) -> Vec4 {
+        let sum: Vec4 = points.iter().copied().sum();
+        match self {
+            Geometry::Euclidean => sum / points.len().max(1) as f32,
+            // Antipodal points on S^3 can cancel out, any of them will do then.
+            Geometry::Spherical if sum.length() < 0.000001 => points.first().copied().unwrap_or(ORIGIN),
+            _ => self.normalize_point(sum),
+        }
+    }
+
+    /// Maps a point to the one with the same normal coordinates about the origin in `to`.
+    pub fn transfer_point(&self, to: Geometry, p: Vec4) -> Vec4 {
+        let dist = self.distance(ORIGIN, p);
+        if dist < 0.000001 {
+            return ORIGIN;
+        }
+        to.flow(ORIGIN, self.direction(ORIGIN, p), dist)
+    }
+}
+
 fn cosh_sinh(t: f32) -> (f32, f32) {
     let exp_t = t.exp();
     let exp_inv_t = 1.0 / exp_t;
@@ -120,6 +382,19 @@ fn cosh_sinh(t: f32) -> (f32, f32) {
     (cosh_t, sinh_t)
 }
 
+/// Vector orthogonal to a, b and c under the Euclidean dot product on R^4.
+pub fn cross4(a: Vec4, b: Vec4, c: Vec4) -> Vec4 {
+    let minor = |i: usize, j: usize, k: usize| {
+        Mat3::from_cols(
+            Vec3::new(a[i], b[i], c[i]),
+            Vec3::new(a[j], b[j], c[j]),
+            Vec3::new(a[k], b[k], c[k]),
+        )
+        .determinant()
+    };
+    Vec4::new(minor(1, 2, 3), -minor(0, 2, 3), minor(0, 1, 3), -minor(0, 1, 2))
+}
+
 pub fn hyp_dot(u: Vec4, v: Vec4) -> f32 {
     u.xyz().dot(v.xyz()) - u.w*v.w
 }
@@ -138,6 +413,22 @@ pub fn hyp_normalize(p: Vec4) -> Vec4 {
     p * 1.0 / p2.abs().sqrt()
 }
 
+/// Upper half-space coordinates of a point on the hyperboloid, with `y` the height above the
+/// boundary plane. The floor's ideal point is the point at infinity, so the floor and the
+/// horospheres parallel to it are the planes of constant height: `floor_distance` is `-ln(y)`,
+/// and climbing away from the floor takes you down towards the boundary.
+pub fn to_upper_half_space(p: Vec4) -> Vec3 {
+    let h = p.w + p.y;
+    Vec3::new(p.x, 1.0, p.z) / h
+}
+
+/// Inverse of `to_upper_half_space`, `q.y` must be positive.
+pub fn from_upper_half_space(q: Vec3) -> Vec4 {
+    let r2 = q.x * q.x + q.z * q.z;
+    let y2 = q.y * q.y;
+    Vec4::new(2.0 * q.x, 1.0 - y2 - r2, 2.0 * q.z, 1.0 + y2 + r2) / (2.0 * q.y)
+}
+
 #[cfg(test)]
 mod tests {
     use bevy::math::NormedVectorSpace;
@@ -171,7 +462,7 @@ mod tests {
     }
 
     fn is_valid_transform(t: &HypTransform) -> bool {
-        return valid_position(t.translation)
+        valid_position(t.translation)
             && is_unit_tangent(t.forward, t.translation)
             && is_unit_tangent(t.up, t.translation)
             && is_unit_tangent(t.right, t.translation)
@@ -214,11 +505,80 @@ mod tests {
 
         t.translate(Vec3::new(1.0, -1.0, 0.0), 1.0);
 
-        assert!(true && is_valid_transform(&t));
+        assert!(is_valid_transform(&t));
 
         t.translate(Vec3::new(1.0, -1.0, 0.0), -1.0);
 
         assert!(is_valid_transform(&t));
         assert!(approximately_identical(t0, t))
     }
+
+    #[test]
+    fn test_translation_in_each_geometry() {
+        for geometry in Geometry::ALL {
+            let mut t = HypTransform::default();
+            t.translate_in(geometry, Vec3::new(1.0, -1.0, 0.5), 0.7);
+
+            let d = geometry.distance(ORIGIN, t.translation);
+            assert!((d - 0.7).abs() < 1e-5, "{geometry:?}: {d}");
+            assert!((geometry.dot(t.up, t.up) - 1.0).abs() < 1e-5);
+            assert!(geometry.dot(t.up, t.right).abs() < 1e-5);
+            if geometry != Geometry::Euclidean {
+                assert!(geometry.dot(t.forward, t.translation).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rotation_keeps_sign() {
+        let mut t = HypTransform::default();
+        let t0 = t.clone();
+
+        t.rotate_local_x(-0.3).rotate_local_y(-1.2);
+        assert!(is_valid_transform(&t));
+        assert!(hyp_dot(t.forward, t0.up) < 0.0);
+
+        t.rotate_local_y(1.2).rotate_local_x(0.3);
+        assert!(approximately_identical(t0.clone(), t.clone()));
+
+        let forward = t.forward;
+        t.rotate_local_z(0.7);
+        assert!(is_valid_transform(&t));
+        assert!(hyp_dot(t.right, t0.up) > 0.0);
+        assert_eq!(t.forward, forward);
+    }
+
+    #[test]
+    fn test_upper_half_space() {
+        let geometry = Geometry::Hyperbolic;
+        let p = HypTransform::default()
+            .translate_in(geometry, Vec3::new(0.4, 1.0, -0.7), 1.3)
+            .translation;
+        let q = HypTransform::default()
+            .translate_in(geometry, Vec3::new(-1.0, -0.2, 0.5), 0.8)
+            .translation;
+        let (p_uhs, q_uhs) = (to_upper_half_space(p), to_upper_half_space(q));
+
+        assert_eq!(to_upper_half_space(ORIGIN), Vec3::Y);
+        assert!((from_upper_half_space(p_uhs) - p).length() < 1e-5);
+        assert!((geometry.floor_distance(p) + p_uhs.y.ln()).abs() < 1e-5);
+
+        // cosh d = 1 + |p - q|^2 / 2 p_y q_y
+        let cosh = 1.0 + (p_uhs - q_uhs).length_squared() / (2.0 * p_uhs.y * q_uhs.y);
+        assert!((cosh.acosh() - geometry.distance(p, q)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_reinterpret_round_trip() {
+        let mut t = HypTransform::default();
+        t.translate(Vec3::new(0.3, 1.0, -0.2), 0.9).rotate_local_y(0.4);
+        let t0 = t.clone();
+
+        t.reinterpret(Geometry::Hyperbolic, Geometry::Euclidean);
+        assert!((t.translation.xyz().length() - 0.9).abs() < 1e-5);
+
+        t.reinterpret(Geometry::Euclidean, Geometry::Hyperbolic);
+        assert!((t.translation - t0.translation).length() < 1e-4);
+        assert!((t.forward - t0.forward).length() < 1e-4);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,106 @@
+// Encodes rendered frame sequences as looping animated GIF or APNG files.
+
+use std::{fs::File, io::BufWriter, path::Path, time::Duration};
+
+use bevy::color::{ColorToPacked, Srgba};
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, RgbaImage,
+};
+
+use crate::cpu_renderer::ReferenceImage;
+
+/// 8-bit sRGB copy of a linear render.
+pub fn to_rgba8(image: &ReferenceImage) -> RgbaImage {
+    let data = image.pixels.iter()
+        .flat_map(|&c| Srgba::from(c).to_u8_array())
+        .collect();
+    RgbaImage::from_raw(image.width, image.height, data).expect("buffer matches image size")
+}
+
+/// Writes `frames` as an animation shown at `fps`, `.gif` or `.png`/`.apng` picked by extension.
+pub fn write_animation(frames: &[ReferenceImage], fps: f32, path: &Path) -> Result<(), String> {
+    let Some(first) = frames.first() else {
+        return Err(format!("no frames to write to {}", path.display()));
+    };
+    if frames.iter().any(|f| f.width != first.width || f.height != first.height) {
+        return Err("frames differ in size".to_string());
+    }
+
+    let extension = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    let result = match extension.as_deref() {
+        Some("gif") => write_gif(frames, fps, path),
+        Some("png" | "apng") => write_apng(frames, fps, path),
+        _ => return Err(format!("{} is not a .gif or .png", path.display())),
+    };
+
+    result.map_err(|err| format!("could not write {}: {err}", path.display()))?;
+    println!("wrote {}", path.display());
+    Ok(())
+}
+
+fn write_gif(frames: &[ReferenceImage], fps: f32, path: &Path) -> Result<(), String> {
+    let file = File::create(path).map_err(|err| err.to_string())?;
+    let mut encoder = GifEncoder::new(BufWriter::new(file));
+    encoder.set_repeat(Repeat::Infinite).map_err(|err| err.to_string())?;
+
+    // GIF delays are whole hundredths of a second, so rates that don't divide 100 drift slightly.
+    let delay = Delay::from_saturating_duration(Duration::from_secs_f32(1.0 / fps));
+    encoder.encode_frames(frames.iter().map(|f| Frame::from_parts(to_rgba8(f), 0, 0, delay)))
+        .map_err(|err| err.to_string())
+}
+
+fn write_apng(frames: &[ReferenceImage], fps: f32, path: &Path) -> Result<(), String> {
+    let file = File::create(path).map_err(|err| err.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), frames[0].width, frames[0].height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0).map_err(|err| err.to_string())?;
+
+    let delay_ms = (1000.0 / fps).round().clamp(1.0, u16::MAX as f32) as u16;
+    encoder.set_frame_delay(delay_ms, 1000).map_err(|err| err.to_string())?;
+
+    let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+    for frame in frames {
+        writer.write_image_data(&to_rgba8(frame)).map_err(|err| err.to_string())?;
+    }
+    writer.finish().map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::color::LinearRgba;
+
+    use super::*;
+
+    fn frame(color: LinearRgba) -> ReferenceImage {
+        ReferenceImage {
+            width: 4,
+            height: 2,
+            pixels: vec![color; 8],
+        }
+    }
+
+    #[test]
+    fn test_apng_has_every_frame() {
+        let path = std::env::temp_dir().join("bevy_ray_marching_test.apng");
+        let frames = [frame(LinearRgba::RED), frame(LinearRgba::GREEN), frame(LinearRgba::BLUE)];
+
+        write_animation(&frames, 30.0, &path).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let reader = decoder.read_info().unwrap();
+        let control = reader.info().animation_control.unwrap();
+        assert_eq!(control.num_frames, 3);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_rejects_unknown_format() {
+        let path = std::env::temp_dir().join("bevy_ray_marching_test.mp4");
+        assert!(write_animation(&[frame(LinearRgba::RED)], 30.0, &path).is_err());
+        assert!(write_animation(&[], 30.0, Path::new("empty.gif")).is_err());
+    }
+}
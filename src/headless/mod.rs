@@ -0,0 +1,695 @@
+// `--render` mode: renders a scene file to an image without opening a window. The GPU path draws
+// the same material into an offscreen image, the CPU reference renderer is used when no adapter
+// is available or `--cpu` is passed. With `--camera-path` every frame of a recorded path is
+// rendered on a fixed clock, so the output doesn't depend on how fast frames render.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    asset::RenderAssetUsages,
+    prelude::*,
+    core_pipeline::tonemapping::{DebandDither, Tonemapping},
+    render::{
+        camera::RenderTarget,
+        render_resource::{Extent3d, PipelineCache, TextureDimension, TextureFormat, TextureUsages},
+        storage::ShaderStorageBuffer,
+        view::screenshot::{Screenshot, ScreenshotCaptured},
+        MainWorld, RenderApp,
+    },
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+
+use crate::{
+    camera_path::{apply_keyframe, RMCameraPath},
+    cpu_renderer::{self, ReferenceImage},
+    geometries::{Geometry, HypTransform},
+    lighting::RMLighting,
+    model_view::{RMHyperbolicModel, RMModelView},
+    quotient::RMFundamentalDomain,
+    ray_marching_material::{
+        prepare_scene, RMCamera, RMClock, RMProjection, RMRenderable, RMUploadSet,
+        RayMarchingMaterial, RayMarchingMaterialPlugin,
+    },
+    scene::{RMPose, RMScene, RMSceneHandle, RMScenePlugin},
+    screen_space_quad::ScreenSpaceQuad,
+};
+
+pub mod animation;
+
+pub const USAGE: &str = "\
+usage: bevy_ray_marching --render <scene.rmscene.ron> [options]
+
+options:
+    --output <path>         .png or .exr, defaults to render.png. With --camera-path this is
+                            the directory numbered frames are written to, defaults to frames
+    --size <width>x<height> defaults to 1920x1080
+    --pose <x>,<y>,<z>,<distance>[,<yaw>,<pitch>]
+                            camera pose, overriding the one in the scene
+    --camera-path <path.rmpath.ron>
+                            render every frame of a recorded camera path
+    --fps <n>               frame rate for --camera-path, defaults to 30
+    --animation <path>      also encode the frames as an animated .gif or .png (APNG)
+    --model <poincare|klein|upper-half-space>
+                            draw H^3 from outside a model of it, with the camera marked in it
+    --projection <perspective|fisheye|equirectangular|cubemap>
+                            camera projection, overriding the one in the scene. Panoramas are
+                            best at 2:1 for equirectangular and 3:2 for cubemap
+    --cpu                   use the CPU reference renderer";
+
+/// GPU renders wait for this many frames in a row with no pipelines left to compile after the
+/// scene loads, so the material has had the chance to queue its own before the first capture.
+const GPU_SETTLE_FRAMES: u32 = 3;
+
+#[derive(Debug, Clone)]
+pub struct CameraPose {
+    pub pose: RMPose,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderArgs {
+    pub scene: PathBuf,
+    pub output: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub pose: Option<CameraPose>,
+    pub camera_path: Option<PathBuf>,
+    pub fps: f32,
+    pub animation: Option<PathBuf>,
+    pub model: Option<RMHyperbolicModel>,
+    pub projection: Option<RMProjection>,
+    pub cpu: bool,
+}
+
+impl RenderArgs {
+    /// Parses the command line, returning `Ok(None)` when `--render` isn't given.
+    pub fn parse(args: &[String]) -> Result<Option<Self>, String> {
+        let mut render = None;
+        let mut output = None;
+        let (mut width, mut height) = (1920, 1080);
+        let mut pose = None;
+        let mut camera_path = None;
+        let mut fps = 30.0;
+        let mut animation = None;
+        let mut model = None;
+        let mut projection = None;
+        let mut cpu = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--render" => render = Some(PathBuf::from(value()?)),
+                "--output" => output = Some(PathBuf::from(value()?)),
+                "--size" => (width, height) = parse_size(value()?)?,
+                "--pose" => pose = Some(parse_pose(value()?)?),
+                "--camera-path" => camera_path = Some(PathBuf::from(value()?)),
+                "--fps" => fps = parse_fps(value()?)?,
+                "--animation" => animation = Some(PathBuf::from(value()?)),
+                "--model" => model = Some(parse_model(value()?)?),
+                "--projection" => projection = Some(parse_projection(value()?)?),
+                "--cpu" => cpu = true,
+                _ => return Err(format!("unknown argument {arg}")),
+            }
+        }
+
+        if animation.is_some() && camera_path.is_none() {
+            return Err("--animation needs --camera-path".to_string());
+        }
+        let output = output.unwrap_or_else(|| {
+            PathBuf::from(if camera_path.is_some() { "frames" } else { "render.png" })
+        });
+
+        Ok(render.map(|scene| Self {
+            scene,
+            output,
+            width,
+            height,
+            pose,
+            camera_path,
+            fps,
+            animation,
+            model,
+            projection,
+            cpu,
+        }))
+    }
+}
+
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let parsed = size.split_once('x').and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+    match parsed {
+        Some((w, h)) if w > 0 && h > 0 => Ok((w, h)),
+        _ => Err(format!("invalid size {size}, expected e.g. 1920x1080")),
+    }
+}
+
+fn parse_fps(fps: &str) -> Result<f32, String> {
+    match fps.parse::<f32>() {
+        Ok(fps) if fps > 0.0 => Ok(fps),
+        _ => Err(format!("invalid frame rate {fps}")),
+    }
+}
+
+fn parse_model(model: &str) -> Result<RMHyperbolicModel, String> {
+    match model.to_ascii_lowercase().as_str() {
+        "poincare" => Ok(RMHyperbolicModel::Poincare),
+        "klein" => Ok(RMHyperbolicModel::Klein),
+        "upper-half-space" | "uhs" => Ok(RMHyperbolicModel::UpperHalfSpace),
+        _ => Err(format!("invalid model {model}, expected poincare, klein or upper-half-space")),
+    }
+}
+
+fn parse_projection(projection: &str) -> Result<RMProjection, String> {
+    match projection.to_ascii_lowercase().as_str() {
+        "perspective" => Ok(RMProjection::Perspective),
+        "fisheye" => Ok(RMProjection::Fisheye),
+        "equirectangular" => Ok(RMProjection::Equirectangular),
+        "cubemap" => Ok(RMProjection::Cubemap),
+        _ => Err(format!(
+            "invalid projection {projection}, expected perspective, fisheye, equirectangular or cubemap"
+        )),
+    }
+}
+
+fn parse_pose(pose: &str) -> Result<CameraPose, String> {
+    let values = pose.split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("invalid pose {pose}: {err}"))?;
+
+    let (direction, distance, yaw, pitch) = match values[..] {
+        [x, y, z, distance] => (Vec3::new(x, y, z), distance, 0.0, 0.0),
+        [x, y, z, distance, yaw, pitch] => (Vec3::new(x, y, z), distance, yaw, pitch),
+        _ => return Err(format!("invalid pose {pose}, expected 4 or 6 numbers")),
+    };
+
+    Ok(CameraPose {
+        pose: RMPose::Geodesic { direction, distance, rotation: Vec3::ZERO },
+        yaw,
+        pitch,
+    })
+}
+
+impl CameraPose {
+    fn apply(&self, rm_camera: &mut RMCamera, geometry: Geometry) {
+        rm_camera.transform = self.pose.to_transform(geometry);
+        rm_camera.orient.set_yaw(self.yaw).set_pitch(self.pitch);
+    }
+}
+
+/// The frames to render: a single still, or every `1 / fps` seconds along a camera path.
+#[derive(Debug, Clone)]
+struct FrameSchedule {
+    path: Option<RMCameraPath>,
+    fps: f32,
+}
+
+impl FrameSchedule {
+    fn new(
+        camera_path: Option<&Path>,
+        fps: f32,
+        output: &Path,
+        geometry: Geometry,
+    ) -> Result<Self, String> {
+        let Some(file) = camera_path else {
+            return Ok(Self { path: None, fps });
+        };
+
+        let text = std::fs::read_to_string(file)
+            .map_err(|err| format!("could not read {}: {err}", file.display()))?;
+        let path = RMCameraPath::from_ron(&text)
+            .map_err(|err| format!("could not parse {}: {err}", file.display()))?;
+        if path.keyframes.is_empty() {
+            return Err(format!("{} has no keyframes", file.display()));
+        }
+        if path.geometry != geometry {
+            return Err(format!(
+                "{} was recorded in {:?} but the scene is {:?}",
+                file.display(),
+                path.geometry,
+                geometry,
+            ));
+        }
+
+        std::fs::create_dir_all(output)
+            .map_err(|err| format!("could not create {}: {err}", output.display()))?;
+
+        Ok(Self { path: Some(path), fps })
+    }
+
+    fn frame_count(&self) -> u32 {
+        self.path.as_ref().map_or(1, |path| (path.duration() * self.fps).floor() as u32 + 1)
+    }
+
+    fn time(&self, frame: u32) -> f32 {
+        frame as f32 / self.fps
+    }
+
+    /// Moves the camera for `frame`. Stills keep the pose the camera already has.
+    fn apply(&self, frame: u32, rm_camera: &mut RMCamera) {
+        if let Some(keyframe) = self.path.as_ref().and_then(|path| path.sample(self.time(frame))) {
+            apply_keyframe(rm_camera, keyframe);
+        }
+    }
+
+    fn output(&self, frame: u32, output: &Path) -> PathBuf {
+        match self.path {
+            Some(_) => output.join(format!("frame_{frame:05}.png")),
+            None => output.to_path_buf(),
+        }
+    }
+}
+
+pub fn run(args: RenderArgs) -> Result<(), String> {
+    if args.cpu {
+        return render_cpu(&args);
+    }
+    if !gpu_available() {
+        eprintln!("no GPU adapter found, falling back to the CPU reference renderer");
+        return render_cpu(&args);
+    }
+    render_gpu(args)
+}
+
+fn gpu_available() -> bool {
+    let instance = wgpu::Instance::default();
+    bevy::tasks::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default())).is_some()
+}
+
+pub fn render_cpu(args: &RenderArgs) -> Result<(), String> {
+    let text = std::fs::read_to_string(&args.scene)
+        .map_err(|err| format!("could not read {}: {err}", args.scene.display()))?;
+    let scene = RMScene::from_ron(&text)
+        .map_err(|err| format!("could not parse {}: {err}", args.scene.display()))?;
+    let schedule = FrameSchedule::new(
+        args.camera_path.as_deref(),
+        args.fps,
+        &args.output,
+        scene.geometry,
+    )?;
+
+    let mut rm_camera = RMCamera {
+        settings: scene.camera.settings.clone(),
+        ..default()
+    };
+    CameraPose {
+        pose: scene.camera.pose.clone(),
+        yaw: scene.camera.yaw,
+        pitch: scene.camera.pitch,
+    }
+    .apply(&mut rm_camera, scene.geometry);
+    let domain = RMFundamentalDomain::new(&scene.quotient, scene.geometry)
+        .map_err(|err| format!("invalid quotient in {}: {err}", args.scene.display()))?;
+    if let Some(pose) = &args.pose {
+        pose.apply(&mut rm_camera, scene.geometry);
+    }
+
+    let entities: Vec<(HypTransform, RMRenderable)> = scene.renderables.iter()
+        .map(|r| {
+            let mut renderable = RMRenderable::new(r.shape.clone(), r.material.clone());
+            renderable.set_visibility(r.visible);
+            (r.pose.to_transform(scene.geometry), renderable)
+        })
+        .collect();
+
+    let mut frames = Vec::new();
+    for frame in 0..schedule.frame_count() {
+        schedule.apply(frame, &mut rm_camera);
+        if let Some(projection) = args.projection {
+            rm_camera.settings.projection = projection;
+        }
+        domain.wrap(&mut rm_camera.transform);
+        let prepared = prepare_scene(
+            &RMLighting::default(),
+            &scene.honeycomb,
+            &domain,
+            scene.geometry,
+            entities.iter().map(|(t, r)| (t, r)),
+        );
+        let image = cpu_renderer::render_model_view(
+            &model_view(args.model),
+            &rm_camera,
+            &prepared,
+            args.width,
+            args.height,
+        );
+
+        write_image(&image, &schedule.output(frame, &args.output))?;
+        if args.animation.is_some() {
+            frames.push(image);
+        }
+    }
+
+    match &args.animation {
+        Some(path) => animation::write_animation(&frames, schedule.fps, path),
+        None => Ok(()),
+    }
+}
+
+fn model_view(model: Option<RMHyperbolicModel>) -> RMModelView {
+    RMModelView {
+        model,
+        ..default()
+    }
+}
+
+fn is_exr(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("exr"))
+}
+
+/// Writes `.exr` files as linear floats and anything else as 8-bit sRGB, picked by extension.
+pub fn write_image(image: &ReferenceImage, path: &Path) -> Result<(), String> {
+    let result = if is_exr(path) {
+        let data = image.pixels.iter()
+            .flat_map(|c| [c.red, c.green, c.blue, c.alpha])
+            .collect();
+        image::Rgba32FImage::from_raw(image.width, image.height, data)
+            .expect("buffer matches image size")
+            .save(path)
+    } else {
+        animation::to_rgba8(image).save(path)
+    };
+
+    result.map_err(|err| format!("could not write {}: {err}", path.display()))?;
+    println!("wrote {}", path.display());
+    Ok(())
+}
+
+#[derive(Resource)]
+struct HeadlessCapture {
+    target: Handle<Image>,
+    output: PathBuf,
+    pose: Option<CameraPose>,
+    projection: Option<RMProjection>,
+    camera_path: Option<PathBuf>,
+    fps: f32,
+    animation: Option<PathBuf>,
+    schedule: Option<FrameSchedule>,
+    /// Frames in a row that `PipelinesReady` has held since the scene loaded.
+    settled_frames: u32,
+    next_frame: u32,
+    /// A screenshot has been requested and its observer hasn't run yet.
+    pending: bool,
+    frames: Vec<ReferenceImage>,
+}
+
+fn render_gpu(args: RenderArgs) -> Result<(), String> {
+    let scene_path = asset_path(&args.scene)?;
+
+    let mut app = App::new();
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                close_when_requested: false,
+            })
+            .disable::<WinitPlugin>(),
+    )
+    .add_plugins(ScheduleRunnerPlugin::run_loop(Duration::ZERO))
+    .init_resource::<PipelinesReady>()
+    .add_plugins((RayMarchingMaterialPlugin, RMScenePlugin { path: scene_path }))
+    .insert_resource(ClearColor(Color::BLACK))
+    .insert_resource(model_view(args.model))
+    .insert_resource(RMClock {
+        elapsed: 0.0,
+        fixed: true,
+    })
+    .add_systems(Startup, move |mut commands: Commands,
+                                mut images: ResMut<Assets<Image>>,
+                                mut meshes: ResMut<Assets<Mesh>>,
+                                mut materials: ResMut<Assets<RayMarchingMaterial>>,
+                                buffers: ResMut<Assets<ShaderStorageBuffer>>,
+                                mut rm_camera: ResMut<RMCamera>| {
+        // EXR keeps the linear colour the shader wrote, so it skips the 8-bit sRGB target and
+        // anything done to the colour on the way there.
+        let hdr = is_exr(&args.output) && args.camera_path.is_none();
+        let (format, pixel) = if hdr {
+            (TextureFormat::Rgba16Float, &[0; 8][..])
+        } else {
+            (TextureFormat::Rgba8UnormSrgb, &[0; 4][..])
+        };
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: args.width,
+                height: args.height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            pixel,
+            format,
+            RenderAssetUsages::default(),
+        );
+        image.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+            | TextureUsages::COPY_SRC
+            | TextureUsages::COPY_DST
+            | TextureUsages::RENDER_ATTACHMENT;
+        let target = images.add(image);
+
+        rm_camera.settings.aspect_ratio = args.width as f32 / args.height as f32;
+
+        let mut camera = commands.spawn((
+            Camera2d,
+            Camera {
+                target: RenderTarget::Image(target.clone()),
+                hdr,
+                ..default()
+            },
+        ));
+        if hdr {
+            camera.insert((Tonemapping::None, DebandDither::Disabled));
+        }
+        commands.spawn((
+            Mesh2d(meshes.add(Mesh::from(ScreenSpaceQuad::default()))),
+            MeshMaterial2d(materials.add(RayMarchingMaterial::from_buffers(buffers))),
+        ));
+        commands.insert_resource(HeadlessCapture {
+            target,
+            output: args.output.clone(),
+            pose: args.pose.clone(),
+            projection: args.projection,
+            camera_path: args.camera_path.clone(),
+            fps: args.fps,
+            animation: args.animation.clone(),
+            schedule: None,
+            settled_frames: 0,
+            next_frame: 0,
+            pending: false,
+            frames: Vec::new(),
+        });
+    })
+    .add_systems(PostUpdate, capture_frames.before(RMUploadSet));
+
+    app.sub_app_mut(RenderApp).add_systems(ExtractSchedule, update_pipelines_ready);
+
+    match app.run() {
+        AppExit::Success => Ok(()),
+        AppExit::Error(_) => Err(format!("could not render {}", args.scene.display())),
+    }
+}
+
+/// Whether the render world had no pipelines left to compile last frame.
+#[derive(Resource, Debug, Default)]
+struct PipelinesReady(bool);
+
+fn update_pipelines_ready(mut main_world: ResMut<MainWorld>, pipelines: Res<PipelineCache>) {
+    if let Some(mut ready) = main_world.get_resource_mut::<PipelinesReady>() {
+        ready.0 = pipelines.waiting_pipelines().next().is_none();
+    }
+}
+
+/// Asset paths are relative to `assets/`, anything outside it is loaded by absolute path.
+fn asset_path(scene: &Path) -> Result<String, String> {
+    let absolute = scene.canonicalize()
+        .map_err(|err| format!("could not read {}: {err}", scene.display()))?;
+    let assets = Path::new("assets").canonicalize().ok();
+
+    let path = match assets.as_deref().and_then(|assets| absolute.strip_prefix(assets).ok()) {
+        Some(relative) => relative.to_path_buf(),
+        None => absolute,
+    };
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Steps through the frame schedule, one screenshot at a time. The clock and camera are only
+/// advanced once the previous frame has been written.
+fn capture_frames(
+    mut commands: Commands,
+    mut capture: ResMut<HeadlessCapture>,
+    scene_handle: Option<Res<RMSceneHandle>>,
+    scenes: Res<Assets<RMScene>>,
+    asset_server: Res<AssetServer>,
+    mut rm_camera: ResMut<RMCamera>,
+    mut clock: ResMut<RMClock>,
+    geometry: Res<Geometry>,
+    pipelines: Res<PipelinesReady>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Some(scene_handle) = scene_handle else {
+        return;
+    };
+    if asset_server.load_state(&scene_handle.handle).is_failed() {
+        error!("could not load {}", scene_handle.path);
+        exit.send(AppExit::error());
+        return;
+    }
+    if !scenes.contains(&scene_handle.handle) || capture.pending {
+        return;
+    }
+
+    if let Some(pose) = &capture.pose {
+        pose.apply(&mut rm_camera, *geometry);
+    }
+    if capture.schedule.is_none() {
+        capture.settled_frames = if pipelines.0 { capture.settled_frames + 1 } else { 0 };
+        if capture.settled_frames < GPU_SETTLE_FRAMES {
+            return;
+        }
+    }
+
+    if capture.schedule.is_none() {
+        let schedule = FrameSchedule::new(
+            capture.camera_path.as_deref(),
+            capture.fps,
+            &capture.output,
+            *geometry,
+        );
+        match schedule {
+            Ok(schedule) => capture.schedule = Some(schedule),
+            Err(err) => {
+                error!("{err}");
+                exit.send(AppExit::error());
+                return;
+            }
+        }
+    }
+    let Some(schedule) = capture.schedule.clone() else {
+        return;
+    };
+
+    let frame = capture.next_frame;
+    if frame == schedule.frame_count() {
+        let written = match &capture.animation {
+            Some(path) => animation::write_animation(&capture.frames, schedule.fps, path),
+            None => Ok(()),
+        };
+        match written {
+            Ok(()) => exit.send(AppExit::Success),
+            Err(err) => {
+                error!("{err}");
+                exit.send(AppExit::error())
+            }
+        };
+        return;
+    }
+
+    clock.elapsed = schedule.time(frame);
+    schedule.apply(frame, &mut rm_camera);
+    if let Some(projection) = capture.projection {
+        rm_camera.settings.projection = projection;
+    }
+    capture.pending = true;
+
+    let output = schedule.output(frame, &capture.output);
+    let keep = capture.animation.is_some();
+    commands.spawn(Screenshot::image(capture.target.clone()))
+        .observe(move |trigger: Trigger<ScreenshotCaptured>,
+                       mut capture: ResMut<HeadlessCapture>,
+                       mut exit: EventWriter<AppExit>| {
+            let written = ReferenceImage::from_image(&trigger.event().0)
+                .ok_or_else(|| "unsupported capture format".to_string())
+                .and_then(|image| write_image(&image, &output).map(|()| image));
+            match written {
+                Ok(image) => {
+                    if keep {
+                        capture.frames.push(image);
+                    }
+                    capture.next_frame += 1;
+                    capture.pending = false;
+                }
+                Err(err) => {
+                    error!("{err}");
+                    exit.send(AppExit::error());
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_render_args() {
+        let parsed = RenderArgs::parse(&args(&[
+            "--render", "scene.rmscene.ron", "--size", "64x32", "--pose", "0,1,0,0.5,1,0", "--cpu",
+            "--model", "Klein", "--projection", "equirectangular",
+        ]))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!((parsed.width, parsed.height), (64, 32));
+        assert_eq!(parsed.output, PathBuf::from("render.png"));
+        assert!(parsed.cpu);
+        assert_eq!(parsed.model, Some(RMHyperbolicModel::Klein));
+        assert_eq!(parsed.projection, Some(RMProjection::Equirectangular));
+        assert_eq!(parsed.pose.unwrap().yaw, 1.0);
+    }
+
+    #[test]
+    fn test_parse_without_render_is_none() {
+        assert!(RenderArgs::parse(&[]).unwrap().is_none());
+        assert!(RenderArgs::parse(&args(&["--size", "0x10"])).is_err());
+        assert!(RenderArgs::parse(&args(&["--render"])).is_err());
+        assert!(RenderArgs::parse(&args(&["--render", "s", "--model", "sphere"])).is_err());
+        assert!(RenderArgs::parse(&args(&["--render", "s", "--projection", "mercator"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_camera_path_args() {
+        let parsed = RenderArgs::parse(&args(&[
+            "--render", "scene.rmscene.ron", "--camera-path", "fly.rmpath.ron", "--fps", "24",
+            "--animation", "fly.gif",
+        ]))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(parsed.output, PathBuf::from("frames"));
+        assert_eq!(parsed.fps, 24.0);
+        assert!(RenderArgs::parse(&args(&["--render", "s", "--animation", "a.gif"])).is_err());
+        assert!(RenderArgs::parse(&args(&["--render", "s", "--fps", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_frame_schedule_covers_path() {
+        let mut path = RMCameraPath::default();
+        let mut rm_camera = RMCamera::default();
+        path.push(&rm_camera, 1.0);
+        rm_camera.transform.translate(Vec3::Z, 1.0);
+        path.push(&rm_camera, 1.0);
+
+        let schedule = FrameSchedule { path: Some(path), fps: 10.0 };
+
+        assert_eq!(schedule.frame_count(), 11);
+        assert_eq!(schedule.time(10), 1.0);
+        assert_eq!(
+            schedule.output(3, Path::new("out")),
+            PathBuf::from("out/frame_00003.png"),
+        );
+
+        let mut camera = RMCamera::default();
+        schedule.apply(10, &mut camera);
+        assert!((camera.transform.translation - rm_camera.transform.translation).length() < 1e-5);
+    }
+}
@@ -0,0 +1,132 @@
+// Parent/child placement for entities with a `HypTransform`. A child's `HypTransform` is read in
+// its parent's frame, with the parent's position as the origin, and `propagate_hyp_transforms`
+// composes the frames down Bevy's hierarchy into each entity's `HypGlobalTransform`.
+
+use bevy::prelude::*;
+
+use crate::geometries::{HypTransform, Isometry};
+
+/// Where an entity ends up in the world, its `HypTransform` composed with all its ancestors'.
+/// Composing the frames as matrices works in every geometry: they are affine maps in E^3,
+/// rotations of R^4 in S^3 and Lorentz matrices in H^3.
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct HypGlobalTransform(HypTransform);
+
+impl HypGlobalTransform {
+    pub fn transform(&self) -> &HypTransform {
+        &self.0
+    }
+
+    pub fn isometry(&self) -> Isometry {
+        Isometry::from(&self.0)
+    }
+
+    /// Where `local`, given relative to this frame, is in the world.
+    pub fn mul_transform(&self, local: &HypTransform) -> HypGlobalTransform {
+        HypGlobalTransform((self.isometry() * Isometry::from(local)).into())
+    }
+}
+
+impl From<HypTransform> for HypGlobalTransform {
+    fn from(transform: HypTransform) -> Self {
+        Self(transform)
+    }
+}
+
+/// Recomputes every `HypGlobalTransform` from the roots of the hierarchy down.
+pub fn propagate_hyp_transforms(
+    roots: Query<(Entity, &HypTransform), Without<Parent>>,
+    locals: Query<&HypTransform, With<Parent>>,
+    children: Query<&Children>,
+    mut globals: Query<&mut HypGlobalTransform>,
+) {
+    for (entity, transform) in roots.iter() {
+        propagate(entity, transform.clone().into(), &locals, &children, &mut globals);
+    }
+}
+
+fn propagate(
+    entity: Entity,
+    global: HypGlobalTransform,
+    locals: &Query<&HypTransform, With<Parent>>,
+    children: &Query<&Children>,
+    globals: &mut Query<&mut HypGlobalTransform>,
+) {
+    if let Ok(mut current) = globals.get_mut(entity) {
+        current.set_if_neq(global.clone());
+    }
+
+    let Ok(entity_children) = children.get(entity) else {
+        return;
+    };
+    for &child in entity_children {
+        // Children without a transform break the chain, like `GlobalTransform` does.
+        let Ok(local) = locals.get(child) else {
+            continue;
+        };
+        propagate(child, global.mul_transform(local), locals, children, globals);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::geometries::Geometry;
+
+    const THRESH: f32 = 1e-4;
+
+    #[test]
+    fn test_children_follow_parents() {
+        let geometry = Geometry::Hyperbolic;
+        let mut world = World::new();
+
+        let parent = HypTransform::default()
+            .translate_in(geometry, Vec3::X, 0.8)
+            .rotate_local_y(0.5)
+            .clone();
+        let local = HypTransform::default()
+            .translate_in(geometry, Vec3::Z, 0.3)
+            .clone();
+
+        let root = world.spawn((parent.clone(), HypGlobalTransform::default())).id();
+        let child = world.spawn((local.clone(), HypGlobalTransform::default())).set_parent(root).id();
+        let grandchild = world.spawn((local, HypGlobalTransform::default())).set_parent(child).id();
+
+        world.run_system_once(propagate_hyp_transforms).unwrap();
+
+        // The child is where moving from the parent along its own frame ends up.
+        let expected = parent.clone().translate_in(geometry, Vec3::Z, 0.3).clone();
+        let global = world.get::<HypGlobalTransform>(child).unwrap().transform();
+        assert!((global.translation - expected.translation).length() < THRESH);
+        assert!((global.forward - expected.forward).length() < THRESH);
+
+        let expected = expected.clone().translate_in(geometry, Vec3::Z, 0.3).clone();
+        let global = world.get::<HypGlobalTransform>(grandchild).unwrap().transform();
+        assert!((global.translation - expected.translation).length() < THRESH);
+
+        assert_eq!(world.get::<HypGlobalTransform>(root).unwrap().transform(), &parent);
+    }
+
+    #[test]
+    fn test_composition_in_each_geometry() {
+        for geometry in Geometry::ALL {
+            let parent = HypTransform::default()
+                .translate_in(geometry, Vec3::new(0.2, 1.0, -0.5), 0.7)
+                .rotate_local_x(0.3)
+                .clone();
+            let local = HypTransform::default()
+                .translate_in(geometry, Vec3::new(1.0, 0.0, 1.0), 0.4)
+                .clone();
+
+            let global = HypGlobalTransform::from(parent.clone()).mul_transform(&local);
+
+            let expected = parent.clone()
+                .translate_in(geometry, Vec3::new(1.0, 0.0, 1.0), 0.4)
+                .clone();
+            assert!((global.transform().translation - expected.translation).length() < THRESH, "{geometry:?}");
+            assert!((global.transform().up - expected.up).length() < THRESH, "{geometry:?}");
+        }
+    }
+}
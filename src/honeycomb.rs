@@ -0,0 +1,282 @@
+// Regular honeycombs {p, q, r}: space tiled by {p, q} cells meeting r around each edge. They are
+// drawn by folding points into one chamber of the honeycomb's symmetry group, generated by the
+// reflections in four mirrors. Mirrors 0 to 2 pass through the origin, which is the centre of a
+// cell, and mirror 3 holds one of that cell's faces. Consecutive mirrors meet at angles π/p, π/q
+// and π/r, all other pairs are perpendicular.
+
+use std::f32::consts::PI;
+
+use bevy::{prelude::*, render::render_resource::ShaderType};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    geometries::{cross4, hyp_normalize, Geometry},
+    ray_marching_material::{RMMaterial, RMMaterialTable},
+};
+
+// Must match the HONEYCOMB_* constants in the shader
+pub const HONEYCOMB_EDGES: u32 = 1;
+pub const HONEYCOMB_VERTICES: u32 = 2;
+pub const HONEYCOMB_CELLS: u32 = 4;
+
+/// Reflection rounds before folding gives up, enough to reach the far plane from anywhere the
+/// ray marcher can see in H^3.
+pub const HONEYCOMB_MAX_FOLDS: u32 = 64;
+
+/// Every regular honeycomb with finite cells and vertices, in E^3, S^3 and H^3.
+pub const SCHLAFLI_SYMBOLS: [[u32; 3]; 11] = [
+    [4, 3, 4],
+    [3, 3, 3],
+    [4, 3, 3],
+    [3, 3, 4],
+    [3, 4, 3],
+    [5, 3, 3],
+    [3, 3, 5],
+    [4, 3, 5],
+    [5, 3, 4],
+    [5, 3, 5],
+    [3, 5, 3],
+];
+
+pub fn schlafli_name([p, q, r]: [u32; 3]) -> String {
+    format!("{{{p},{q},{r}}}")
+}
+
+/// The geometry {p, q, r} tiles, or `None` if its cells or vertex figures aren't finite polyhedra.
+pub fn honeycomb_geometry([p, q, r]: [u32; 3]) -> Option<Geometry> {
+    let finite = |a: u32, b: u32| a >= 3 && b >= 3 && (a - 2) * (b - 2) < 4;
+    if !finite(p, q) || !finite(q, r) {
+        return None;
+    }
+
+    let [p, q, r] = [p, q, r].map(|m| PI / m as f32);
+    let excess = p.sin() * r.sin() - q.cos();
+    Some(if excess.abs() < 1e-6 {
+        Geometry::Euclidean
+    } else if excess > 0.0 {
+        Geometry::Spherical
+    } else {
+        Geometry::Hyperbolic
+    })
+}
+
+/// The chamber containing the origin. Planes are stored as unit normals, see
+/// `Geometry::plane_dot`, oriented so the chamber is where every `plane_dot` is negative.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chamber {
+    pub mirrors: [Vec4; 4],
+    /// Perpendicular planes meeting in the line through the chamber's edge.
+    pub edge_normals: [Vec4; 2],
+    pub vertex: Vec4,
+}
+
+impl Chamber {
+    /// `None` unless {p, q, r} is a honeycomb of `geometry`.
+    pub fn new(geometry: Geometry, schlafli: [u32; 3]) -> Option<Self> {
+        if honeycomb_geometry(schlafli)? != geometry {
+            return None;
+        }
+        let [p, q, r] = schlafli.map(|m| PI / m as f32);
+
+        let n0 = Vec4::X;
+        let n1 = Vec4::new(-p.cos(), p.sin(), 0.0, 0.0);
+        let c = -q.cos() / p.sin();
+        let d = (1.0 - c * c).sqrt();
+        let n2 = Vec4::new(0.0, c, d, 0.0);
+
+        // The last mirror is the only one missing the origin, its w component sets how far away
+        // it is. That distance is the cell's inradius, which the angles fix except in E^3.
+        let e = -r.cos() / d;
+        let f = match geometry {
+            Geometry::Euclidean => -1.0,
+            Geometry::Spherical => -(1.0 - e * e).max(0.0).sqrt(),
+            Geometry::Hyperbolic => (e * e - 1.0).max(0.0).sqrt(),
+        };
+        let n3 = Vec4::new(0.0, 0.0, e, f);
+
+        // The vertex is the point on mirrors 1 to 3.
+        let covector = |n: Vec4| match geometry {
+            Geometry::Hyperbolic => n.with_w(-n.w),
+            _ => n,
+        };
+        let v = cross4(covector(n1), covector(n2), covector(n3));
+        let vertex = match geometry {
+            Geometry::Euclidean => v / v.w,
+            Geometry::Spherical => (v * v.w.signum()).normalize(),
+            Geometry::Hyperbolic => hyp_normalize(v * v.w.signum()),
+        };
+
+        Some(Self {
+            mirrors: [n0, n1, n2, n3],
+            edge_normals: [n2, (n3 + r.cos() * n2) / r.sin()],
+            vertex,
+        })
+    }
+}
+
+/// A honeycomb filling the whole world, centred on the origin. It is only drawn when the
+/// current geometry is the one its Schläfli symbol tiles.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RMHoneycomb {
+    pub visible: bool,
+    pub schlafli: [u32; 3],
+    pub edges: bool,
+    pub edge_radius: f32,
+    pub vertices: bool,
+    pub vertex_radius: f32,
+    /// Cells are drawn as solids shrunk by `cell_gap`, so neighbours stay apart.
+    pub cells: bool,
+    pub cell_gap: f32,
+    /// Used for edges and vertices.
+    pub material: RMMaterial,
+    pub cell_material: RMMaterial,
+}
+
+impl Default for RMHoneycomb {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            schlafli: [5, 3, 4],
+            edges: true,
+            edge_radius: 0.03,
+            vertices: true,
+            vertex_radius: 0.06,
+            cells: false,
+            cell_gap: 0.1,
+            material: RMMaterial::Pbr {
+                base_color: LinearRgba::rgb(0.8, 0.7, 0.5),
+                metallic: 0.6,
+                roughness: 0.3,
+            },
+            cell_material: RMMaterial::Flat(LinearRgba::rgb(0.2, 0.4, 0.8)),
+        }
+    }
+}
+
+/// See `Honeycomb` in the shader. `parts == 0` hides the honeycomb.
+#[derive(ShaderType, Clone, Debug, Default)]
+pub struct PreparedRMHoneycomb {
+    pub mirrors: [Vec4; 4],
+    pub edge_normals: [Vec4; 2],
+    pub vertex: Vec4,
+    pub edge_radius: f32,
+    pub vertex_radius: f32,
+    pub cell_gap: f32,
+    pub parts: u32,
+    pub material_id: u32,
+    pub cell_material_id: u32,
+}
+
+impl PreparedRMHoneycomb {
+    pub fn new(honeycomb: &RMHoneycomb, geometry: Geometry, materials: &mut RMMaterialTable) -> Self {
+        if !honeycomb.visible {
+            return Self::default();
+        }
+        let Some(chamber) = Chamber::new(geometry, honeycomb.schlafli) else {
+            return Self::default();
+        };
+
+        let mut parts = 0;
+        let mut material_id = 0;
+        let mut cell_material_id = 0;
+        if honeycomb.edges {
+            parts |= HONEYCOMB_EDGES;
+        }
+        if honeycomb.vertices {
+            parts |= HONEYCOMB_VERTICES;
+        }
+        if parts != 0 {
+            material_id = materials.slot(&honeycomb.material);
+        }
+        if honeycomb.cells {
+            parts |= HONEYCOMB_CELLS;
+            cell_material_id = materials.slot(&honeycomb.cell_material);
+        }
+
+        Self {
+            mirrors: chamber.mirrors,
+            edge_normals: chamber.edge_normals,
+            vertex: chamber.vertex,
+            edge_radius: honeycomb.edge_radius,
+            vertex_radius: honeycomb.vertex_radius,
+            cell_gap: honeycomb.cell_gap,
+            parts,
+            material_id,
+            cell_material_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THRESH: f32 = 1e-4;
+
+    fn chambers() -> impl Iterator<Item = ([u32; 3], Geometry, Chamber)> {
+        SCHLAFLI_SYMBOLS.into_iter().map(|symbol| {
+            let geometry = honeycomb_geometry(symbol).unwrap();
+            (symbol, geometry, Chamber::new(geometry, symbol).unwrap())
+        })
+    }
+
+    #[test]
+    fn test_honeycomb_geometry() {
+        assert_eq!(honeycomb_geometry([4, 3, 4]), Some(Geometry::Euclidean));
+        assert_eq!(honeycomb_geometry([5, 3, 3]), Some(Geometry::Spherical));
+        assert_eq!(honeycomb_geometry([5, 3, 4]), Some(Geometry::Hyperbolic));
+        // Ideal vertices and cells
+        assert_eq!(honeycomb_geometry([4, 4, 3]), None);
+        assert_eq!(honeycomb_geometry([6, 3, 3]), None);
+        assert!(Chamber::new(Geometry::Euclidean, [5, 3, 4]).is_none());
+    }
+
+    #[test]
+    fn test_mirror_angles() {
+        for ([p, q, r], geometry, chamber) in chambers() {
+            let angle = |i: usize, j: usize| {
+                let (a, b) = (chamber.mirrors[i], chamber.mirrors[j]);
+                geometry.dot(a, b) / (geometry.dot(a, a) * geometry.dot(b, b)).sqrt()
+            };
+            for (i, j, m) in [(0, 1, p), (1, 2, q), (2, 3, r)] {
+                assert!((angle(i, j) + (PI / m as f32).cos()).abs() < THRESH, "{geometry:?} {p} {q} {r}");
+            }
+            for (i, j) in [(0, 2), (0, 3), (1, 3)] {
+                assert!(angle(i, j).abs() < THRESH, "{geometry:?} {p} {q} {r}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_vertex_is_a_corner_of_the_chamber() {
+        for (symbol, geometry, chamber) in chambers() {
+            for &n in &chamber.mirrors[1..] {
+                assert!(geometry.plane_dot(chamber.vertex, n).abs() < THRESH, "{symbol:?}");
+            }
+            assert!(geometry.plane_dot(chamber.vertex, chamber.mirrors[0]) < 0.0, "{symbol:?}");
+            for &n in &chamber.edge_normals {
+                assert!(geometry.plane_dot(chamber.vertex, n).abs() < THRESH, "{symbol:?}");
+            }
+        }
+
+        // Cubes with inradius 1
+        let cubic = Chamber::new(Geometry::Euclidean, [4, 3, 4]).unwrap();
+        assert!((cubic.vertex.truncate().length() - 3.0_f32.sqrt()).abs() < THRESH);
+    }
+
+    #[test]
+    fn test_prepared_only_in_matching_geometry() {
+        let mut materials = RMMaterialTable::default();
+        let honeycomb = RMHoneycomb {
+            visible: true,
+            ..default()
+        };
+
+        let prepared = PreparedRMHoneycomb::new(&honeycomb, Geometry::Hyperbolic, &mut materials);
+        assert_eq!(prepared.parts, HONEYCOMB_EDGES | HONEYCOMB_VERTICES);
+
+        let prepared = PreparedRMHoneycomb::new(&honeycomb, Geometry::Spherical, &mut materials);
+        assert_eq!(prepared.parts, 0);
+    }
+}
@@ -0,0 +1,491 @@
+// Keyboard, mouse and gamepad input mapped to actions. Controls ask `RMActions` whether an action
+// is held rather than checking keys themselves, so everything can be rebound in the UI or in the
+// bindings file. Gamepad sticks are read separately, shaped by `RMStickSettings`.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use bevy::{asset::io::file::FileAssetReader, input::InputSystem, prelude::*};
+use serde::{Deserialize, Serialize};
+
+/// The bindings file, relative to the assets folder.
+pub const DEFAULT_BINDINGS: &str = "input/bindings.ron";
+
+/// Where the bindings file lives, found the same way Bevy finds the assets folder rather than
+/// relative to the working directory.
+pub fn bindings_path() -> PathBuf {
+    FileAssetReader::get_base_path().join("assets").join(DEFAULT_BINDINGS)
+}
+
+pub struct RMInputPlugin;
+
+impl Plugin for RMInputPlugin {
+    fn build(&self, app: &mut App) {
+        let path = bindings_path();
+        let bindings = match RMBindings::load(&path) {
+            Ok(bindings) => bindings,
+            Err(err) => {
+                warn!("using default bindings, could not load {}: {err}", path.display());
+                RMBindings::default()
+            }
+        };
+
+        app.insert_resource(bindings)
+            .init_resource::<RMActions>()
+            .init_resource::<RMRebinding>()
+            .add_systems(
+                PreUpdate,
+                (update_actions, capture_rebinding).chain().after(InputSystem),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum RMAction {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Jump,
+    RollLeft,
+    RollRight,
+    GrabCursor,
+    ReleaseCursor,
+    Pick,
+    LogPosition,
+    AddKeyframe,
+    PlayPath,
+    SaveScene,
+}
+
+impl RMAction {
+    pub const ALL: [RMAction; 16] = [
+        RMAction::MoveForward,
+        RMAction::MoveBack,
+        RMAction::MoveLeft,
+        RMAction::MoveRight,
+        RMAction::MoveUp,
+        RMAction::MoveDown,
+        RMAction::Jump,
+        RMAction::RollLeft,
+        RMAction::RollRight,
+        RMAction::GrabCursor,
+        RMAction::ReleaseCursor,
+        RMAction::Pick,
+        RMAction::LogPosition,
+        RMAction::AddKeyframe,
+        RMAction::PlayPath,
+        RMAction::SaveScene,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RMAction::MoveForward => "Move Forward",
+            RMAction::MoveBack => "Move Back",
+            RMAction::MoveLeft => "Move Left",
+            RMAction::MoveRight => "Move Right",
+            RMAction::MoveUp => "Move Up",
+            RMAction::MoveDown => "Move Down",
+            RMAction::Jump => "Jump",
+            RMAction::RollLeft => "Roll Left",
+            RMAction::RollRight => "Roll Right",
+            RMAction::GrabCursor => "Grab Cursor",
+            RMAction::ReleaseCursor => "Release Cursor",
+            RMAction::Pick => "Pick",
+            RMAction::LogPosition => "Log Position",
+            RMAction::AddKeyframe => "Add Keyframe",
+            RMAction::PlayPath => "Play Path",
+            RMAction::SaveScene => "Save Scene",
+        }
+    }
+}
+
+/// Held together with a key in a `RMBinding::Chord`, either side counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RMModifier {
+    Ctrl,
+    Shift,
+    Alt,
+}
+
+impl RMModifier {
+    pub const ALL: [RMModifier; 3] = [RMModifier::Ctrl, RMModifier::Shift, RMModifier::Alt];
+
+    pub fn keys(&self) -> [KeyCode; 2] {
+        match self {
+            RMModifier::Ctrl => [KeyCode::ControlLeft, KeyCode::ControlRight],
+            RMModifier::Shift => [KeyCode::ShiftLeft, KeyCode::ShiftRight],
+            RMModifier::Alt => [KeyCode::AltLeft, KeyCode::AltRight],
+        }
+    }
+
+    pub fn held(&self, keys: &ButtonInput<KeyCode>) -> bool {
+        keys.any_pressed(self.keys())
+    }
+
+    fn is_modifier(key: KeyCode) -> bool {
+        RMModifier::ALL.iter().any(|modifier| modifier.keys().contains(&key))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RMBinding {
+    Key(KeyCode),
+    /// A key pressed while the modifier is held.
+    Chord(RMModifier, KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+impl RMBinding {
+    pub fn name(&self) -> String {
+        match self {
+            RMBinding::Key(key) => format!("{key:?}"),
+            RMBinding::Chord(modifier, key) => format!("{modifier:?}+{key:?}"),
+            RMBinding::Mouse(button) => format!("Mouse {button:?}"),
+            RMBinding::Gamepad(button) => format!("Pad {button:?}"),
+        }
+    }
+}
+
+/// Response of a gamepad stick.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RMStickSettings {
+    /// Deflection ignored around the centre, the rest of the range is stretched to start at zero.
+    pub dead_zone: f32,
+    /// The response is the stretched deflection raised to this power. Above 1 gives finer
+    /// control near the centre.
+    pub exponent: f32,
+    /// Response at full deflection.
+    pub sensitivity: f32,
+}
+
+impl Default for RMStickSettings {
+    fn default() -> Self {
+        Self {
+            dead_zone: 0.15,
+            exponent: 1.5,
+            sensitivity: 1.0,
+        }
+    }
+}
+
+impl RMStickSettings {
+    pub fn apply(&self, stick: Vec2) -> Vec2 {
+        let deflection = stick.length();
+        if deflection <= self.dead_zone {
+            return Vec2::ZERO;
+        }
+
+        let t = ((deflection - self.dead_zone) / (1.0 - self.dead_zone).max(f32::EPSILON)).min(1.0);
+        stick / deflection * t.powf(self.exponent) * self.sensitivity
+    }
+}
+
+/// Contents of the bindings file.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RMBindings {
+    pub actions: BTreeMap<RMAction, Vec<RMBinding>>,
+    /// The left stick, moving at walking or flying speed at full sensitivity.
+    pub move_stick: RMStickSettings,
+    /// The right stick, turning this many radians per second at full sensitivity.
+    pub look_stick: RMStickSettings,
+}
+
+impl Default for RMBindings {
+    fn default() -> Self {
+        use RMBinding::{Chord, Gamepad as Pad, Key, Mouse};
+
+        let actions = [
+            (RMAction::MoveForward, vec![Key(KeyCode::KeyW), Pad(GamepadButton::DPadUp)]),
+            (RMAction::MoveBack, vec![Key(KeyCode::KeyS), Pad(GamepadButton::DPadDown)]),
+            (RMAction::MoveLeft, vec![Key(KeyCode::KeyA), Pad(GamepadButton::DPadLeft)]),
+            (RMAction::MoveRight, vec![Key(KeyCode::KeyD), Pad(GamepadButton::DPadRight)]),
+            (RMAction::MoveUp, vec![Key(KeyCode::KeyR), Pad(GamepadButton::RightTrigger)]),
+            (RMAction::MoveDown, vec![Key(KeyCode::KeyF), Pad(GamepadButton::LeftTrigger)]),
+            (RMAction::Jump, vec![Key(KeyCode::Space), Pad(GamepadButton::South)]),
+            (RMAction::RollLeft, vec![Key(KeyCode::KeyQ), Pad(GamepadButton::LeftTrigger2)]),
+            (RMAction::RollRight, vec![Key(KeyCode::KeyE), Pad(GamepadButton::RightTrigger2)]),
+            (RMAction::GrabCursor, vec![Mouse(MouseButton::Left)]),
+            (RMAction::ReleaseCursor, vec![Key(KeyCode::Escape)]),
+            (RMAction::Pick, vec![Mouse(MouseButton::Right), Pad(GamepadButton::West)]),
+            (RMAction::LogPosition, vec![Key(KeyCode::KeyL)]),
+            (RMAction::AddKeyframe, vec![Key(KeyCode::KeyK)]),
+            (RMAction::PlayPath, vec![Key(KeyCode::KeyP), Pad(GamepadButton::Start)]),
+            (RMAction::SaveScene, vec![Chord(RMModifier::Ctrl, KeyCode::KeyS)]),
+        ];
+
+        Self {
+            actions: actions.into_iter().collect(),
+            move_stick: RMStickSettings::default(),
+            look_stick: RMStickSettings {
+                dead_zone: 0.1,
+                exponent: 2.0,
+                sensitivity: 2.0,
+            },
+        }
+    }
+}
+
+impl RMBindings {
+    pub fn get(&self, action: RMAction) -> &[RMBinding] {
+        self.actions.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(text)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = self.to_ron().map_err(|err| err.to_string())?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        std::fs::write(path, text).map_err(|err| err.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        Self::from_ron(&text).map_err(|err| err.to_string())
+    }
+}
+
+/// Actions held this frame, and the shaped gamepad sticks.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct RMActions {
+    pressed: HashSet<RMAction>,
+    just_pressed: HashSet<RMAction>,
+    /// Left stick, x to the right and y forward.
+    pub movement: Vec2,
+    /// Right stick, x to the right and y up.
+    pub look: Vec2,
+}
+
+impl RMActions {
+    pub fn pressed(&self, action: RMAction) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: RMAction) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+/// The action waiting for its next binding, which is whatever key or button goes down next,
+/// with Ctrl, Shift or Alt if one is held. Escape cancels, except when binding `ReleaseCursor`.
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct RMRebinding(pub Option<RMAction>);
+
+fn update_actions(
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Query<&Gamepad>,
+    bindings: Res<RMBindings>,
+    rebinding: Res<RMRebinding>,
+    mut actions: ResMut<RMActions>,
+) {
+    *actions = RMActions::default();
+    // The key being bound shouldn't also do whatever it did before.
+    if rebinding.0.is_some() {
+        return;
+    }
+
+    // Plain keys are left to chords while a modifier some chord uses is held, so Ctrl+S doesn't
+    // also walk back.
+    let chording = bindings.actions.values().flatten().any(|binding| match binding {
+        RMBinding::Chord(modifier, _) => modifier.held(&keys),
+        _ => false,
+    });
+    let plain = |key: KeyCode| !chording || RMModifier::is_modifier(key);
+
+    let pressed = |binding: &RMBinding| match *binding {
+        RMBinding::Key(key) => plain(key) && keys.pressed(key),
+        RMBinding::Chord(modifier, key) => modifier.held(&keys) && keys.pressed(key),
+        RMBinding::Mouse(button) => mouse.pressed(button),
+        RMBinding::Gamepad(button) => gamepads.iter().any(|gamepad| gamepad.pressed(button)),
+    };
+    let just_pressed = |binding: &RMBinding| match *binding {
+        RMBinding::Key(key) => plain(key) && keys.just_pressed(key),
+        RMBinding::Chord(modifier, key) => modifier.held(&keys) && keys.just_pressed(key),
+        RMBinding::Mouse(button) => mouse.just_pressed(button),
+        RMBinding::Gamepad(button) => gamepads.iter().any(|gamepad| gamepad.just_pressed(button)),
+    };
+
+    for (&action, action_bindings) in bindings.actions.iter() {
+        if action_bindings.iter().any(&pressed) {
+            actions.pressed.insert(action);
+        }
+        if action_bindings.iter().any(&just_pressed) {
+            actions.just_pressed.insert(action);
+        }
+    }
+
+    let left: Vec2 = gamepads.iter().map(Gamepad::left_stick).sum();
+    let right: Vec2 = gamepads.iter().map(Gamepad::right_stick).sum();
+    actions.movement = bindings.move_stick.apply(left);
+    actions.look = bindings.look_stick.apply(right);
+}
+
+fn capture_rebinding(
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Query<&Gamepad>,
+    mut bindings: ResMut<RMBindings>,
+    mut rebinding: ResMut<RMRebinding>,
+) {
+    let Some(action) = rebinding.0 else {
+        return;
+    };
+
+    // Escape is what releases the cursor, so it can't cancel that one.
+    if keys.just_pressed(KeyCode::Escape) && action != RMAction::ReleaseCursor {
+        rebinding.0 = None;
+        return;
+    }
+
+    // Modifiers on their own wait for the key they're held with.
+    let modifier = RMModifier::ALL.into_iter().find(|modifier| modifier.held(&keys));
+    let key = keys.get_just_pressed().copied().find(|key| !RMModifier::is_modifier(*key));
+    let binding = key
+        .map(|key| match modifier {
+            Some(modifier) => RMBinding::Chord(modifier, key),
+            None => RMBinding::Key(key),
+        })
+        .or_else(|| mouse.get_just_pressed().next().copied().map(RMBinding::Mouse))
+        .or_else(|| {
+            gamepads.iter()
+                .find_map(|gamepad| gamepad.get_just_pressed().next().copied())
+                .map(RMBinding::Gamepad)
+        });
+    let Some(binding) = binding else {
+        return;
+    };
+
+    let action_bindings = bindings.actions.entry(action).or_default();
+    if !action_bindings.contains(&binding) {
+        action_bindings.push(binding);
+    }
+    rebinding.0 = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stick_dead_zone_and_curve() {
+        let settings = RMStickSettings {
+            dead_zone: 0.2,
+            exponent: 2.0,
+            sensitivity: 3.0,
+        };
+
+        assert_eq!(settings.apply(Vec2::new(0.1, -0.1)), Vec2::ZERO);
+        assert!((settings.apply(Vec2::Y) - 3.0 * Vec2::Y).length() < 1e-5);
+        // Halfway between the dead zone and the edge, squared.
+        assert!((settings.apply(Vec2::new(-0.6, 0.0)) - Vec2::new(-0.75, 0.0)).length() < 1e-5);
+        // Sticks pushed past the edge, or two of them summed, saturate.
+        assert!((settings.apply(Vec2::new(1.5, 0.0)) - Vec2::new(3.0, 0.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn test_default_bindings_file_matches_defaults() {
+        let text = include_str!("../assets/input/bindings.ron");
+        assert_eq!(RMBindings::from_ron(text).unwrap(), RMBindings::default());
+    }
+
+    fn world_with_keys(pressed: &[KeyCode]) -> World {
+        let mut world = World::new();
+        let mut keys = ButtonInput::<KeyCode>::default();
+        for key in pressed {
+            keys.press(*key);
+        }
+        world.insert_resource(keys);
+        world.init_resource::<ButtonInput<MouseButton>>();
+        world.insert_resource(RMBindings::default());
+        world.init_resource::<RMActions>();
+        world.init_resource::<RMRebinding>();
+        world
+    }
+
+    #[test]
+    fn test_ctrl_chords_suppress_plain_keys() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        let mut world = world_with_keys(&[KeyCode::KeyS]);
+        world.run_system_once(update_actions).unwrap();
+        let actions = world.resource::<RMActions>();
+        assert!(actions.pressed(RMAction::MoveBack));
+        assert!(!actions.just_pressed(RMAction::SaveScene));
+
+        let mut world = world_with_keys(&[KeyCode::ControlRight, KeyCode::KeyS]);
+        world.run_system_once(update_actions).unwrap();
+        let actions = world.resource::<RMActions>();
+        assert!(!actions.pressed(RMAction::MoveBack));
+        assert!(actions.just_pressed(RMAction::SaveScene));
+    }
+
+    #[test]
+    fn test_shift_chords_suppress_plain_keys() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        // Shift does nothing to plain keys until a chord uses it.
+        let mut world = world_with_keys(&[KeyCode::ShiftLeft, KeyCode::KeyW]);
+        world.run_system_once(update_actions).unwrap();
+        assert!(world.resource::<RMActions>().pressed(RMAction::MoveForward));
+
+        world.resource_mut::<RMBindings>()
+            .actions
+            .insert(RMAction::Jump, vec![RMBinding::Chord(RMModifier::Shift, KeyCode::KeyW)]);
+        world.run_system_once(update_actions).unwrap();
+        let actions = world.resource::<RMActions>();
+        assert!(actions.pressed(RMAction::Jump));
+        assert!(!actions.pressed(RMAction::MoveForward));
+    }
+
+    #[test]
+    fn test_rebinding_captures_chords_and_escape_cancels() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        let mut world = world_with_keys(&[KeyCode::ShiftLeft, KeyCode::KeyJ]);
+        world.insert_resource(RMRebinding(Some(RMAction::Jump)));
+        world.run_system_once(capture_rebinding).unwrap();
+        let jump = world.resource::<RMBindings>().get(RMAction::Jump);
+        assert_eq!(jump.last(), Some(&RMBinding::Chord(RMModifier::Shift, KeyCode::KeyJ)));
+        assert_eq!(world.resource::<RMRebinding>().0, None);
+
+        let mut world = world_with_keys(&[KeyCode::Escape]);
+        world.insert_resource(RMRebinding(Some(RMAction::Jump)));
+        world.run_system_once(capture_rebinding).unwrap();
+        assert_eq!(world.resource::<RMBindings>(), &RMBindings::default());
+        assert_eq!(world.resource::<RMRebinding>().0, None);
+
+        // Escape comes back as a binding after being removed from `ReleaseCursor`.
+        let mut world = world_with_keys(&[KeyCode::Escape]);
+        world.resource_mut::<RMBindings>().actions.insert(RMAction::ReleaseCursor, vec![]);
+        world.insert_resource(RMRebinding(Some(RMAction::ReleaseCursor)));
+        world.run_system_once(capture_rebinding).unwrap();
+        assert_eq!(world.resource::<RMBindings>(), &RMBindings::default());
+        assert_eq!(world.resource::<RMRebinding>().0, None);
+    }
+
+    #[test]
+    fn test_bindings_round_trip_through_ron() {
+        let mut bindings = RMBindings::default();
+        bindings.actions.insert(RMAction::Jump, vec![RMBinding::Gamepad(GamepadButton::Other(3))]);
+
+        let text = bindings.to_ron().unwrap();
+        assert_eq!(RMBindings::from_ron(&text).unwrap(), bindings);
+        assert!(RMAction::ALL.iter().all(|action| !bindings.get(*action).is_empty()));
+    }
+}
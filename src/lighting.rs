@@ -0,0 +1,146 @@
+use bevy::{prelude::*, render::render_resource::ShaderType};
+
+use crate::geometries::HypTransform;
+
+/// Lighting and shading settings read by the ray marching shader.
+#[derive(Resource, Debug, Clone)]
+pub struct RMLighting {
+    pub ambient: LinearRgba,
+    pub directional_lights: Vec<RMDirectionalLight>,
+    pub point_lights: Vec<RMPointLight>,
+    pub shadows: bool,
+    /// Penumbra sharpness, larger values give harder shadows.
+    pub shadow_softness: f32,
+    pub shadow_max_steps: u32,
+    pub ambient_occlusion: bool,
+    pub ao_samples: u32,
+    pub ao_step: f32,
+    pub ao_strength: f32,
+    /// Step along the tangent space used to estimate SDF gradients.
+    pub normal_epsilon: f32,
+}
+
+impl Default for RMLighting {
+    fn default() -> Self {
+        Self {
+            ambient: LinearRgba::rgb(0.15, 0.15, 0.15),
+            directional_lights: vec![RMDirectionalLight {
+                direction: Vec3::new(0.3, 1.0, 0.5),
+                color: LinearRgba::WHITE,
+                intensity: 0.8,
+            }],
+            point_lights: vec![RMPointLight {
+                position: HypTransform::default()
+                    .translate(Vec3::new(0.0, 1.0, 1.0), 1.2)
+                    .translation,
+                color: LinearRgba::rgb(1.0, 0.8, 0.6),
+                intensity: 0.5,
+            }],
+            shadows: true,
+            shadow_softness: 8.0,
+            shadow_max_steps: 64,
+            ambient_occlusion: true,
+            ao_samples: 5,
+            ao_step: 0.05,
+            ao_strength: 2.0,
+            normal_epsilon: 0.0005,
+        }
+    }
+}
+
+/// A light at an ideal point, so its rays arrive along the geodesics converging on it.
+#[derive(Debug, Clone)]
+pub struct RMDirectionalLight {
+    /// Direction from the origin towards the ideal point.
+    pub direction: Vec3,
+    pub color: LinearRgba,
+    pub intensity: f32,
+}
+
+/// A light whose intensity falls off with the area `4π sinh²(d)` of the sphere of radius `d`.
+#[derive(Debug, Clone)]
+pub struct RMPointLight {
+    pub position: Vec4,
+    pub color: LinearRgba,
+    pub intensity: f32,
+}
+
+// Must match the LIGHT_* constants in the shader
+pub const LIGHT_DIRECTIONAL: u32 = 0;
+pub const LIGHT_POINT: u32 = 1;
+
+#[derive(ShaderType, Clone, Debug)]
+pub struct PreparedRMLighting {
+    pub ambient: Vec4,
+    pub light_count: u32,
+    /// Zero disables shadows.
+    pub shadow_max_steps: u32,
+    /// Zero disables ambient occlusion.
+    pub ao_samples: u32,
+    pub shadow_softness: f32,
+    pub ao_step: f32,
+    pub ao_strength: f32,
+    pub normal_epsilon: f32,
+}
+
+/// Either a point on the hyperboloid or, for directional lights, a light-like ideal point.
+#[derive(ShaderType, Clone, Debug)]
+pub struct PreparedRMLight {
+    pub position: Vec4,
+    pub color: Vec4,
+    pub kind: u32,
+}
+
+#[derive(ShaderType, Clone, Debug, Default)]
+pub struct PreparedRMLights {
+    #[size(runtime)]
+    pub lights: Vec<PreparedRMLight>,
+}
+
+impl From<&RMLighting> for PreparedRMLighting {
+    fn from(lighting: &RMLighting) -> Self {
+        Self {
+            ambient: Vec4::new(lighting.ambient.red, lighting.ambient.green, lighting.ambient.blue, 1.0),
+            light_count: (lighting.directional_lights.len() + lighting.point_lights.len()) as u32,
+            shadow_max_steps: if lighting.shadows { lighting.shadow_max_steps } else { 0 },
+            ao_samples: if lighting.ambient_occlusion { lighting.ao_samples } else { 0 },
+            shadow_softness: lighting.shadow_softness,
+            ao_step: lighting.ao_step,
+            ao_strength: lighting.ao_strength,
+            normal_epsilon: lighting.normal_epsilon,
+        }
+    }
+}
+
+impl From<&RMLighting> for PreparedRMLights {
+    fn from(lighting: &RMLighting) -> Self {
+        let radiance = |color: LinearRgba, intensity: f32| {
+            Vec4::new(color.red, color.green, color.blue, 1.0) * intensity
+        };
+
+        let directional = lighting.directional_lights.iter().map(|l| PreparedRMLight {
+            position: l.direction.normalize_or(Vec3::Y).extend(1.0),
+            color: radiance(l.color, l.intensity),
+            kind: LIGHT_DIRECTIONAL,
+        });
+
+        let point = lighting.point_lights.iter().map(|l| PreparedRMLight {
+            position: l.position,
+            color: radiance(l.color, l.intensity),
+            kind: LIGHT_POINT,
+        });
+
+        let mut lights: Vec<_> = directional.chain(point).collect();
+
+        // Storage buffers cannot be empty, `light_count` keeps the shader from reading this one.
+        if lights.is_empty() {
+            lights.push(PreparedRMLight {
+                position: Vec4::W,
+                color: Vec4::ZERO,
+                kind: LIGHT_POINT,
+            });
+        }
+
+        Self { lights }
+    }
+}
@@ -0,0 +1,166 @@
+// Turning the view with the mouse and the look stick. Mouse movement is a distance, turned into an
+// angle by `sensitivity` without regard to frame time, while the stick is a rate. Both feed the same
+// pending rotation, which smoothing lets out gradually instead of all at once.
+
+use bevy::{
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::*,
+    window::CursorGrabMode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input::RMActions,
+    navigation::{RMNavigation, RMNavigationMode},
+    ray_marching_material::RMCamera,
+    CamSystemSet,
+};
+
+/// Pixel scrolling, as from touchpads, counts this many pixels as one line of a wheel.
+pub const PIXELS_PER_LINE: f32 = 20.0;
+
+pub struct RMLookPlugin;
+
+impl Plugin for RMLookPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RMLookSettings>()
+            .add_systems(Update, look.in_set(CamSystemSet));
+    }
+}
+
+/// Pitch limits live on `LocalOrient`, free flight has none.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RMLookSettings {
+    /// Radians turned per pixel of mouse movement.
+    pub sensitivity: f32,
+    /// Time constant of the smoothing in seconds, zero turns as soon as the mouse moves.
+    pub smoothing: f32,
+    /// Moving the mouse or stick up looks down.
+    pub invert_y: bool,
+    /// In free flight the scroll wheel rolls the view.
+    pub wheel_roll: bool,
+    /// Radians rolled per line scrolled.
+    pub roll_sensitivity: f32,
+}
+
+impl Default for RMLookSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.002,
+            smoothing: 0.0,
+            invert_y: false,
+            wheel_roll: true,
+            roll_sensitivity: 0.05,
+        }
+    }
+}
+
+impl RMLookSettings {
+    /// Yaw and pitch for a mouse movement in pixels, y down.
+    pub fn mouse_turn(&self, delta: Vec2) -> Vec2 {
+        self.stick_turn(Vec2::new(delta.x, -delta.y)) * self.sensitivity
+    }
+
+    /// Yaw and pitch for a stick deflection, y up.
+    pub fn stick_turn(&self, look: Vec2) -> Vec2 {
+        if self.invert_y {
+            Vec2::new(look.x, -look.y)
+        } else {
+            look
+        }
+    }
+}
+
+/// How much of `pending` to turn over `dt` seconds. Whatever is left over decays exponentially,
+/// so the total turned doesn't depend on how the time is split into frames.
+pub fn smooth(pending: Vec2, smoothing: f32, dt: f32) -> Vec2 {
+    if smoothing <= 0.0 {
+        return pending;
+    }
+    pending * (1.0 - (-dt / smoothing).exp())
+}
+
+fn look(
+    mut motion: EventReader<MouseMotion>,
+    mut wheel: EventReader<MouseWheel>,
+    windows: Query<&Window>,
+    time: Res<Time>,
+    settings: Res<RMLookSettings>,
+    navigation: Res<RMNavigation>,
+    actions: Res<RMActions>,
+    mut pending: Local<Vec2>,
+    mut rm_camera: ResMut<RMCamera>,
+) {
+    let dt = time.delta_secs();
+    let grabbed = windows.get_single()
+        .is_ok_and(|window| window.cursor_options.grab_mode == CursorGrabMode::Locked);
+
+    let mouse: Vec2 = motion.read().map(|event| event.delta).sum();
+    let scroll: f32 = wheel.read()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / PIXELS_PER_LINE,
+        })
+        .sum();
+
+    if grabbed {
+        *pending += settings.mouse_turn(mouse);
+    }
+    // The stick turns whether or not the cursor is grabbed.
+    *pending += settings.stick_turn(actions.look) * dt;
+
+    let turn = smooth(*pending, settings.smoothing, dt);
+    *pending -= turn;
+
+    if navigation.mode == RMNavigationMode::FreeFly {
+        // Turning the frame itself leaves pitch unlimited.
+        rm_camera.transform.rotate_local_y(-turn.x).rotate_local_x(turn.y);
+        if settings.wheel_roll && grabbed && scroll != 0.0 {
+            rm_camera.transform.rotate_local_z(scroll * settings.roll_sensitivity);
+        }
+    } else if turn != Vec2::ZERO {
+        let pitch = rm_camera.orient.pitch();
+        rm_camera.orient
+            .add_mut_yaw(turn.x)
+            .add_mut_pitch(turn.y);
+
+        // Don't keep pushing against a pitch limit once it's been reached.
+        if (rm_camera.orient.pitch() - pitch - turn.y).abs() > 0.000001 {
+            pending.y = 0.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smoothing_is_frame_rate_independent() {
+        let pending = Vec2::new(0.4, -0.2);
+
+        let once = smooth(pending, 0.1, 1.0 / 30.0);
+        let first = smooth(pending, 0.1, 1.0 / 60.0);
+        let twice = first + smooth(pending - first, 0.1, 1.0 / 60.0);
+
+        assert!((once - twice).length() < 1e-6, "{once} {twice}");
+        assert!(once.length() < pending.length());
+        assert_eq!(smooth(pending, 0.0, 1.0 / 60.0), pending);
+    }
+
+    #[test]
+    fn test_mouse_turn_in_radians_per_pixel() {
+        let mut settings = RMLookSettings {
+            sensitivity: 0.01,
+            ..default()
+        };
+
+        // Moving the mouse right and up turns right and looks up.
+        assert_eq!(settings.mouse_turn(Vec2::new(100.0, -50.0)), Vec2::new(1.0, 0.5));
+
+        settings.invert_y = true;
+        assert_eq!(settings.mouse_turn(Vec2::new(100.0, -50.0)), Vec2::new(1.0, -0.5));
+        assert_eq!(settings.stick_turn(Vec2::new(0.5, 1.0)), Vec2::new(0.5, -1.0));
+    }
+}
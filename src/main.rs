@@ -1,10 +1,15 @@
+// encase's `ShaderType` derive emits `check` helpers that rustc reports as unused.
+#![allow(dead_code)]
+// Bevy systems take their resources and queries as arguments.
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
 use bevy::{
-    diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin}, input::mouse::MouseMotion, prelude::*, render::storage::ShaderStorageBuffer, window::{CursorGrabMode, WindowResized, WindowResolution}
+    diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin}, prelude::*, render::storage::ShaderStorageBuffer, window::{CursorGrabMode, WindowResized, WindowResolution}
 };
 
 use bevy_egui::EguiPlugin;
-use geometries::{hyp_dot, hyp_normalize, HypTransform};
-use ray_marching_material::{RMCamera, RMMaterial, RMRenderable};
+use geometries::HypTransform;
+use ray_marching_material::RMCamera;
 
 mod screen_space_quad;
 use crate::screen_space_quad::ScreenSpaceQuad;
@@ -17,6 +22,46 @@ use crate::ui::UIPlugin;
 
 mod geometries;
 
+mod cpu_renderer;
+
+mod lighting;
+
+mod bvh;
+
+mod hierarchy;
+
+mod honeycomb;
+
+mod quotient;
+
+mod scene;
+use crate::scene::RMScenePlugin;
+
+mod headless;
+
+mod camera_path;
+use crate::camera_path::{camera_path_playing, RMCameraPathPlugin};
+
+mod demo;
+use crate::demo::RMDemoPlugin;
+
+mod physics;
+use crate::physics::RMPhysicsPlugin;
+
+mod picking;
+use crate::picking::{RMPickingPlugin, RMPickingSet};
+
+mod navigation;
+use crate::navigation::RMNavigationPlugin;
+
+mod input;
+use crate::input::{RMAction, RMActions, RMInputPlugin};
+
+mod look;
+use crate::look::RMLookPlugin;
+
+mod model_view;
+
 pub const INIT_WIDTH: f32 = 720.0;
 pub const INIT_HEIGHT: f32 = 720.0;
 pub const WINDOW_NAME: &str = "Awesome game dude.";
@@ -26,6 +71,22 @@ pub const WINDOW_NAME: &str = "Awesome game dude.";
 pub struct CamSystemSet;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match headless::RenderArgs::parse(&args) {
+        Ok(Some(render_args)) => {
+            if let Err(err) = headless::run(render_args) {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(None) => {}
+        Err(err) => {
+            eprintln!("{err}\n\n{}", headless::USAGE);
+            std::process::exit(2);
+        }
+    }
+
     let mut app = App::new();
 
     app.insert_resource(ClearColor(Color::srgb(0.3, 0.3, 0.3)))
@@ -39,15 +100,23 @@ fn main() {
             }),
             ..default()
         }))
-        .add_plugins(RayMarchingMaterialPlugin)
+        .add_plugins((
+            RayMarchingMaterialPlugin,
+            RMScenePlugin::default(),
+            RMCameraPathPlugin,
+            RMDemoPlugin,
+            RMPhysicsPlugin,
+            RMPickingPlugin,
+            RMNavigationPlugin,
+            RMInputPlugin,
+            RMLookPlugin,
+        ))
         .add_plugins(LogDiagnosticsPlugin::default())
-        .add_plugins(FrameTimeDiagnosticsPlugin::default())
+        .add_plugins(FrameTimeDiagnosticsPlugin)
         .add_plugins((EguiPlugin, UIPlugin))
         //Create the aspect ratio as a resource. Only one instance of this data is needed so a global resource was chosen
         .add_systems(Startup, setup)
         .add_systems(Update, resize_event)
-        .add_systems(Update, process_camera_translation.in_set(CamSystemSet))
-        .add_systems(Update, process_camera_rotation.in_set(CamSystemSet))
         .add_systems(Update, cursor_grab_system.in_set(CamSystemSet))
         .add_systems(Update, log_pos_system);
 
@@ -55,20 +124,15 @@ fn main() {
         .add_systems(PostUpdate, check_egui_wants_focus)
         .configure_sets(
             Update,
-            CamSystemSet.run_if(resource_equals(EguiWantsFocus(false))),
-        );
-
-    app.insert_resource(Player { vertical_velocity: 0.0, grounded: true });
+            CamSystemSet
+                .run_if(resource_equals(EguiWantsFocus(false)))
+                .run_if(not(camera_path_playing)),
+        )
+        .configure_sets(Update, RMPickingSet.run_if(resource_equals(EguiWantsFocus(false))));
 
     app.run();
 }
 
-#[derive(Resource, Clone, Debug)]
-struct Player {
-    vertical_velocity: f32,
-    grounded: bool,
-}
-
 #[derive(Resource, Deref, DerefMut, PartialEq, Eq, Default)]
 struct EguiWantsFocus(bool);
 
@@ -103,13 +167,6 @@ fn setup(
         Mesh2d(meshes.add(Mesh::from(ScreenSpaceQuad::default()))),
         MeshMaterial2d(materials.add(RayMarchingMaterial::from_buffers(buffers))),
     ));
-
-    commands.spawn((
-        RMRenderable::sphere(0.2, RMMaterial::Flat(LinearRgba::BLUE)),
-        HypTransform::default()
-            .translate(Vec3::new(0.0, 1.0, 1.0), 0.5)
-            .clone(),
-    ));
 }
 
 //Handle a window resize event to set the AspectRatio so it can be updated in the uniform that is sent to our shader
@@ -122,108 +179,12 @@ fn resize_event(
     }
 }
 
-fn process_camera_translation(
-    keys: Res<ButtonInput<KeyCode>>,
-    mut rm_camera: ResMut<RMCamera>,
-    time: Res<Time>,
-    mut player: ResMut<Player>,
-) {
-    // Constants for speed and default directions.
-    const SPEED: f32 = 0.1;
-    let yaw = rm_camera.orient.yaw();
-    let forward = Vec3::new(yaw.sin(), 0.0, yaw.cos());
-    let right = Vec3::new(yaw.cos(), 0.0, -1.0 * yaw.sin());
-    let up = Vec3::ZERO.with_y(1.0);
-
-    let height = {
-        let t = rm_camera.transform.translation;
-        (t.w + t.y).ln() - 0.1
-    };
-
-    if keys.just_pressed(KeyCode::Space) && player.grounded {
-        player.vertical_velocity += 0.15
-    }
-
-    if height <= 0.0 {
-        // rm_camera.transform.translate(up, height);
-        // println!("{:?}", rm_camera.transform);
-        player.vertical_velocity = player.vertical_velocity.max(0.0);
-        rm_camera.transform.translate(up, player.vertical_velocity * time.delta_secs());
-        player.grounded = true;
-    } else {
-        player.vertical_velocity -= 0.1 * time.delta_secs();
-        rm_camera.transform.translate(up, player.vertical_velocity * time.delta_secs());
-        player.vertical_velocity -= 0.1 * time.delta_secs();
-        player.grounded = false;
-    }
-
-    // This will accumulate the total movement for this frame.
-    let mut movement = Vec3::ZERO;
-
-    // Check for key presses and adjust the movement vector accordingly.
-    if keys.pressed(KeyCode::KeyW) {
-        movement += forward; // Note: moving "forward" typically means reducing the Z coordinate in many engines.
-    }
-    if keys.pressed(KeyCode::KeyS) {
-        movement -= forward;
-    }
-    if keys.pressed(KeyCode::KeyA) {
-        movement -= right;
-    }
-    if keys.pressed(KeyCode::KeyD) {
-        movement += right;
-    }
-    if keys.pressed(KeyCode::KeyR) {
-        movement += up;
-    }
-    if keys.pressed(KeyCode::KeyF) {
-        movement -= up;
-    }
-
-    // If there's any movement, normalize the vector to ensure consistent movement speed in all directions.
-    if movement == Vec3::ZERO {
-        return;
-    }
-    
-    movement = movement.normalize();
-
-    let n = Vec4::new(0.0, -1.0, 0.0, 1.0);
-    
-    rm_camera.transform
-        .translate(movement, SPEED * time.delta_secs());
-
-    let p = rm_camera.transform.translation;
-
-    let v = n + hyp_dot(p, n) * p;
-
-    rm_camera.transform
-        .set_up(-1.0 * hyp_normalize(v));
-}
-
-fn process_camera_rotation(
-    mut motion_event: EventReader<MouseMotion>,
-    windows: Query<&mut Window>,
-    mut rm_camera: ResMut<RMCamera>,
-    time: Res<Time>,
-) {
-    let window = windows.single();
-
-    for event in motion_event.read() {
-        const ROTATION_SPEED: f32 = 0.1;
-        if window.cursor_options.grab_mode == CursorGrabMode::Locked {
-            rm_camera.orient
-                .add_mut_yaw(event.delta.x * ROTATION_SPEED * time.delta_secs())
-                .add_mut_pitch(-event.delta.y * ROTATION_SPEED * time.delta_secs());
-        }
-    }
-}
-
 fn log_pos_system(
     transforms: Query<&HypTransform>,
-    key: Res<ButtonInput<KeyCode>>,
+    actions: Res<RMActions>,
     cam: Res<RMCamera>,
 ) {
-    if key.just_pressed(KeyCode::KeyL) {
+    if actions.just_pressed(RMAction::LogPosition) {
         println!("cam: {:?}", cam.transform);
         for t in transforms.iter() {
             println!("{t:?}")
@@ -235,17 +196,16 @@ fn log_pos_system(
 // and releases it when the escape key is pressed
 fn cursor_grab_system(
     mut windows: Query<&mut Window>,
-    mouse: Res<ButtonInput<MouseButton>>,
-    key: Res<ButtonInput<KeyCode>>,
+    actions: Res<RMActions>,
 ) {
     let mut window = windows.single_mut();
 
-    if mouse.just_pressed(MouseButton::Left) {
+    if actions.just_pressed(RMAction::GrabCursor) {
         window.cursor_options.visible = false;
         window.cursor_options.grab_mode = CursorGrabMode::Locked;
     }
 
-    if key.just_pressed(KeyCode::Escape) {
+    if actions.just_pressed(RMAction::ReleaseCursor) {
         window.cursor_options.visible = true;
         window.cursor_options.grab_mode = CursorGrabMode::None;
     }
@@ -0,0 +1,397 @@
+// Looking at H^3 from outside, through the Poincaré or Beltrami-Klein ball or the upper half-space.
+// Rays are straight lines through the model, marched with each step shrunk from the hyperbolic
+// distance to the scene to what that distance covers in the model, and whatever they hit is lit as
+// usual in H^3. The camera is drawn as a small ball with the edges of its view frustum, all
+// geodesics, so they come out straight in the Klein model and as circular arcs in the others. Only
+// perspective cameras have a frustum, the other projections are drawn as just the ball.
+
+use bevy::{prelude::*, render::render_resource::ShaderType};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    geometries::{from_upper_half_space, hyp_dot, to_upper_half_space, Geometry},
+    ray_marching_material::{PreparedRMCamera, RMCamera, RMProjection},
+};
+
+// Must match the MODEL_* constants in the shader
+pub const MODEL_NONE: u32 = 0;
+pub const MODEL_POINCARE: u32 = 1;
+pub const MODEL_KLEIN: u32 = 2;
+pub const MODEL_UPPER_HALF_SPACE: u32 = 3;
+
+/// The balls are drawn out to this distance from the origin in H^3. Beyond it points crowd
+/// against the boundary closer together than `f32` can tell apart.
+pub const MODEL_MAX_DIST: f32 = 6.0;
+
+/// The upper half-space is drawn in a box this far across either way from the origin, and as high.
+pub const UPPER_HALF_SPACE_EXTENT: f32 = 4.0;
+/// Height the upper half-space is drawn down to, for the same reason as `MODEL_MAX_DIST`.
+pub const UPPER_HALF_SPACE_MIN_HEIGHT: f32 = 0.01;
+
+/// Half the height of the view at unit distance from the viewer, independent of the camera's
+/// own field of view.
+pub const MODEL_TAN_FOV: f32 = 0.5;
+
+/// Edges of the drawn frustum: four out from the camera and the four sides of its far end.
+pub const FRUSTUM_SEGMENTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RMHyperbolicModel {
+    /// Conformal, geodesics are circular arcs meeting the boundary at right angles.
+    #[default]
+    Poincare,
+    /// Geodesics are straight lines, angles are distorted.
+    Klein,
+    /// Conformal, with the floor's ideal point at infinity, see `to_upper_half_space`. It is seen
+    /// with the boundary plane at the top, so up in the scene is up on screen.
+    UpperHalfSpace,
+}
+
+impl RMHyperbolicModel {
+    pub const ALL: [RMHyperbolicModel; 3] = [
+        RMHyperbolicModel::Poincare,
+        RMHyperbolicModel::Klein,
+        RMHyperbolicModel::UpperHalfSpace,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RMHyperbolicModel::Poincare => "Poincaré Ball",
+            RMHyperbolicModel::Klein => "Klein Ball",
+            RMHyperbolicModel::UpperHalfSpace => "Upper Half-Space",
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        match self {
+            RMHyperbolicModel::Poincare => MODEL_POINCARE,
+            RMHyperbolicModel::Klein => MODEL_KLEIN,
+            RMHyperbolicModel::UpperHalfSpace => MODEL_UPPER_HALF_SPACE,
+        }
+    }
+
+    /// The point of the model showing `p` on the hyperboloid.
+    pub fn to_model(self, p: Vec4) -> Vec3 {
+        match self {
+            RMHyperbolicModel::Poincare => p.truncate() / (1.0 + p.w),
+            RMHyperbolicModel::Klein => p.truncate() / p.w,
+            RMHyperbolicModel::UpperHalfSpace => to_upper_half_space(p),
+        }
+    }
+
+    /// The point on the hyperboloid shown at `q`, which must lie inside the model.
+    pub fn to_hyperboloid(self, q: Vec3) -> Vec4 {
+        let r2 = q.length_squared();
+        match self {
+            RMHyperbolicModel::Poincare => (2.0 * q).extend(1.0 + r2) / (1.0 - r2),
+            RMHyperbolicModel::Klein => q.extend(1.0) / (1.0 - r2).sqrt(),
+            RMHyperbolicModel::UpperHalfSpace => from_upper_half_space(q),
+        }
+    }
+
+    /// How far a ray in the model can go from `q` when the scene is `distance` away in H^3. The
+    /// balls squeeze space most along the radius, so this is how far a point moving straight out
+    /// from the centre gets. Balls around a point in the upper half-space are Euclidean balls
+    /// centred higher up, so there it is how far below `q` the bottom of one is, the nearer side.
+    pub fn step(&self, q: Vec3, distance: f32) -> f32 {
+        let r = q.length();
+        match self {
+            RMHyperbolicModel::Poincare => (r.atanh() + 0.5 * distance).tanh() - r,
+            RMHyperbolicModel::Klein => (r.atanh() + distance).tanh() - r,
+            RMHyperbolicModel::UpperHalfSpace => q.y * (1.0 - (-distance).exp()),
+        }
+    }
+
+    /// Where a ray from `eye` along the unit vector `dir` enters and leaves the part of the model
+    /// that is drawn, if it passes through it at all.
+    pub fn clip(&self, eye: Vec3, dir: Vec3) -> Option<(f32, f32)> {
+        let (enter, exit) = match self {
+            RMHyperbolicModel::Poincare | RMHyperbolicModel::Klein => {
+                let scale = if *self == RMHyperbolicModel::Poincare { 0.5 } else { 1.0 };
+                let edge = (scale * MODEL_MAX_DIST).tanh();
+                let b = eye.dot(dir);
+                let disc = b * b - eye.length_squared() + edge * edge;
+                if disc < 0.0 {
+                    return None;
+                }
+                (-b - disc.sqrt(), -b + disc.sqrt())
+            }
+            RMHyperbolicModel::UpperHalfSpace => {
+                let e = UPPER_HALF_SPACE_EXTENT;
+                let min = Vec3::new(-e, UPPER_HALF_SPACE_MIN_HEIGHT, -e);
+                let max = Vec3::splat(e);
+                let a = (min - eye) / dir;
+                let b = (max - eye) / dir;
+                (a.min(b).max_element(), a.max(b).min_element())
+            }
+        };
+        (enter < exit && exit > 0.0).then_some((enter.max(0.0), exit))
+    }
+
+    /// The point the model is seen around, the origin of H^3.
+    pub fn centre(&self) -> Vec3 {
+        self.to_model(Vec4::W)
+    }
+
+    /// Which way in the model is up on screen.
+    pub fn up(&self) -> Vec3 {
+        match self {
+            RMHyperbolicModel::UpperHalfSpace => Vec3::NEG_Y,
+            _ => Vec3::Y,
+        }
+    }
+}
+
+/// Whether the scene is drawn from the camera or from outside a model of H^3, and where the
+/// model is seen from. Model views are only drawn in H^3, other geometries keep the first-person
+/// view.
+#[derive(Resource, Debug, Clone)]
+pub struct RMModelView {
+    /// `None` draws the usual first-person view.
+    pub model: Option<RMHyperbolicModel>,
+    /// Direction the model is seen from: radians about its vertical axis, and up from level.
+    pub yaw: f32,
+    pub pitch: f32,
+    /// From `RMHyperbolicModel::centre`, in the model's units. The balls have unit radius.
+    pub distance: f32,
+    pub show_camera: bool,
+    /// Radius of the ball marking the camera, in H^3.
+    pub camera_radius: f32,
+    /// Length of the frustum's edges out from the camera, in H^3.
+    pub frustum_length: f32,
+}
+
+impl Default for RMModelView {
+    fn default() -> Self {
+        Self {
+            model: None,
+            yaw: 0.6,
+            pitch: 0.4,
+            distance: 3.0,
+            show_camera: true,
+            camera_radius: 0.05,
+            frustum_length: 1.0,
+        }
+    }
+}
+
+impl RMModelView {
+    /// The model drawn in `geometry`, if any.
+    pub fn active(&self, geometry: Geometry) -> Option<RMHyperbolicModel> {
+        self.model.filter(|_| geometry == Geometry::Hyperbolic)
+    }
+
+    /// Where `model` is seen from.
+    pub fn eye(&self, model: RMHyperbolicModel) -> Vec3 {
+        let direction = self.pitch.cos() * (self.yaw.sin() * Vec3::X + self.yaw.cos() * Vec3::Z)
+            + self.pitch.sin() * model.up();
+        model.centre() + self.distance * direction
+    }
+}
+
+/// Run condition for systems that only make sense when looking through the camera.
+pub fn first_person(model_view: Res<RMModelView>, geometry: Res<Geometry>) -> bool {
+    model_view.active(*geometry).is_none()
+}
+
+/// Distance from `p` to the geodesic segment from `a` to `b`, all on the hyperboloid.
+pub fn segment_sdf(p: Vec4, a: Vec4, b: Vec4) -> f32 {
+    let geometry = Geometry::Hyperbolic;
+    let length = geometry.distance(a, b);
+    if length < 0.000001 {
+        return geometry.distance(p, a);
+    }
+    let v = geometry.direction(a, b);
+
+    // Along the geodesic, -<p, a cosh t + v sinh t> is the cosh of the distance to p.
+    let x = -hyp_dot(p, a);
+    let y = hyp_dot(p, v);
+    let t = (y / x).clamp(-0.999999, 0.999999).atanh().clamp(0.0, length);
+    (x * t.cosh() - y * t.sinh()).max(1.0).acosh()
+}
+
+/// See `ModelView` in the shader. `model == MODEL_NONE` draws the first-person view.
+#[derive(ShaderType, Clone, Debug, Default)]
+pub struct PreparedRMModelView {
+    /// The viewer, in the model's coordinates. `right` and `up` are scaled so the camera's
+    /// `uv_coords` span `MODEL_TAN_FOV`.
+    pub eye: Vec4,
+    pub forward: Vec4,
+    pub right: Vec4,
+    pub up: Vec4,
+    pub frustum_starts: [Vec4; FRUSTUM_SEGMENTS],
+    pub frustum_ends: [Vec4; FRUSTUM_SEGMENTS],
+    pub camera_position: Vec4,
+    pub camera_radius: f32,
+    pub edge_radius: f32,
+    pub model: u32,
+    pub show_camera: u32,
+}
+
+impl PreparedRMModelView {
+    pub fn new(model_view: &RMModelView, rm_camera: &RMCamera, geometry: Geometry) -> Self {
+        let Some(model) = model_view.active(geometry) else {
+            return Self::default();
+        };
+
+        let eye = model_view.eye(model);
+        let forward = (model.centre() - eye).normalize_or(Vec3::NEG_Z);
+        let right = forward.cross(model.up()).normalize_or(Vec3::X);
+        let up = right.cross(forward);
+        let scale = MODEL_TAN_FOV / rm_camera.settings.tan_fov;
+
+        let camera: PreparedRMCamera = rm_camera.into();
+        let mut frustum_starts = [camera.position; FRUSTUM_SEGMENTS];
+        let mut frustum_ends = [camera.position; FRUSTUM_SEGMENTS];
+        if rm_camera.settings.projection == RMProjection::Perspective {
+            let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)| {
+                let direction = geometry.normalize(
+                    camera.forward
+                        + x * camera.tan_fov * camera.aspect_ratio * camera.right
+                        + y * camera.tan_fov * camera.up,
+                );
+                geometry.flow(camera.position, direction, model_view.frustum_length)
+            });
+            for (i, &corner) in corners.iter().enumerate() {
+                frustum_ends[i] = corner;
+                frustum_starts[i + 4] = corner;
+                frustum_ends[i + 4] = corners[(i + 1) % 4];
+            }
+        }
+
+        Self {
+            eye: eye.extend(0.0),
+            forward: forward.extend(0.0),
+            right: (right * scale).extend(0.0),
+            up: (up * scale).extend(0.0),
+            frustum_starts,
+            frustum_ends,
+            camera_position: camera.position,
+            camera_radius: model_view.camera_radius,
+            edge_radius: 0.4 * model_view.camera_radius,
+            model: model.id(),
+            show_camera: model_view.show_camera as u32,
+        }
+    }
+
+    pub fn model(&self) -> Option<RMHyperbolicModel> {
+        RMHyperbolicModel::ALL.into_iter().find(|model| model.id() == self.model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometries::{HypTransform, ORIGIN};
+
+    fn points() -> impl Iterator<Item = Vec4> {
+        [
+            (Vec3::X, 0.0),
+            (Vec3::new(0.3, -1.0, 0.2), 0.7),
+            (Vec3::new(-1.0, 0.5, 2.0), 2.5),
+            (Vec3::Z, 5.0),
+        ]
+        .into_iter()
+        .map(|(v, t)| HypTransform::default().translate_in(Geometry::Hyperbolic, v, t).translation)
+    }
+
+    #[test]
+    fn test_model_round_trip() {
+        for model in RMHyperbolicModel::ALL {
+            for p in points() {
+                let q = model.to_model(p);
+                assert!((model.to_hyperboloid(q) - p).length() < 1e-3 * p.w, "{model:?}: {p}");
+            }
+        }
+
+        for p in points() {
+            let distance = Geometry::Hyperbolic.distance(ORIGIN, p);
+            let poincare = RMHyperbolicModel::Poincare.to_model(p).length();
+            let klein = RMHyperbolicModel::Klein.to_model(p).length();
+            assert!((poincare - (0.5 * distance).tanh()).abs() < 1e-5, "{p}");
+            assert!((klein - distance.tanh()).abs() < 1e-5, "{p}");
+        }
+    }
+
+    #[test]
+    fn test_klein_geodesics_are_straight() {
+        let a = HypTransform::default()
+            .translate_in(Geometry::Hyperbolic, Vec3::new(1.0, 0.4, 0.0), 1.2)
+            .clone();
+        let direction = Geometry::Hyperbolic.normalize(a.up + 0.5 * a.forward);
+
+        let [p, q, r] = [0.0, 0.8, 2.0]
+            .map(|t| RMHyperbolicModel::Klein.to_model(Geometry::Hyperbolic.flow(a.translation, direction, t)));
+        assert!((q - p).cross(r - p).length() < 1e-5);
+    }
+
+    #[test]
+    fn test_step_stays_outside_sphere() {
+        let geometry = Geometry::Hyperbolic;
+        let centre = HypTransform::default()
+            .translate_in(geometry, Vec3::new(0.5, 0.5, -1.0), 1.5)
+            .translation;
+        let radius = 0.3;
+
+        for model in RMHyperbolicModel::ALL {
+            for p in points() {
+                let q = model.to_model(p);
+                let distance = geometry.distance(p, centre) - radius;
+                let step = model.step(q, distance);
+                assert!(step > 0.0);
+
+                for direction in [Vec3::X, Vec3::NEG_Y, Vec3::new(1.0, 1.0, -1.0).normalize(), -q.normalize_or(Vec3::Y)] {
+                    let reached = model.to_hyperboloid(q + step * direction);
+                    assert!(geometry.distance(reached, centre) > radius - 1e-3, "{model:?}: {p} {direction}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_segment_sdf() {
+        let geometry = Geometry::Hyperbolic;
+        let a = ORIGIN;
+        let b = geometry.flow(ORIGIN, Vec4::X, 2.0);
+        let beside = HypTransform::default()
+            .translate_in(geometry, Vec3::X, 1.0)
+            .translate_in(geometry, Vec3::Y, 0.5)
+            .translation;
+        let beyond = geometry.flow(ORIGIN, Vec4::X, 3.0);
+
+        assert!((segment_sdf(beside, a, b) - 0.5).abs() < 1e-4);
+        assert!((segment_sdf(beyond, a, b) - 1.0).abs() < 1e-4);
+        assert!(segment_sdf(geometry.flow(ORIGIN, Vec4::X, 0.5), a, b) < 1e-3);
+    }
+
+    #[test]
+    fn test_model_view_only_in_hyperbolic_space() {
+        let model_view = RMModelView {
+            model: Some(RMHyperbolicModel::Klein),
+            ..default()
+        };
+        let camera = RMCamera::default();
+
+        assert_eq!(PreparedRMModelView::new(&model_view, &camera, Geometry::Hyperbolic).model(), Some(RMHyperbolicModel::Klein));
+        assert_eq!(PreparedRMModelView::new(&model_view, &camera, Geometry::Spherical).model, MODEL_NONE);
+    }
+
+    #[test]
+    fn test_frustum_only_for_perspective() {
+        let model_view = RMModelView {
+            model: Some(RMHyperbolicModel::Poincare),
+            ..default()
+        };
+        let mut camera = RMCamera::default();
+        let drawn = |camera: &RMCamera| {
+            let prepared = PreparedRMModelView::new(&model_view, camera, Geometry::Hyperbolic);
+            prepared.frustum_starts.iter().zip(prepared.frustum_ends).any(|(a, b)| *a != b)
+        };
+
+        assert!(drawn(&camera));
+        for projection in [RMProjection::Fisheye, RMProjection::Equirectangular, RMProjection::Cubemap] {
+            camera.settings.projection = projection;
+            assert!(!drawn(&camera), "{projection:?}");
+        }
+    }
+}
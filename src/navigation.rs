@@ -0,0 +1,400 @@
+// Ways of moving the camera around. Walking leaves falling and standing on things to physics,
+// flying steers the camera's own frame so it can roll and loop without a floor to refer to, and
+// orbiting circles an entity at a fixed distance.
+
+use bevy::prelude::*;
+
+use crate::{
+    camera_path::camera_path_playing,
+    geometries::{Geometry, HypTransform},
+    hierarchy::{propagate_hyp_transforms, HypGlobalTransform},
+    input::{RMAction, RMActions},
+    physics::RMCameraBody,
+    picking::RMSelection,
+    quotient::{self, RMFundamentalDomain},
+    ray_marching_material::RMCamera,
+    CamSystemSet,
+};
+
+pub struct RMNavigationPlugin;
+
+impl Plugin for RMNavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RMNavigation>()
+            .add_systems(Update, switch_mode.before(CamSystemSet))
+            .add_systems(
+                Update,
+                (
+                    walk.run_if(navigating(RMNavigationMode::Walk)),
+                    fly.run_if(navigating(RMNavigationMode::FreeFly)),
+                    steer_orbit.run_if(navigating(RMNavigationMode::Orbit)),
+                )
+                    .in_set(CamSystemSet),
+            )
+            // Follows the target to where it is drawn this frame, before the camera is wrapped.
+            .add_systems(
+                PostUpdate,
+                orbit_camera
+                    .run_if(navigating(RMNavigationMode::Orbit))
+                    .run_if(not(camera_path_playing))
+                    .after(propagate_hyp_transforms)
+                    .before(quotient::wrap_camera),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RMNavigationMode {
+    /// Upright on the floor, with gravity and jumping.
+    #[default]
+    Walk,
+    /// No gravity, moving along and turning about every axis of the view.
+    FreeFly,
+    /// Circling `RMNavigation::orbit_target`, looking at it.
+    Orbit,
+}
+
+impl RMNavigationMode {
+    pub const ALL: [RMNavigationMode; 3] = [
+        RMNavigationMode::Walk,
+        RMNavigationMode::FreeFly,
+        RMNavigationMode::Orbit,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RMNavigationMode::Walk => "Walk",
+            RMNavigationMode::FreeFly => "Free Fly",
+            RMNavigationMode::Orbit => "Orbit",
+        }
+    }
+}
+
+/// How the camera is currently moved, and the settings for each mode. Speeds are in distance
+/// or radians per second.
+#[derive(Resource, Debug, Clone)]
+pub struct RMNavigation {
+    pub mode: RMNavigationMode,
+    pub walk_speed: f32,
+    pub jump_speed: f32,
+    pub fly_speed: f32,
+    pub roll_speed: f32,
+    /// Entity to orbit, taken from the selection when switching to orbiting.
+    pub orbit_target: Option<Entity>,
+    /// Geodesic distance kept from the target.
+    pub orbit_distance: f32,
+    pub orbit_speed: f32,
+    pub zoom_speed: f32,
+}
+
+impl Default for RMNavigation {
+    fn default() -> Self {
+        Self {
+            mode: RMNavigationMode::Walk,
+            walk_speed: 0.1,
+            jump_speed: 0.15,
+            fly_speed: 0.2,
+            roll_speed: 1.0,
+            orbit_target: None,
+            orbit_distance: 1.0,
+            orbit_speed: 1.0,
+            zoom_speed: 0.5,
+        }
+    }
+}
+
+/// Run condition for systems driving the camera in one mode.
+pub fn navigating(mode: RMNavigationMode) -> impl Fn(Res<RMNavigation>) -> bool + Clone {
+    move |navigation: Res<RMNavigation>| navigation.mode == mode
+}
+
+fn switch_mode(
+    mut navigation: ResMut<RMNavigation>,
+    mut last: Local<RMNavigationMode>,
+    selection: Res<RMSelection>,
+    mut rm_camera: ResMut<RMCamera>,
+    mut camera_body: ResMut<RMCameraBody>,
+) {
+    if navigation.mode == *last {
+        return;
+    }
+    if navigation.mode == RMNavigationMode::Orbit && selection.0.is_none() {
+        warn!("nothing selected to orbit, flying instead");
+        navigation.mode = RMNavigationMode::FreeFly;
+        if navigation.mode == *last {
+            return;
+        }
+    }
+    *last = navigation.mode;
+    camera_body.0.velocity = Vec3::ZERO;
+
+    match navigation.mode {
+        RMNavigationMode::Walk => {}
+        RMNavigationMode::FreeFly => {
+            // Flying turns the frame itself, so fold the view into it.
+            let rm_camera = &mut *rm_camera;
+            let [right, up, forward] = rm_camera.orient.to_global_orient(&rm_camera.transform);
+            rm_camera.transform.right = right;
+            rm_camera.transform.up = up;
+            rm_camera.transform.forward = forward;
+            rm_camera.orient.set_yaw(0.0).set_pitch(0.0);
+        }
+        RMNavigationMode::Orbit => navigation.orbit_target = selection.0,
+    }
+}
+
+fn walk(
+    actions: Res<RMActions>,
+    navigation: Res<RMNavigation>,
+    rm_camera: Res<RMCamera>,
+    mut camera_body: ResMut<RMCameraBody>,
+    domain: Res<RMFundamentalDomain>,
+) {
+    // Quotients have no floor to fall to or stand up from, so you fly around them freely.
+    let has_floor = domain.is_empty();
+
+    let yaw = rm_camera.orient.yaw();
+    let forward = Vec3::new(yaw.sin(), 0.0, yaw.cos());
+    let right = Vec3::new(yaw.cos(), 0.0, -yaw.sin());
+
+    // This will accumulate the total movement for this frame.
+    let mut movement = actions.movement.y * forward + actions.movement.x * right;
+    let mut vertical = 0.0;
+
+    if actions.pressed(RMAction::MoveForward) {
+        movement += forward;
+    }
+    if actions.pressed(RMAction::MoveBack) {
+        movement -= forward;
+    }
+    if actions.pressed(RMAction::MoveLeft) {
+        movement -= right;
+    }
+    if actions.pressed(RMAction::MoveRight) {
+        movement += right;
+    }
+    if actions.pressed(RMAction::MoveUp) {
+        vertical += 1.0;
+    }
+    if actions.pressed(RMAction::MoveDown) {
+        vertical -= 1.0;
+    }
+
+    // Walking sets the velocity along the floor outright, falling is left to physics.
+    let body = &mut camera_body.0;
+    let walk = movement.clamp_length_max(1.0) * navigation.walk_speed;
+    body.velocity.x = walk.x;
+    body.velocity.z = walk.z;
+    if vertical != 0.0 || !has_floor {
+        body.velocity.y = vertical * navigation.walk_speed;
+    }
+
+    if actions.just_pressed(RMAction::Jump) && body.grounded {
+        body.velocity.y = navigation.jump_speed;
+    }
+}
+
+fn fly(
+    actions: Res<RMActions>,
+    time: Res<Time>,
+    navigation: Res<RMNavigation>,
+    mut rm_camera: ResMut<RMCamera>,
+    mut camera_body: ResMut<RMCameraBody>,
+) {
+    let mut movement = Vec3::new(actions.movement.x, 0.0, actions.movement.y);
+    let mut roll = 0.0;
+
+    if actions.pressed(RMAction::MoveForward) {
+        movement += Vec3::Z;
+    }
+    if actions.pressed(RMAction::MoveBack) {
+        movement -= Vec3::Z;
+    }
+    if actions.pressed(RMAction::MoveLeft) {
+        movement -= Vec3::X;
+    }
+    if actions.pressed(RMAction::MoveRight) {
+        movement += Vec3::X;
+    }
+    if actions.pressed(RMAction::MoveUp) {
+        movement += Vec3::Y;
+    }
+    if actions.pressed(RMAction::MoveDown) {
+        movement -= Vec3::Y;
+    }
+    if actions.pressed(RMAction::RollLeft) {
+        roll += 1.0;
+    }
+    if actions.pressed(RMAction::RollRight) {
+        roll -= 1.0;
+    }
+
+    let view = rm_camera.orient.mat3();
+    camera_body.0.velocity = view * movement.clamp_length_max(1.0) * navigation.fly_speed;
+    if roll != 0.0 {
+        rm_camera.transform.rotate_local_z(roll * navigation.roll_speed * time.delta_secs());
+    }
+}
+
+/// Moving forward and back zooms in and out, left and right circle the target. Looking around
+/// as usual swings the camera around the target.
+fn steer_orbit(
+    actions: Res<RMActions>,
+    time: Res<Time>,
+    mut navigation: ResMut<RMNavigation>,
+    mut rm_camera: ResMut<RMCamera>,
+) {
+    let dt = time.delta_secs();
+
+    let mut zoom = -actions.movement.y;
+    if actions.pressed(RMAction::MoveForward) {
+        zoom -= 1.0;
+    }
+    if actions.pressed(RMAction::MoveBack) {
+        zoom += 1.0;
+    }
+    if zoom != 0.0 {
+        let distance = navigation.orbit_distance + zoom * navigation.zoom_speed * dt;
+        navigation.orbit_distance = distance.max(0.1);
+    }
+
+    let mut circle = -actions.movement.x;
+    if actions.pressed(RMAction::MoveLeft) {
+        circle += 1.0;
+    }
+    if actions.pressed(RMAction::MoveRight) {
+        circle -= 1.0;
+    }
+    if circle != 0.0 {
+        rm_camera.orient.add_mut_yaw(circle * navigation.orbit_speed * dt);
+    }
+}
+
+/// Puts `camera` `distance` away from `target`, looking at it along `view`, given in the
+/// camera's local axes. The frame is carried over to the target and back along geodesics.
+pub fn orbit_transform(
+    geometry: Geometry,
+    camera: &HypTransform,
+    view: Vec3,
+    target: Vec4,
+    distance: f32,
+    upright: bool,
+) -> HypTransform {
+    let mut transform = camera.clone();
+
+    let to_target = geometry.distance(transform.translation, target);
+    if to_target > 0.000001 {
+        let direction = transform.to_local_in(geometry, geometry.direction(transform.translation, target));
+        transform.translate_in(geometry, direction, to_target);
+    }
+    if upright {
+        transform.set_up_in(geometry, geometry.up(transform.translation));
+    }
+
+    transform.translate_in(geometry, -view, distance);
+    transform
+}
+
+fn orbit_camera(
+    mut navigation: ResMut<RMNavigation>,
+    geometry: Res<Geometry>,
+    domain: Res<RMFundamentalDomain>,
+    targets: Query<&HypGlobalTransform>,
+    mut rm_camera: ResMut<RMCamera>,
+) {
+    let Some(target) = navigation.orbit_target.and_then(|entity| targets.get(entity).ok()) else {
+        warn!("orbit target is gone, flying instead");
+        navigation.orbit_target = None;
+        navigation.mode = RMNavigationMode::FreeFly;
+        return;
+    };
+
+    let view = rm_camera.orient.mat3() * Vec3::Z;
+    rm_camera.transform = orbit_transform(
+        *geometry,
+        &rm_camera.transform,
+        view,
+        target.transform().translation,
+        navigation.orbit_distance,
+        domain.is_empty(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orbit_keeps_distance_and_faces_target() {
+        for geometry in Geometry::ALL {
+            let camera = HypTransform::default()
+                .translate_in(geometry, Vec3::new(0.3, 0.5, -1.0), 0.8)
+                .clone();
+            let target = HypTransform::default()
+                .translate_in(geometry, Vec3::new(1.0, 0.2, 0.4), 0.6)
+                .translation;
+            let view = Vec3::new(0.2, -0.3, 1.0).normalize();
+
+            let orbiting = orbit_transform(geometry, &camera, view, target, 0.7, false);
+
+            let distance = geometry.distance(orbiting.translation, target);
+            assert!((distance - 0.7).abs() < 1e-4, "{geometry:?}: {distance}");
+            let to_target = orbiting.to_local_in(geometry, geometry.direction(orbiting.translation, target));
+            assert!((to_target - view).length() < 1e-3, "{geometry:?}: {to_target}");
+        }
+    }
+
+    #[test]
+    fn test_orbit_falls_back_to_flying() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        let mut world = World::new();
+        world.init_resource::<RMNavigation>();
+        world.init_resource::<RMCamera>();
+        world.init_resource::<RMCameraBody>();
+        world.init_resource::<Geometry>();
+        world.init_resource::<RMFundamentalDomain>();
+        let first = world.spawn(HypGlobalTransform::default()).id();
+        let second = world.spawn(HypGlobalTransform::default()).id();
+
+        // Switching takes whatever is selected now, not what was orbited last time.
+        world.insert_resource(RMSelection(Some(first)));
+        world.resource_mut::<RMNavigation>().mode = RMNavigationMode::Orbit;
+        world.run_system_once(switch_mode).unwrap();
+        world.insert_resource(RMSelection(Some(second)));
+        world.resource_mut::<RMNavigation>().mode = RMNavigationMode::Orbit;
+        world.run_system_once(switch_mode).unwrap();
+        assert_eq!(world.resource::<RMNavigation>().orbit_target, Some(second));
+
+        world.despawn(second);
+        world.run_system_once(orbit_camera).unwrap();
+        let navigation = world.resource::<RMNavigation>();
+        assert_eq!(navigation.mode, RMNavigationMode::FreeFly);
+        assert_eq!(navigation.orbit_target, None);
+
+        world.insert_resource(RMSelection(None));
+        world.resource_mut::<RMNavigation>().mode = RMNavigationMode::Orbit;
+        world.run_system_once(switch_mode).unwrap();
+        assert_eq!(world.resource::<RMNavigation>().mode, RMNavigationMode::FreeFly);
+    }
+
+    #[test]
+    fn test_orbit_stays_upright() {
+        let geometry = Geometry::Hyperbolic;
+        let camera = HypTransform::default()
+            .translate_in(geometry, Vec3::Y, 0.5)
+            .rotate_local_x(0.3)
+            .clone();
+        let target = HypTransform::default()
+            .translate_in(geometry, Vec3::new(1.0, 0.3, 0.0), 1.5)
+            .translation;
+
+        let orbiting = orbit_transform(geometry, &camera, Vec3::Z, target, 1.0, true);
+
+        // Upright at the target. Backing away along a geodesic tips the camera over a little,
+        // but never sideways.
+        let up = orbiting.to_local_in(geometry, geometry.up(orbiting.translation));
+        assert!(up.y > 0.5, "{up}");
+        assert!(up.x.abs() < 1e-3, "{up}");
+    }
+}
@@ -0,0 +1,462 @@
+// Collisions against the same distance field the renderer draws, evaluated with the CPU mirror
+// of the shader. Bodies are spheres: whenever one sinks into the scene it is pushed back out
+// along the field's gradient, and the part of its velocity going into the surface is bounced or
+// absorbed. Velocities are kept in the body's local axes, which translation parallel transports,
+// so between contacts bodies simply follow geodesics.
+
+use bevy::prelude::*;
+
+use crate::{
+    camera_path::camera_path_playing,
+    cpu_renderer::{sdf::scene_sdf, shading::estimate_normal},
+    demo::CameraGizmo,
+    geometries::{Geometry, HypTransform},
+    hierarchy::{propagate_hyp_transforms, HypGlobalTransform},
+    honeycomb::RMHoneycomb,
+    lighting::RMLighting,
+    navigation::{navigating, RMNavigation, RMNavigationMode},
+    quotient::RMFundamentalDomain,
+    ray_marching_material::{
+        prepare_scene, PreparedRMCamera, PreparedRMScene, RMCamera, RMRenderable, RMUploadSet,
+    },
+};
+
+/// Push-outs per step, enough to settle into a corner between two surfaces.
+pub const CONTACT_ITERATIONS: u32 = 4;
+
+/// Longer frames are split up so fast bodies don't tunnel through thin shapes.
+pub const MAX_TIME_STEP: f32 = 1.0 / 60.0;
+
+pub struct RMPhysicsPlugin;
+
+impl Plugin for RMPhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RMCameraBody>()
+            .init_resource::<RMColliders>()
+            .add_systems(
+                PostUpdate,
+                (
+                    // Colliders follow whatever moved or was reparented in `Update` this frame.
+                    propagate_hyp_transforms,
+                    update_colliders,
+                    step_camera
+                        .run_if(not(camera_path_playing))
+                        .run_if(not(navigating(RMNavigationMode::Orbit))),
+                    step_bodies,
+                )
+                    .chain()
+                    .in_set(RMPhysicsSet)
+                    .before(RMUploadSet),
+            );
+    }
+}
+
+/// Systems moving the camera and rigid bodies. Anything placing entities relative to them
+/// should run after it.
+#[derive(Debug, Clone, Copy, SystemSet, PartialEq, Eq, Hash)]
+pub struct RMPhysicsSet;
+
+/// A sphere of `radius` moved by physics. The entity must not have a parent, its shape is drawn
+/// as usual but only the sphere collides.
+#[derive(Component, Debug, Clone)]
+#[require(HypTransform)]
+pub struct RMRigidBody {
+    /// Distance per second along the local `right`, `up` and `forward` axes.
+    pub velocity: Vec3,
+    pub radius: f32,
+    /// Acceleration towards the floor, only applied when there is one.
+    pub gravity: f32,
+    /// Fraction of the speed into a surface kept after bouncing off it.
+    pub restitution: f32,
+    /// Coulomb friction: speed along a surface lost per unit of speed absorbed into it.
+    pub friction: f32,
+    /// Resting on something below it after the last step.
+    pub grounded: bool,
+}
+
+impl Default for RMRigidBody {
+    fn default() -> Self {
+        Self {
+            velocity: Vec3::ZERO,
+            radius: 0.1,
+            gravity: 0.2,
+            restitution: 0.3,
+            friction: 0.5,
+            grounded: false,
+        }
+    }
+}
+
+/// The camera's body. Walking sets its velocity directly, so it has no friction or bounce.
+#[derive(Resource, Debug, Clone)]
+pub struct RMCameraBody(pub RMRigidBody);
+
+impl Default for RMCameraBody {
+    fn default() -> Self {
+        Self(RMRigidBody {
+            restitution: 0.0,
+            friction: 0.0,
+            ..default()
+        })
+    }
+}
+
+/// Everything bodies collide with besides each other: the floor, the honeycomb and every
+/// renderable that isn't a body itself.
+#[derive(Resource, Debug, Clone)]
+pub struct RMColliders {
+    pub camera: PreparedRMCamera,
+    pub scene: PreparedRMScene,
+}
+
+impl Default for RMColliders {
+    fn default() -> Self {
+        Self {
+            camera: RMCamera::default().into(),
+            scene: PreparedRMScene::default(),
+        }
+    }
+}
+
+impl RMColliders {
+    pub fn new<'a>(
+        rm_camera: &RMCamera,
+        lighting: &RMLighting,
+        honeycomb: &RMHoneycomb,
+        domain: &RMFundamentalDomain,
+        geometry: Geometry,
+        renderables: impl Iterator<Item = (&'a HypTransform, &'a RMRenderable)>,
+    ) -> Self {
+        Self {
+            camera: rm_camera.clone().into(),
+            scene: prepare_scene(lighting, honeycomb, domain, geometry, renderables),
+        }
+    }
+
+    pub fn distance(&self, p: Vec4) -> f32 {
+        scene_sdf(&self.camera, &self.scene, p).distance
+    }
+
+    /// Unit gradient of `distance`, a tangent vector at p.
+    pub fn normal(&self, p: Vec4) -> Vec4 {
+        estimate_normal(&self.camera, &self.scene, p)
+    }
+
+    /// Whether there is a floor pulling bodies down, quotients have none.
+    pub fn has_floor(&self) -> bool {
+        self.scene.quotient.face_count == 0
+    }
+}
+
+/// Advances `body` by `dt` seconds: gravity, geodesic motion, then contacts with `colliders`.
+pub fn step_body(
+    body: &mut RMRigidBody,
+    transform: &mut HypTransform,
+    colliders: &RMColliders,
+    dt: f32,
+) {
+    let geometry = colliders.scene.geometry;
+    let steps = (dt / MAX_TIME_STEP).ceil().max(1.0);
+    let dt = dt / steps;
+
+    for _ in 0..steps as u32 {
+        if colliders.has_floor() {
+            let up = transform.to_local_in(geometry, geometry.up(transform.translation));
+            body.velocity -= body.gravity * dt * up;
+        }
+
+        let speed = body.velocity.length();
+        if speed > 0.0 {
+            transform.translate_in(geometry, body.velocity, speed * dt);
+        }
+
+        resolve_contacts(body, transform, colliders);
+    }
+}
+
+fn resolve_contacts(body: &mut RMRigidBody, transform: &mut HypTransform, colliders: &RMColliders) {
+    let geometry = colliders.scene.geometry;
+    body.grounded = false;
+
+    for _ in 0..CONTACT_ITERATIONS {
+        let p = transform.translation;
+        let depth = body.radius - colliders.distance(p);
+        if depth <= 0.0 {
+            break;
+        }
+
+        let normal = transform.to_local_in(geometry, colliders.normal(p)).normalize_or_zero();
+        if normal == Vec3::ZERO {
+            break;
+        }
+        transform.translate_in(geometry, normal, depth);
+
+        let into = body.velocity.dot(normal);
+        if into < 0.0 {
+            let along = body.velocity - into * normal;
+            let slowed = (along.length() + body.friction * into).max(0.0);
+            body.velocity = along.normalize_or_zero() * slowed - body.restitution * into * normal;
+        }
+
+        if colliders.has_floor() {
+            let up = transform.to_local_in(geometry, geometry.up(transform.translation));
+            body.grounded |= normal.dot(up) > 0.5;
+        }
+    }
+}
+
+/// Separates two overlapping bodies of equal mass and bounces them off each other.
+pub fn collide_bodies(
+    geometry: Geometry,
+    (a, a_transform): (&mut RMRigidBody, &mut HypTransform),
+    (b, b_transform): (&mut RMRigidBody, &mut HypTransform),
+) {
+    let (p, q) = (a_transform.translation, b_transform.translation);
+    let overlap = a.radius + b.radius - geometry.distance(p, q);
+    if overlap <= 0.0 {
+        return;
+    }
+
+    // Each body's direction to the other, in its own tangent space.
+    let a_normal = a_transform.to_local_in(geometry, geometry.direction(p, q)).normalize_or_zero();
+    let b_normal = b_transform.to_local_in(geometry, geometry.direction(q, p)).normalize_or_zero();
+    a_transform.translate_in(geometry, -a_normal, 0.5 * overlap);
+    b_transform.translate_in(geometry, -b_normal, 0.5 * overlap);
+
+    let closing = a.velocity.dot(a_normal) + b.velocity.dot(b_normal);
+    if closing > 0.0 {
+        let impulse = 0.5 * (1.0 + a.restitution.min(b.restitution)) * closing;
+        a.velocity -= impulse * a_normal;
+        b.velocity -= impulse * b_normal;
+    }
+}
+
+// Bodies are handled by `collide_bodies`, and the camera's own markers would only get in its way.
+type ColliderFilter = (Without<RMRigidBody>, Without<CameraGizmo>);
+
+/// Rebuilds the colliders only when the scene they are made from changes, the camera's settings
+/// are cheap to keep up to date.
+fn update_colliders(
+    mut colliders: ResMut<RMColliders>,
+    rm_camera: Res<RMCamera>,
+    lighting: Res<RMLighting>,
+    honeycomb: Res<RMHoneycomb>,
+    domain: Res<RMFundamentalDomain>,
+    geometry: Res<Geometry>,
+    renderables: Query<(&HypGlobalTransform, &RMRenderable), ColliderFilter>,
+    changed: Query<(), (ColliderFilter, Or<(Changed<HypGlobalTransform>, Changed<RMRenderable>)>)>,
+    mut removed: RemovedComponents<RMRenderable>,
+) {
+    let rebuild = !changed.is_empty()
+        || removed.read().count() > 0
+        || lighting.is_changed()
+        || honeycomb.is_changed()
+        || domain.is_changed()
+        || geometry.is_changed();
+    if !rebuild {
+        if rm_camera.is_changed() {
+            colliders.camera = rm_camera.clone().into();
+        }
+        return;
+    }
+
+    *colliders = RMColliders::new(
+        &rm_camera,
+        &lighting,
+        &honeycomb,
+        &domain,
+        *geometry,
+        renderables.iter().map(|(global, renderable)| (global.transform(), renderable)),
+    );
+}
+
+fn step_camera(
+    time: Res<Time>,
+    colliders: Res<RMColliders>,
+    navigation: Res<RMNavigation>,
+    mut rm_camera: ResMut<RMCamera>,
+    mut camera_body: ResMut<RMCameraBody>,
+) {
+    let geometry = colliders.scene.geometry;
+    let walking = navigation.mode == RMNavigationMode::Walk;
+
+    // Only walking falls, the body's own gravity is kept for when it walks again.
+    let mut body = camera_body.0.clone();
+    if !walking {
+        body.gravity = 0.0;
+    }
+    step_body(&mut body, &mut rm_camera.transform, &colliders, time.delta_secs());
+    camera_body.0 = RMRigidBody {
+        gravity: camera_body.0.gravity,
+        ..body
+    };
+
+    // Stay upright on the floor, without one any way up will do.
+    if walking && colliders.has_floor() {
+        let up = geometry.up(rm_camera.transform.translation);
+        rm_camera.transform.set_up_in(geometry, up);
+    }
+}
+
+fn step_bodies(
+    time: Res<Time>,
+    colliders: Res<RMColliders>,
+    domain: Res<RMFundamentalDomain>,
+    mut rm_camera: ResMut<RMCamera>,
+    mut camera_body: ResMut<RMCameraBody>,
+    mut bodies: Query<(&mut RMRigidBody, &mut HypTransform)>,
+) {
+    let geometry = colliders.scene.geometry;
+
+    for (mut body, mut transform) in bodies.iter_mut() {
+        step_body(&mut body, &mut transform, &colliders, time.delta_secs());
+        domain.wrap(&mut transform);
+    }
+
+    let mut pairs = bodies.iter_combinations_mut();
+    while let Some([(mut a, mut a_transform), (mut b, mut b_transform)]) = pairs.fetch_next() {
+        collide_bodies(geometry, (&mut *a, &mut *a_transform), (&mut *b, &mut *b_transform));
+    }
+
+    // The camera shoves bodies aside, but walking overrides whatever they do to it.
+    let rm_camera = &mut *rm_camera;
+    for (mut body, mut transform) in bodies.iter_mut() {
+        collide_bodies(
+            geometry,
+            (&mut camera_body.0, &mut rm_camera.transform),
+            (&mut *body, &mut *transform),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::ray_marching_material::RMMaterial;
+
+    fn colliders(geometry: Geometry) -> RMColliders {
+        RMColliders::new(
+            &RMCamera::default(),
+            &RMLighting::default(),
+            &RMHoneycomb::default(),
+            &RMFundamentalDomain::default(),
+            geometry,
+            std::iter::empty(),
+        )
+    }
+
+    fn above_floor(geometry: Geometry, height: f32) -> HypTransform {
+        HypTransform::default()
+            .translate_in(geometry, Vec3::Y, height)
+            .clone()
+    }
+
+    #[test]
+    fn test_falling_body_comes_to_rest() {
+        for geometry in Geometry::ALL {
+            let colliders = colliders(geometry);
+            let mut transform = above_floor(geometry, 0.5);
+            let mut body = RMRigidBody {
+                gravity: 1.0,
+                restitution: 0.0,
+                ..default()
+            };
+
+            for _ in 0..200 {
+                step_body(&mut body, &mut transform, &colliders, 1.0 / 60.0);
+            }
+
+            let height = geometry.floor_distance(transform.translation);
+            assert!((height - body.radius).abs() < 0.01, "{geometry:?}: {height}");
+            assert!(body.velocity.length() < 0.05, "{geometry:?}: {}", body.velocity);
+            assert!(body.grounded, "{geometry:?}");
+        }
+    }
+
+    #[test]
+    fn test_elastic_bounce() {
+        let colliders = colliders(Geometry::Hyperbolic);
+        let mut transform = above_floor(Geometry::Hyperbolic, 0.3);
+        let mut body = RMRigidBody {
+            velocity: -Vec3::Y,
+            gravity: 0.0,
+            restitution: 1.0,
+            friction: 0.0,
+            ..default()
+        };
+
+        for _ in 0..10 {
+            step_body(&mut body, &mut transform, &colliders, 0.05);
+        }
+
+        assert!((body.velocity.y - 1.0).abs() < 0.05, "{}", body.velocity);
+    }
+
+    #[test]
+    fn test_friction_slows_sliding() {
+        let colliders = colliders(Geometry::Euclidean);
+        let mut transform = above_floor(Geometry::Euclidean, 0.1);
+        let mut body = RMRigidBody {
+            velocity: Vec3::X,
+            gravity: 1.0,
+            friction: 0.5,
+            // Bouncing would lift it off the floor for part of every step.
+            restitution: 0.0,
+            ..default()
+        };
+
+        for _ in 0..60 {
+            step_body(&mut body, &mut transform, &colliders, 1.0 / 60.0);
+        }
+
+        // Losing 0.5 * g per second.
+        assert!((body.velocity.x - 0.5).abs() < 0.05, "{}", body.velocity);
+    }
+
+    #[test]
+    fn test_bodies_bounce_apart() {
+        let geometry = Geometry::Euclidean;
+        let mut a_transform = HypTransform::default()
+            .translate_in(geometry, -Vec3::X, 0.09)
+            .clone();
+        let mut b_transform = HypTransform::default()
+            .translate_in(geometry, Vec3::X, 0.09)
+            .clone();
+        let mut a = RMRigidBody { velocity: Vec3::X, restitution: 1.0, ..default() };
+        let mut b = RMRigidBody { velocity: -Vec3::X, restitution: 1.0, ..default() };
+
+        collide_bodies(geometry, (&mut a, &mut a_transform), (&mut b, &mut b_transform));
+
+        assert!((a.velocity + Vec3::X).length() < 1e-4, "{}", a.velocity);
+        assert!((b.velocity - Vec3::X).length() < 1e-4, "{}", b.velocity);
+        let gap = geometry.distance(a_transform.translation, b_transform.translation);
+        assert!((gap - a.radius - b.radius).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_colliders_follow_moved_shapes() {
+        let geometry = Geometry::Hyperbolic;
+        let mut world = World::new();
+        world.init_resource::<RMColliders>();
+        world.init_resource::<RMCamera>();
+        world.init_resource::<RMLighting>();
+        world.init_resource::<RMHoneycomb>();
+        world.init_resource::<RMFundamentalDomain>();
+        world.insert_resource(geometry);
+
+        let at = |distance| above_floor(geometry, 1.0).translate_in(geometry, Vec3::Z, distance).clone();
+        let sphere = world.spawn((RMRenderable::sphere(0.2, RMMaterial::Flat(LinearRgba::WHITE)), at(1.0))).id();
+        let update = |world: &mut World| {
+            world.run_system_once(propagate_hyp_transforms).unwrap();
+            world.run_system_once(update_colliders).unwrap();
+            world.resource::<RMColliders>().distance(at(1.0).translation)
+        };
+
+        assert!((update(&mut world) + 0.2).abs() < 1e-4);
+
+        *world.get_mut::<HypTransform>(sphere).unwrap() = at(3.0);
+        assert!(update(&mut world) > 0.5);
+    }
+}
@@ -0,0 +1,258 @@
+// Selecting entities by clicking on them. Rays are built and marched exactly as the fragment
+// shader does, through the CPU mirror in `cpu_renderer`, so whatever is drawn under the cursor
+// is what gets hit, wrapping through quotient faces included.
+
+use bevy::{prelude::*, window::CursorGrabMode};
+
+use crate::{
+    cpu_renderer::{camera_ray, march, screen_to_uv, sdf::shape_sdf, RayMarch},
+    demo::CameraGizmo,
+    geometries::{Geometry, HypTransform},
+    hierarchy::HypGlobalTransform,
+    input::{RMAction, RMActions},
+    honeycomb::RMHoneycomb,
+    lighting::RMLighting,
+    model_view::first_person,
+    quotient::RMFundamentalDomain,
+    ray_marching_material::{
+        prepare_scene, PreparedRMCamera, PreparedRMScene, PreparedRMShape, RMCamera, RMRenderable,
+    },
+};
+
+pub struct RMPickingPlugin;
+
+impl Plugin for RMPickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RMSelection>()
+            // Rays are cast from the camera, not from wherever a model view is seen from.
+            .add_systems(Update, select_under_cursor.in_set(RMPickingSet).run_if(first_person));
+    }
+}
+
+/// Systems turning clicks into selections, so they can be switched off while the cursor is
+/// busy elsewhere.
+#[derive(Debug, Clone, Copy, SystemSet, PartialEq, Eq, Hash)]
+pub struct RMPickingSet;
+
+/// Renderables clicks can select. The camera's own markers sit right in front of it, so they
+/// are left out, as they are for physics.
+pub type PickableFilter = Without<CameraGizmo>;
+
+/// The renderable picked last, if any.
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct RMSelection(pub Option<Entity>);
+
+/// Where a ray first hit the scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RMRayHit {
+    /// The renderable hit, `None` for the floor and the honeycomb.
+    pub entity: Option<Entity>,
+    /// The point hit, on the hyperboloid in H^3 and the unit sphere in S^3.
+    pub point: Vec4,
+    /// Distance travelled along the ray, counting every trip through a quotient face.
+    pub distance: f32,
+}
+
+/// A snapshot of the scene to cast rays into, taken the same way `update_material` prepares it
+/// for the GPU.
+#[derive(Debug, Clone)]
+pub struct RMRayCaster {
+    pub camera: PreparedRMCamera,
+    pub scene: PreparedRMScene,
+    targets: Vec<(Entity, PreparedRMShape)>,
+}
+
+impl RMRayCaster {
+    pub fn new<'a>(
+        rm_camera: &RMCamera,
+        lighting: &RMLighting,
+        honeycomb: &RMHoneycomb,
+        domain: &RMFundamentalDomain,
+        geometry: Geometry,
+        renderables: impl Iterator<Item = (Entity, &'a HypTransform, &'a RMRenderable)>,
+    ) -> Self {
+        let renderables: Vec<_> = renderables.filter(|(_, _, renderable)| renderable.visible).collect();
+        let targets = renderables.iter()
+            .map(|&(entity, transform, renderable)| {
+                (entity, PreparedRMShape::new(transform, &renderable.shape, 0))
+            })
+            .collect();
+
+        Self {
+            camera: rm_camera.into(),
+            scene: prepare_scene(
+                lighting,
+                honeycomb,
+                domain,
+                geometry,
+                renderables.iter().map(|&(_, transform, renderable)| (transform, renderable)),
+            ),
+            targets,
+        }
+    }
+
+    /// Casts the ray through `uv_coords`, see `cpu_renderer::screen_to_uv`.
+    pub fn cast_uv(&self, uv_coords: Vec2) -> Option<RMRayHit> {
+        let (origin, direction) = camera_ray(&self.camera, self.scene.geometry, uv_coords)?;
+        self.cast(origin, direction)
+    }
+
+    /// Marches from `origin` along the unit tangent `direction`. Rays that leave the scene or
+    /// run out of iterations hit nothing.
+    pub fn cast(&self, origin: Vec4, direction: Vec4) -> Option<RMRayHit> {
+        let RayMarch::Hit(hit) = march(&self.camera, &self.scene, origin, direction) else {
+            return None;
+        };
+
+        // The march only knows the distance to the nearest surface, so find whose it was.
+        let geometry = self.scene.geometry;
+        let entity = self.targets.iter()
+            .map(|(entity, shape)| (*entity, shape_sdf(&self.camera, geometry, shape, hit.position)))
+            .filter(|&(_, distance)| distance < self.camera.min_dist)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(entity, _)| entity);
+
+        Some(RMRayHit {
+            entity,
+            point: hit.position,
+            distance: hit.distance,
+        })
+    }
+}
+
+/// Picking, right click by default, selects the renderable under the cursor, or in the middle of
+/// the view while the cursor is grabbed. Clicking on nothing clears the selection.
+fn select_under_cursor(
+    actions: Res<RMActions>,
+    windows: Query<&Window>,
+    rm_camera: Res<RMCamera>,
+    lighting: Res<RMLighting>,
+    honeycomb: Res<RMHoneycomb>,
+    domain: Res<RMFundamentalDomain>,
+    geometry: Res<Geometry>,
+    renderables: Query<(Entity, &HypGlobalTransform, &RMRenderable), PickableFilter>,
+    mut selection: ResMut<RMSelection>,
+) {
+    if !actions.just_pressed(RMAction::Pick) {
+        return;
+    }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let cursor = if window.cursor_options.grab_mode == CursorGrabMode::Locked {
+        window.size() / 2.0
+    } else {
+        let Some(cursor) = window.cursor_position() else {
+            return;
+        };
+        cursor
+    };
+
+    let caster = RMRayCaster::new(
+        &rm_camera,
+        &lighting,
+        &honeycomb,
+        &domain,
+        *geometry,
+        renderables.iter().map(|(entity, global, renderable)| (entity, global.transform(), renderable)),
+    );
+    let uv_coords = screen_to_uv(&caster.camera, cursor, window.size());
+    let hit = caster.cast_uv(uv_coords);
+
+    selection.set_if_neq(RMSelection(hit.and_then(|hit| hit.entity)));
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{geometries::hyp_dot, ray_marching_material::RMMaterial};
+
+    fn raised_camera(geometry: Geometry) -> RMCamera {
+        let mut cam = RMCamera::default();
+        cam.transform.translate_in(geometry, Vec3::Y, 0.5);
+        cam
+    }
+
+    fn caster(geometry: Geometry, cam: &RMCamera, spheres: &[(Entity, HypTransform)]) -> RMRayCaster {
+        let renderable = RMRenderable::sphere(0.3, RMMaterial::Flat(LinearRgba::WHITE));
+        RMRayCaster::new(
+            cam,
+            &RMLighting::default(),
+            &RMHoneycomb::default(),
+            &RMFundamentalDomain::default(),
+            geometry,
+            spheres.iter().map(|(entity, transform)| (*entity, transform, &renderable)),
+        )
+    }
+
+    #[test]
+    fn test_centre_of_view_picks_sphere_ahead() {
+        for geometry in Geometry::ALL {
+            let cam = raised_camera(geometry);
+            let near = Entity::from_raw(1);
+            let far = Entity::from_raw(2);
+            let spheres = [
+                (far, cam.transform.clone().translate_in(geometry, Vec3::Z, 2.0).clone()),
+                (near, cam.transform.clone().translate_in(geometry, Vec3::Z, 1.0).clone()),
+            ];
+
+            let hit = caster(geometry, &cam, &spheres).cast_uv(Vec2::ZERO).unwrap();
+
+            assert_eq!(hit.entity, Some(near), "{geometry:?}");
+            assert!((hit.distance - 0.7).abs() < 1e-3, "{geometry:?}: {}", hit.distance);
+        }
+    }
+
+    #[test]
+    fn test_floor_hits_no_entity() {
+        let geometry = Geometry::Hyperbolic;
+        let cam = raised_camera(geometry);
+        let spheres = [(Entity::from_raw(1), cam.transform.clone().translate_in(geometry, Vec3::Z, 1.0).clone())];
+        let caster = caster(geometry, &cam, &spheres);
+
+        let hit = caster.cast(caster.camera.position, -1.0 * caster.camera.up).unwrap();
+
+        assert_eq!(hit.entity, None);
+        assert!((hit.distance - 0.5).abs() < 1e-3, "{}", hit.distance);
+        assert!((hyp_dot(hit.point, hit.point) + 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_camera_gizmos_are_not_picked() {
+        let geometry = Geometry::Hyperbolic;
+        let cam = raised_camera(geometry);
+        let sphere = || RMRenderable::sphere(0.1, RMMaterial::Flat(LinearRgba::WHITE));
+        let ahead = |distance| HypGlobalTransform::from(cam.transform.clone().translate_in(geometry, Vec3::Z, distance).clone());
+
+        let mut world = World::new();
+        world.spawn((CameraGizmo { offset: 0.5 * Vec3::Z }, ahead(0.5), sphere()));
+        let target = world.spawn((ahead(1.5), sphere())).id();
+
+        let hit = world
+            .run_system_once(move |renderables: Query<(Entity, &HypGlobalTransform, &RMRenderable), PickableFilter>| {
+                RMRayCaster::new(
+                    &cam,
+                    &RMLighting::default(),
+                    &RMHoneycomb::default(),
+                    &RMFundamentalDomain::default(),
+                    geometry,
+                    renderables.iter().map(|(entity, global, renderable)| (entity, global.transform(), renderable)),
+                )
+                .cast_uv(Vec2::ZERO)
+            })
+            .unwrap();
+
+        assert_eq!(hit.and_then(|hit| hit.entity), Some(target));
+    }
+
+    #[test]
+    fn test_screen_centre_is_view_direction() {
+        let camera: PreparedRMCamera = (&RMCamera::default()).into();
+        let size = Vec2::new(640.0, 480.0);
+
+        assert_eq!(screen_to_uv(&camera, size / 2.0, size), Vec2::ZERO);
+        assert!(screen_to_uv(&camera, Vec2::ZERO, size).y > 0.0);
+    }
+}
@@ -0,0 +1,502 @@
+// Closed hyperbolic manifolds: H^3 modulo the group generated by the face pairings of a
+// fundamental polyhedron. A ray or the camera leaving the polyhedron through a face is carried
+// back in through its partner, so only the polyhedron and its contents are ever rendered.
+
+use std::f32::consts::PI;
+
+use bevy::{prelude::*, render::render_resource::ShaderType};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    geometries::{cross4, hyp_dot, hyp_normalize, Geometry, HypTransform, Isometry},
+    honeycomb::Chamber,
+    ray_marching_material::RMCamera,
+};
+
+/// Pairings applied in a row before giving up, points near a vertex can need several.
+pub const QUOTIENT_MAX_WRAPS: u32 = 4;
+
+const THRESH: f32 = 1e-3;
+
+/// The space the scene lives in, read from the scene file. Quotients only apply in H^3.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum RMQuotient {
+    #[default]
+    None,
+    /// A cell of the {5,3,5} honeycomb with each face glued to the opposite one by a 3/10 turn.
+    SeifertWeber,
+    Polyhedron(RMPolyhedron),
+}
+
+impl RMQuotient {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RMQuotient::None => "None",
+            RMQuotient::SeifertWeber => "Seifert-Weber",
+            RMQuotient::Polyhedron(_) => "Polyhedron",
+        }
+    }
+}
+
+/// A convex polyhedron with its faces glued in pairs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RMPolyhedron {
+    /// Klein model coordinates, inside the unit ball.
+    pub vertices: Vec<Vec3>,
+    /// Vertex indices around each face, counter-clockwise seen from outside.
+    pub faces: Vec<Vec<usize>>,
+    /// Every face must appear in exactly one pairing.
+    pub pairings: Vec<RMFacePairing>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RMFacePairing {
+    pub face: usize,
+    pub to: usize,
+    /// Vertex `k` of `face` is glued to vertex `offset - k` of `to`, modulo the vertex count.
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum RMPolyhedronError {
+    #[error("vertex {0} is not inside the unit ball")]
+    VertexOutside(usize),
+    #[error("face {0} has fewer than 3 vertices or a vertex that doesn't exist")]
+    InvalidFace(usize),
+    #[error("face {0} is not planar")]
+    NonPlanar(usize),
+    #[error("face {0} is not paired")]
+    Unpaired(usize),
+    #[error("face {0} is paired more than once")]
+    PairedTwice(usize),
+    #[error("faces {0} and {1} are not congruent")]
+    NotCongruent(usize, usize),
+}
+
+/// A face of the fundamental polyhedron, see `Chamber` for how planes are stored. `pairing` is
+/// the Lorentz matrix taking points just outside this face to just inside its partner.
+#[derive(ShaderType, Debug, Clone, PartialEq)]
+pub struct RMQuotientFace {
+    pub plane: Vec4,
+    pub pairing: Mat4,
+}
+
+/// The polyhedron for the current `RMQuotient`, no faces meaning the whole of space.
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct RMFundamentalDomain {
+    pub faces: Vec<RMQuotientFace>,
+}
+
+impl RMFundamentalDomain {
+    pub fn new(quotient: &RMQuotient, geometry: Geometry) -> Result<Self, RMPolyhedronError> {
+        match quotient {
+            _ if geometry != Geometry::Hyperbolic => Ok(Self::default()),
+            RMQuotient::None => Ok(Self::default()),
+            RMQuotient::SeifertWeber => Ok(Self::seifert_weber()),
+            RMQuotient::Polyhedron(polyhedron) => Self::from_polyhedron(polyhedron),
+        }
+    }
+
+    pub fn seifert_weber() -> Self {
+        let chamber = Chamber::new(Geometry::Hyperbolic, [5, 3, 5]).expect("{5,3,5} tiles H^3");
+
+        // The cell's faces are the images of the last mirror under the other three.
+        let mut planes = vec![chamber.mirrors[3]];
+        let mut i = 0;
+        while i < planes.len() {
+            for &mirror in &chamber.mirrors[..3] {
+                let plane = Geometry::Hyperbolic.reflect(planes[i], mirror);
+                if planes.iter().all(|p| p.distance(plane) > THRESH) {
+                    planes.push(plane);
+                }
+            }
+            i += 1;
+        }
+
+        // Translating across the cell lands on the opposite face 36° out of step with its
+        // vertices, the extra 72° makes up the 3/10 turn.
+        let faces = planes.into_iter()
+            .map(|plane| {
+                let axis = plane.truncate().normalize();
+                let inradius = plane.w.asinh();
+                let pairing = Isometry::from_rotation(Quat::from_axis_angle(axis, 0.6 * PI))
+                    * Isometry::from_boost(-axis, 2.0 * inradius);
+                RMQuotientFace {
+                    plane,
+                    pairing: pairing.0,
+                }
+            })
+            .collect();
+
+        Self { faces }
+    }
+
+    pub fn from_polyhedron(polyhedron: &RMPolyhedron) -> Result<Self, RMPolyhedronError> {
+        let vertices = polyhedron.vertices.iter()
+            .enumerate()
+            .map(|(i, k)| {
+                let r2 = k.length_squared();
+                if r2 < 1.0 {
+                    Ok(k.extend(1.0) / (1.0 - r2).sqrt())
+                } else {
+                    Err(RMPolyhedronError::VertexOutside(i))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let centre = hyp_normalize(vertices.iter().copied().sum());
+
+        let mut faces = Vec::with_capacity(polyhedron.faces.len());
+        for (i, face) in polyhedron.faces.iter().enumerate() {
+            if face.len() < 3 || face.iter().any(|&v| v >= vertices.len()) {
+                return Err(RMPolyhedronError::InvalidFace(i));
+            }
+            let corners: Vec<Vec4> = face.iter().map(|&v| vertices[v]).collect();
+
+            // Orthogonal to the corners under hyp_dot, the covector flips the sign of w.
+            let normal = cross4(corners[0], corners[1], corners[2]);
+            let normal = normal.with_w(-normal.w);
+            let normal = normal / hyp_dot(normal, normal).sqrt();
+            let plane = if hyp_dot(centre, normal) > 0.0 { -normal } else { normal };
+
+            if corners.iter().any(|&c| hyp_dot(c, plane).abs() > THRESH) {
+                return Err(RMPolyhedronError::NonPlanar(i));
+            }
+            faces.push((plane, corners));
+        }
+
+        let mut pairings = vec![None; faces.len()];
+        for pairing in &polyhedron.pairings {
+            let (a, b) = (pairing.face, pairing.to);
+            for face in [a, b] {
+                if face >= faces.len() {
+                    return Err(RMPolyhedronError::InvalidFace(face));
+                }
+                if pairings[face].is_some() || a == b {
+                    return Err(RMPolyhedronError::PairedTwice(face));
+                }
+            }
+
+            let g = face_pairing(&faces[a], &faces[b], pairing.offset)
+                .ok_or(RMPolyhedronError::NotCongruent(a, b))?;
+            pairings[a] = Some(g);
+            pairings[b] = Some(Isometry(g).inverse().0);
+        }
+
+        let faces = faces.into_iter()
+            .zip(pairings)
+            .enumerate()
+            .map(|(i, ((plane, _), pairing))| {
+                let pairing = pairing.ok_or(RMPolyhedronError::Unpaired(i))?;
+                Ok(RMQuotientFace { plane, pairing })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { faces })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+
+    /// Carries `transform` back into the polyhedron, returning whether it had left.
+    pub fn wrap(&self, transform: &mut HypTransform) -> bool {
+        let mut wrapped = false;
+        for _ in 0..QUOTIENT_MAX_WRAPS {
+            let Some(face) = exit_face(&self.faces, transform.translation) else {
+                break;
+            };
+            transform.apply_isometry(&face.pairing);
+            wrapped = true;
+        }
+        // Looping around the manifold applies pairings indefinitely, so drift would build up.
+        if wrapped {
+            transform.orthonormalize();
+        }
+        wrapped
+    }
+}
+
+// The isometry taking face a to face b, with vertex k of a landing on vertex `offset - k` of b
+// and the outside of a on the inside of b.
+fn face_pairing(a: &(Vec4, Vec<Vec4>), b: &(Vec4, Vec<Vec4>), offset: usize) -> Option<Mat4> {
+    let ((plane_a, corners_a), (plane_b, corners_b)) = (a, b);
+    let n = corners_a.len();
+    if corners_b.len() != n {
+        return None;
+    }
+    let target = |k: usize| corners_b[(offset + n - k % n) % n];
+
+    let from = Mat4::from_cols(corners_a[0], corners_a[1], corners_a[2], *plane_a);
+    let to = Mat4::from_cols(target(0), target(1), target(2), -*plane_b);
+    let g = to * from.inverse();
+
+    let congruent = (0..n).all(|k| (g * corners_a[k]).distance(target(k)) < THRESH);
+    congruent.then_some(g)
+}
+
+/// The face p lies farthest outside of, if any.
+pub fn exit_face(faces: &[RMQuotientFace], p: Vec4) -> Option<&RMQuotientFace> {
+    faces.iter()
+        .map(|face| (hyp_dot(p, face.plane), face))
+        .filter(|(s, _)| *s > 0.0)
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, face)| face)
+}
+
+/// Distance along the geodesic from p with unit velocity v until it leaves the polyhedron.
+pub fn exit_distance(faces: &[RMQuotientFace], p: Vec4, v: Vec4, max_dist: f32) -> f32 {
+    faces.iter()
+        .map(|face| {
+            // Along the geodesic the plane's dot product is a cosh(t) + b sinh(t).
+            let a = hyp_dot(p, face.plane);
+            let b = hyp_dot(v, face.plane);
+            if b > -a {
+                (-a / b).atanh().max(0.0)
+            } else {
+                max_dist
+            }
+        })
+        .fold(max_dist, f32::min)
+}
+
+/// See `Quotient` in the shader. Storage buffers can't be empty, so `face_count` can be less
+/// than the length of `faces`.
+#[derive(ShaderType, Clone, Debug)]
+pub struct PreparedRMQuotient {
+    pub face_count: u32,
+    #[size(runtime)]
+    pub faces: Vec<RMQuotientFace>,
+}
+
+impl Default for PreparedRMQuotient {
+    fn default() -> Self {
+        Self {
+            face_count: 0,
+            faces: vec![RMQuotientFace {
+                plane: Vec4::ZERO,
+                pairing: Mat4::IDENTITY,
+            }],
+        }
+    }
+}
+
+impl PreparedRMQuotient {
+    pub fn faces(&self) -> &[RMQuotientFace] {
+        &self.faces[..self.face_count as usize]
+    }
+}
+
+impl From<&RMFundamentalDomain> for PreparedRMQuotient {
+    fn from(domain: &RMFundamentalDomain) -> Self {
+        if domain.is_empty() {
+            return Self::default();
+        }
+        Self {
+            face_count: domain.faces.len() as u32,
+            faces: domain.faces.clone(),
+        }
+    }
+}
+
+/// Rebuilds the fundamental domain when the quotient or geometry changes.
+pub fn update_fundamental_domain(
+    quotient: Res<RMQuotient>,
+    geometry: Res<Geometry>,
+    mut domain: ResMut<RMFundamentalDomain>,
+) {
+    if !quotient.is_changed() && !geometry.is_changed() {
+        return;
+    }
+    match RMFundamentalDomain::new(&quotient, *geometry) {
+        Ok(new) => domain.set_if_neq(new),
+        Err(err) => {
+            error!("invalid quotient polyhedron: {err}");
+            domain.set_if_neq(RMFundamentalDomain::default())
+        }
+    };
+}
+
+pub fn wrap_camera(domain: Res<RMFundamentalDomain>, mut rm_camera: ResMut<RMCamera>) {
+    if !domain.is_empty() && domain.wrap(&mut rm_camera.bypass_change_detection().transform) {
+        rm_camera.set_changed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometries::ORIGIN;
+
+    fn is_lorentz(g: &Mat4) -> bool {
+        Isometry(*g).is_lorentz(1e-3)
+    }
+
+    // Cube of the {4,3,5} honeycomb, opposite faces glued by translation.
+    fn cube() -> RMPolyhedron {
+        let k = 0.4857;
+        let corner = |x: f32, y: f32, z: f32| Vec3::new(x, y, z) * k;
+        let vertices = (0..8)
+            .map(|i| corner(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { -1.0 } else { 1.0 },
+            ))
+            .collect();
+
+        RMPolyhedron {
+            vertices,
+            faces: vec![
+                vec![1, 3, 7, 5],
+                vec![0, 4, 6, 2],
+                vec![2, 6, 7, 3],
+                vec![0, 1, 5, 4],
+                vec![4, 5, 7, 6],
+                vec![0, 2, 3, 1],
+            ],
+            pairings: vec![
+                RMFacePairing { face: 0, to: 1, offset: 0 },
+                RMFacePairing { face: 2, to: 3, offset: 0 },
+                RMFacePairing { face: 4, to: 5, offset: 0 },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_seifert_weber_pairings() {
+        let domain = RMFundamentalDomain::seifert_weber();
+        assert_eq!(domain.faces.len(), 12);
+
+        for face in &domain.faces {
+            assert!(is_lorentz(&face.pairing));
+            assert!(hyp_dot(ORIGIN, face.plane) < 0.0);
+
+            // The partner's pairing undoes it, and points just outside land just inside.
+            assert!(domain.faces.iter()
+                .any(|f| (f.pairing * face.pairing).abs_diff_eq(Mat4::IDENTITY, 1e-3)));
+
+            let u = face.plane.truncate().normalize();
+            let outside = hyp_normalize((1.05 * face.plane.w.asinh().tanh() * u).extend(1.0));
+            assert!(exit_face(&domain.faces, outside).is_some());
+            assert!(exit_face(&domain.faces, face.pairing * outside).is_none());
+        }
+    }
+
+    #[test]
+    fn test_seifert_weber_vertices_and_edges() {
+        let domain = RMFundamentalDomain::seifert_weber();
+        let on = |v: Vec4, face: usize| (hyp_dot(v, domain.faces[face].plane) / v.w).abs() < 1e-3;
+
+        // Vertices are where three face planes meet, orthogonal to each under hyp_dot.
+        let mut vertices: Vec<Vec4> = Vec::new();
+        let flip = |p: Vec4| p.with_w(-p.w);
+        for i in 0..12 {
+            for j in i + 1..12 {
+                for k in j + 1..12 {
+                    let planes = [i, j, k].map(|f| flip(domain.faces[f].plane));
+                    let v = cross4(planes[0], planes[1], planes[2]);
+                    if hyp_dot(v, v) >= 0.0 {
+                        continue;
+                    }
+                    let v = hyp_normalize(v * v.w.signum());
+                    let inside = domain.faces.iter().all(|f| hyp_dot(v, f.plane) / v.w < 1e-3);
+                    if inside && vertices.iter().all(|u| u.distance(v) > 1e-2 * v.w) {
+                        vertices.push(v);
+                    }
+                }
+            }
+        }
+        assert_eq!(vertices.len(), 20);
+
+        let vertex_at = |p: Vec4| vertices.iter().position(|v| v.distance(p) < 1e-2 * v.w);
+        let face_vertices = |face: usize| -> Vec<usize> {
+            (0..vertices.len()).filter(|&v| on(vertices[v], face)).collect()
+        };
+        let partner = |face: usize| {
+            (0..12)
+                .find(|&f| (domain.faces[f].pairing * domain.faces[face].pairing).abs_diff_eq(Mat4::IDENTITY, 1e-3))
+                .unwrap()
+        };
+
+        // Each face's vertices land on its partner's.
+        for face in 0..12 {
+            let mut images: Vec<usize> = face_vertices(face).iter()
+                .map(|&v| vertex_at(domain.faces[face].pairing * vertices[v]).expect("lands on a vertex"))
+                .collect();
+            images.sort();
+            assert_eq!(face_vertices(face).len(), 5);
+            assert_eq!(images, face_vertices(partner(face)), "face {face}");
+        }
+
+        // Crossing faces around an edge comes back to it after five 72° wedges, which the 3/10
+        // turn gets right and a 1/10 or 5/10 turn would not.
+        let faces_on = |a: usize, b: usize| -> Vec<usize> {
+            (0..12).filter(|&f| on(vertices[a], f) && on(vertices[b], f)).collect()
+        };
+        for face in 0..12 {
+            let corners = face_vertices(face);
+            for (n, &a) in corners.iter().enumerate() {
+                for &b in &corners[n + 1..] {
+                    if faces_on(a, b).len() != 2 {
+                        continue;
+                    }
+
+                    let start = (a.min(b), a.max(b), face);
+                    let (mut a, mut b, mut face) = start;
+                    let mut wedges = 0;
+                    loop {
+                        let g = domain.faces[face].pairing;
+                        let (ga, gb) = (vertex_at(g * vertices[a]).unwrap(), vertex_at(g * vertices[b]).unwrap());
+                        let entered = partner(face);
+                        face = faces_on(ga, gb).into_iter().find(|&f| f != entered).unwrap();
+                        (a, b) = (ga.min(gb), ga.max(gb));
+                        wedges += 1;
+                        if (a, b, face) == start || wedges > 12 {
+                            break;
+                        }
+                    }
+                    assert_eq!(wedges, 5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_polyhedron_pairings() {
+        let domain = RMFundamentalDomain::from_polyhedron(&cube()).unwrap();
+        assert_eq!(domain.faces.len(), 6);
+        for face in &domain.faces {
+            assert!(is_lorentz(&face.pairing));
+        }
+
+        let mut unpaired = cube();
+        unpaired.pairings.pop();
+        assert_eq!(RMFundamentalDomain::from_polyhedron(&unpaired), Err(RMPolyhedronError::Unpaired(4)));
+
+        let mut outside = cube();
+        outside.vertices[3] = Vec3::ONE;
+        assert_eq!(RMFundamentalDomain::from_polyhedron(&outside), Err(RMPolyhedronError::VertexOutside(3)));
+    }
+
+    #[test]
+    fn test_camera_wraps_into_domain() {
+        let domain = RMFundamentalDomain::seifert_weber();
+        let mut transform = HypTransform::default();
+        transform.translate(Vec3::new(0.3, 1.0, -0.2), 1.5);
+
+        assert!(domain.wrap(&mut transform));
+        assert!(exit_face(&domain.faces, transform.translation).is_none());
+        assert!((hyp_dot(transform.translation, transform.translation) + 1.0).abs() < 1e-3);
+        assert!(hyp_dot(transform.forward, transform.translation).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_exit_distance_reaches_face() {
+        let domain = RMFundamentalDomain::seifert_weber();
+        let face = &domain.faces[0];
+        let v = face.plane.truncate().normalize().extend(0.0);
+
+        let t = exit_distance(&domain.faces, ORIGIN, v, 100.0);
+
+        assert!((t - face.plane.w.asinh()).abs() < 1e-4);
+    }
+}
@@ -1,14 +1,30 @@
-use std::f32::consts::{PI, TAU};
+use std::{collections::HashMap, f32::consts::{PI, TAU}};
 
 // use crate::MandelbulbUniforms;
 use bevy::{
     prelude::*,
     reflect::TypePath,
-    render::{render_resource::{AsBindGroup, ShaderRef, ShaderType}, storage::ShaderStorageBuffer},
-    sprite::{Material2d, Material2dPlugin},
+    render::{
+        mesh::MeshVertexBufferLayoutRef,
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, ShaderRef, ShaderType,
+            SpecializedMeshPipelineError,
+        },
+        storage::ShaderStorageBuffer,
+    },
+    sprite::{Material2d, Material2dKey, Material2dPlugin},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bvh::{self, PreparedRMBvh},
+    geometries::{Geometry, HypTransform},
+    hierarchy::{propagate_hyp_transforms, HypGlobalTransform},
+    honeycomb::{PreparedRMHoneycomb, RMHoneycomb},
+    lighting::{PreparedRMLighting, PreparedRMLights, RMLighting},
+    model_view::{PreparedRMModelView, RMModelView},
+    quotient::{self, PreparedRMQuotient, RMFundamentalDomain, RMQuotient},
 };
-
-use crate::geometries::HypTransform;
 
 pub struct RayMarchingMaterialPlugin;
 
@@ -18,13 +34,38 @@ impl Plugin for RayMarchingMaterialPlugin {
         cam.transform.translate(Vec3::new(0.0, 1.0, 0.0), 0.5);
         println!("{:?}", cam );
         app.add_plugins(Material2dPlugin::<RayMarchingMaterial>::default())
-            .add_systems(PostUpdate, update_material)
+            .add_systems(
+                PostUpdate,
+                (
+                    change_geometry,
+                    propagate_hyp_transforms,
+                    quotient::update_fundamental_domain,
+                    quotient::wrap_camera,
+                    advance_clock,
+                    update_material,
+                )
+                    .chain()
+                    .in_set(RMUploadSet),
+            )
+            .init_resource::<RMClock>()
+            .add_event::<ChangeGeometry>()
+            .init_resource::<Geometry>()
+            .init_resource::<RMLighting>()
+            .init_resource::<RMHoneycomb>()
+            .init_resource::<RMQuotient>()
+            .init_resource::<RMFundamentalDomain>()
+            .init_resource::<RMModelView>()
             .insert_resource(cam);
     }
 }
 
+/// Systems preparing and uploading the scene. Anything moving the camera or entities for the
+/// current frame must run before it.
+#[derive(Debug, Clone, Copy, SystemSet, PartialEq, Eq, Hash)]
+pub struct RMUploadSet;
+
 #[derive(Component)]
-#[require(HypTransform)]
+#[require(HypTransform, HypGlobalTransform)]
 pub struct RMRenderable {
     pub visible: bool,
     pub material: RMMaterial,
@@ -32,14 +73,18 @@ pub struct RMRenderable {
 }
 
 impl RMRenderable {
-    pub fn sphere(radius: f32, material: RMMaterial) -> Self {
+    pub fn new(shape: RMShape, material: RMMaterial) -> Self {
         Self {
             visible: true,
             material,
-            shape: RMShape::Sphere { radius },
+            shape,
         }
     }
 
+    pub fn sphere(radius: f32, material: RMMaterial) -> Self {
+        Self::new(RMShape::Sphere { radius }, material)
+    }
+
     pub fn hide(&mut self) -> &mut Self {
         self.visible = false;
         self
@@ -61,25 +106,278 @@ impl RMRenderable {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Shapes are positioned by the `HypGlobalTransform` of their entity. "Local" axes below refer to
+/// its `right`, `up` and `forward` vectors.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum RMShape {
     Sphere {
         radius: f32,
     },
+    /// Bounded by the geodesic planes perpendicular to each local axis at the given distances.
+    Box {
+        half_extents: Vec3,
+    },
+    /// Points within `radius` of the geodesic along local forward, capped by the planes
+    /// perpendicular to it at `half_length`.
+    Cylinder {
+        radius: f32,
+        half_length: f32,
+    },
+    /// Points within `minor_radius` of the circle of radius `major_radius` around local up.
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    /// Half-space below the totally geodesic plane through the origin with normal local up.
+    Plane,
+    /// Horoball through the origin whose ideal point lies straight down local up.
+    Horosphere,
+    /// Slab of points within `distance` of the totally geodesic plane with normal local up.
+    Equidistant {
+        distance: f32,
+    },
+    /// Evaluated in normal coordinates around the origin, where it is `scale` units across.
+    Mandelbulb {
+        power: f32,
+        iterations: u32,
+        bailout: f32,
+        scale: f32,
+    },
 }
 
-#[derive(Debug, Clone)]
+pub const SHAPE_SPHERE: u32 = 0;
+pub const SHAPE_BOX: u32 = 1;
+pub const SHAPE_CYLINDER: u32 = 2;
+pub const SHAPE_TORUS: u32 = 3;
+pub const SHAPE_PLANE: u32 = 4;
+pub const SHAPE_HOROSPHERE: u32 = 5;
+pub const SHAPE_EQUIDISTANT: u32 = 6;
+pub const SHAPE_MANDELBULB: u32 = 7;
+
+impl RMShape {
+    /// One of each shape, in `kind` order, with parameters sized for a first look.
+    pub const ALL: [RMShape; 8] = [
+        RMShape::Sphere { radius: 0.2 },
+        RMShape::Box { half_extents: Vec3::splat(0.2) },
+        RMShape::Cylinder { radius: 0.1, half_length: 0.3 },
+        RMShape::Torus { major_radius: 0.3, minor_radius: 0.08 },
+        RMShape::Plane,
+        RMShape::Horosphere,
+        RMShape::Equidistant { distance: 0.1 },
+        RMShape::Mandelbulb { power: 8.0, iterations: 16, bailout: 2.0, scale: 0.5 },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RMShape::Sphere { .. } => "Sphere",
+            RMShape::Box { .. } => "Box",
+            RMShape::Cylinder { .. } => "Cylinder",
+            RMShape::Torus { .. } => "Torus",
+            RMShape::Plane => "Plane",
+            RMShape::Horosphere => "Horosphere",
+            RMShape::Equidistant { .. } => "Equidistant",
+            RMShape::Mandelbulb { .. } => "Mandelbulb",
+        }
+    }
+
+    /// Shape id matching the `SHAPE_*` constants in the shader.
+    pub fn kind(&self) -> u32 {
+        match self {
+            RMShape::Sphere { .. } => SHAPE_SPHERE,
+            RMShape::Box { .. } => SHAPE_BOX,
+            RMShape::Cylinder { .. } => SHAPE_CYLINDER,
+            RMShape::Torus { .. } => SHAPE_TORUS,
+            RMShape::Plane => SHAPE_PLANE,
+            RMShape::Horosphere => SHAPE_HOROSPHERE,
+            RMShape::Equidistant { .. } => SHAPE_EQUIDISTANT,
+            RMShape::Mandelbulb { .. } => SHAPE_MANDELBULB,
+        }
+    }
+
+    /// Shape parameters as packed into `PreparedRMShape::params`.
+    pub fn params(&self) -> Vec4 {
+        match *self {
+            RMShape::Sphere { radius } => Vec4::new(radius, 0.0, 0.0, 0.0),
+            RMShape::Box { half_extents } => half_extents.extend(0.0),
+            RMShape::Cylinder { radius, half_length } => Vec4::new(radius, half_length, 0.0, 0.0),
+            RMShape::Torus { major_radius, minor_radius } => Vec4::new(major_radius, minor_radius, 0.0, 0.0),
+            RMShape::Plane | RMShape::Horosphere => Vec4::ZERO,
+            RMShape::Equidistant { distance } => Vec4::new(distance, 0.0, 0.0, 0.0),
+            RMShape::Mandelbulb { power, iterations, bailout, scale } => {
+                Vec4::new(power, iterations as f32, bailout, scale)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum RMMaterial {
     Flat(LinearRgba),
+    Emissive {
+        color: LinearRgba,
+        intensity: f32,
+    },
+    Pbr {
+        base_color: LinearRgba,
+        metallic: f32,
+        roughness: f32,
+    },
+    /// Alternates between two colours on a grid of cells `1 / scale` wide.
+    Checker {
+        a: LinearRgba,
+        b: LinearRgba,
+        scale: f32,
+    },
+    /// Horizontal bands, `1 / scale` high, measured as distance above the floor horosphere.
+    Stripes {
+        a: LinearRgba,
+        b: LinearRgba,
+        scale: f32,
+    },
+    Rainbow {
+        scale: f32,
+    },
 }
 
-#[derive(Debug, Clone)]
+impl RMMaterial {
+    /// One of each kind of material.
+    pub const ALL: [RMMaterial; 6] = [
+        RMMaterial::Flat(LinearRgba::rgb(0.8, 0.8, 0.8)),
+        RMMaterial::Emissive { color: LinearRgba::rgb(1.0, 0.8, 0.5), intensity: 2.0 },
+        RMMaterial::Pbr { base_color: LinearRgba::rgb(0.8, 0.8, 0.8), metallic: 0.0, roughness: 0.5 },
+        RMMaterial::Checker { a: LinearRgba::WHITE, b: LinearRgba::BLACK, scale: 4.0 },
+        RMMaterial::Stripes { a: LinearRgba::WHITE, b: LinearRgba::BLACK, scale: 4.0 },
+        RMMaterial::Rainbow { scale: 1.0 },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RMMaterial::Flat(_) => "Flat",
+            RMMaterial::Emissive { .. } => "Emissive",
+            RMMaterial::Pbr { .. } => "PBR",
+            RMMaterial::Checker { .. } => "Checker",
+            RMMaterial::Stripes { .. } => "Stripes",
+            RMMaterial::Rainbow { .. } => "Rainbow",
+        }
+    }
+}
+
+pub const PATTERN_NONE: u32 = 0;
+pub const PATTERN_CHECKER: u32 = 1;
+pub const PATTERN_STRIPES: u32 = 2;
+pub const PATTERN_RAINBOW: u32 = 3;
+
+/// Material used by the built-in horosphere floor, always in slot 0 of the material table.
+pub const FLOOR_MATERIAL: RMMaterial = RMMaterial::Flat(LinearRgba::rgb(1.0, 1.0, 0.0));
+
+fn color_to_vec4(color: LinearRgba) -> Vec4 {
+    Vec4::new(color.red, color.green, color.blue, color.alpha)
+}
+
+impl From<&RMMaterial> for PreparedRMMaterial {
+    fn from(material: &RMMaterial) -> Self {
+        let flat = |base_color: LinearRgba| PreparedRMMaterial {
+            base_color: color_to_vec4(base_color),
+            secondary_color: color_to_vec4(base_color),
+            emissive: Vec4::ZERO,
+            metallic: 0.0,
+            roughness: 1.0,
+            pattern: PATTERN_NONE,
+            pattern_scale: 1.0,
+        };
+
+        match *material {
+            RMMaterial::Flat(color) => flat(color),
+            RMMaterial::Emissive { color, intensity } => PreparedRMMaterial {
+                emissive: color_to_vec4(color) * intensity,
+                ..flat(color)
+            },
+            RMMaterial::Pbr { base_color, metallic, roughness } => PreparedRMMaterial {
+                metallic,
+                roughness,
+                ..flat(base_color)
+            },
+            RMMaterial::Checker { a, b, scale } => PreparedRMMaterial {
+                secondary_color: color_to_vec4(b),
+                pattern: PATTERN_CHECKER,
+                pattern_scale: scale,
+                ..flat(a)
+            },
+            RMMaterial::Stripes { a, b, scale } => PreparedRMMaterial {
+                secondary_color: color_to_vec4(b),
+                pattern: PATTERN_STRIPES,
+                pattern_scale: scale,
+                ..flat(a)
+            },
+            RMMaterial::Rainbow { scale } => PreparedRMMaterial {
+                pattern: PATTERN_RAINBOW,
+                pattern_scale: scale,
+                ..flat(LinearRgba::WHITE)
+            },
+        }
+    }
+}
+
+// Must match the PROJECTION_* constants in the shader
+pub const PROJECTION_PERSPECTIVE: u32 = 0;
+pub const PROJECTION_FISHEYE: u32 = 1;
+pub const PROJECTION_EQUIRECTANGULAR: u32 = 2;
+pub const PROJECTION_CUBEMAP: u32 = 3;
+
+/// How pixels on screen map to directions out of the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RMProjection {
+    /// A flat pinhole image, `tan_fov` up from the centre to the top edge.
+    #[default]
+    Perspective,
+    /// Equidistant fisheye, the angle from the centre growing evenly out to `fisheye_angle` at
+    /// the top edge. Past half a turn there is nothing to see.
+    Fisheye,
+    /// The whole sphere of directions, longitude across and latitude up, best at 2:1.
+    Equirectangular,
+    /// Six square faces of a cube in a 3x2 grid, best at 3:2. The top row looks right, left
+    /// and up, the bottom row down, forward and back.
+    Cubemap,
+}
+
+impl RMProjection {
+    pub const ALL: [RMProjection; 4] = [
+        RMProjection::Perspective,
+        RMProjection::Fisheye,
+        RMProjection::Equirectangular,
+        RMProjection::Cubemap,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RMProjection::Perspective => "Perspective",
+            RMProjection::Fisheye => "Fisheye",
+            RMProjection::Equirectangular => "Equirectangular",
+            RMProjection::Cubemap => "Cube Map",
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        match self {
+            RMProjection::Perspective => PROJECTION_PERSPECTIVE,
+            RMProjection::Fisheye => PROJECTION_FISHEYE,
+            RMProjection::Equirectangular => PROJECTION_EQUIRECTANGULAR,
+            RMProjection::Cubemap => PROJECTION_CUBEMAP,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RMCameraSettings {
     pub aspect_ratio: f32,
     pub max_iterations: u32,
     pub max_dist: f32,
     pub min_dist: f32,
     pub tan_fov: f32,
+    pub projection: RMProjection,
+    /// Radians from the centre to the top edge of a fisheye view.
+    pub fisheye_angle: f32,
 }
 
 impl Default for RMCameraSettings {
@@ -90,6 +388,8 @@ impl Default for RMCameraSettings {
             max_dist: 100.0,
             min_dist: 0.0001,
             tan_fov: (7.0/18.0*PI).tan(),
+            projection: RMProjection::Perspective,
+            fisheye_angle: PI / 2.0,
         }
     }
 }
@@ -113,10 +413,30 @@ impl Default for RMCameraSettings {
 //     }
 // }
 
-#[derive(Debug, Clone, Default)]
+/// Straight down to straight up, the widest range `LocalOrient` allows.
+pub const PITCH_LIMITS: (f32, f32) = (-PI / 2.0, PI / 2.0);
+
+fn pitch_limits_default() -> (f32, f32) {
+    PITCH_LIMITS
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LocalOrient {
     yaw: f32,
     pitch: f32,
+    /// A control setting rather than part of the pose, so it isn't saved with it.
+    #[serde(skip, default = "pitch_limits_default")]
+    pitch_limits: (f32, f32),
+}
+
+impl Default for LocalOrient {
+    fn default() -> Self {
+        Self {
+            yaw: 0.0,
+            pitch: 0.0,
+            pitch_limits: PITCH_LIMITS,
+        }
+    }
 }
 
 impl LocalOrient {
@@ -138,10 +458,21 @@ impl LocalOrient {
     }
 
     pub fn set_pitch(&mut self, pitch: f32) -> &mut Self {
-        self.pitch = pitch.clamp(-PI/2.0, PI/2.0);
+        self.pitch = pitch.clamp(self.pitch_limits.0, self.pitch_limits.1);
         self
     }
 
+    pub fn pitch_limits(&self) -> (f32, f32) {
+        self.pitch_limits
+    }
+
+    /// Restricts how far the view can pitch down and up, within `PITCH_LIMITS`.
+    pub fn set_pitch_limits(&mut self, min: f32, max: f32) -> &mut Self {
+        let min = min.clamp(PITCH_LIMITS.0, PITCH_LIMITS.1);
+        self.pitch_limits = (min, max.clamp(min, PITCH_LIMITS.1));
+        self.set_pitch(self.pitch)
+    }
+
     pub fn add_mut_yaw(&mut self, delta_yaw: f32) -> &mut Self {
         self.set_yaw(self.yaw() + delta_yaw);
         self
@@ -152,7 +483,7 @@ impl LocalOrient {
         self
     }
 
-    pub fn into_global_orient(&self, transform: &HypTransform) -> [Vec4; 3] {
+    pub fn to_global_orient(&self, transform: &HypTransform) -> [Vec4; 3] {
         into_global_orient(self.mat3(), transform)
     }
 }
@@ -172,7 +503,7 @@ pub struct RMCamera {
 }
 
 #[derive(ShaderType, Clone, Debug)]
-struct PreparedRMCamera {
+pub struct PreparedRMCamera {
     pub position: Vec4,
     pub forward: Vec4,
     pub right: Vec4,
@@ -182,160 +513,350 @@ struct PreparedRMCamera {
     pub min_dist: f32,
     pub max_dist: f32,
     pub tan_fov: f32,
+    pub projection: u32,
+    pub fisheye_angle: f32,
 }
 
-impl Into<PreparedRMCamera> for RMCamera {
-    fn into(self) -> PreparedRMCamera {
-        (&self).into()
+impl From<RMCamera> for PreparedRMCamera {
+    fn from(camera: RMCamera) -> Self {
+        (&camera).into()
     }
 }
 
-impl Into<PreparedRMCamera> for &RMCamera {
-    fn into(self) -> PreparedRMCamera {
-        let orient = self.orient.into_global_orient(&self.transform);
+impl From<&RMCamera> for PreparedRMCamera {
+    fn from(camera: &RMCamera) -> Self {
+        let orient = camera.orient.to_global_orient(&camera.transform);
         PreparedRMCamera {
-            position: self.transform.translation,
+            position: camera.transform.translation,
             forward: orient[2],
             right: orient[0],
             up: orient[1],
-            aspect_ratio: self.settings.aspect_ratio,
-            max_iterations: self.settings.max_iterations,
-            max_dist: self.settings.max_dist,
-            min_dist: self.settings.min_dist,
-            tan_fov: self.settings.tan_fov,
+            aspect_ratio: camera.settings.aspect_ratio,
+            max_iterations: camera.settings.max_iterations,
+            max_dist: camera.settings.max_dist,
+            min_dist: camera.settings.min_dist,
+            tan_fov: camera.settings.tan_fov,
+            projection: camera.settings.projection.id(),
+            fisheye_angle: camera.settings.fisheye_angle,
         }
     }
 }
 
+/// Packed shape layout shared by every `RMShape`, see `Shape` in the shader.
 #[derive(Debug, Clone, ShaderType)]
-struct PreparedRMSphere {
-    centre: Vec4,
-    radius: f32,
-    material_id: u32,
+pub struct PreparedRMShape {
+    pub position: Vec4,
+    pub right: Vec4,
+    pub up: Vec4,
+    pub forward: Vec4,
+    pub params: Vec4,
+    pub kind: u32,
+    pub material_id: u32,
+}
+
+impl PreparedRMShape {
+    pub fn new(transform: &HypTransform, shape: &RMShape, material_id: u32) -> Self {
+        Self {
+            position: transform.translation,
+            right: transform.right,
+            up: transform.up,
+            forward: transform.forward,
+            params: shape.params(),
+            kind: shape.kind(),
+            material_id,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, ShaderType)]
-pub struct PreparedRMSpheres {
+pub struct PreparedRMShapes {
     #[size(runtime)]
-    spheres: Vec<PreparedRMSphere>,
+    pub shapes: Vec<PreparedRMShape>,
 }
 
+/// See `Material` in the shader.
+#[derive(Debug, Clone, PartialEq, ShaderType)]
+pub struct PreparedRMMaterial {
+    pub base_color: Vec4,
+    pub secondary_color: Vec4,
+    pub emissive: Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub pattern: u32,
+    pub pattern_scale: f32,
+}
 
-fn update_material(
-    mut rm_mats: ResMut<Assets<RayMarchingMaterial>>,
-    mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
-    rm_camera: Res<RMCamera>,
-    renderables: Query<(&HypTransform, &RMRenderable)>,
-    time: Res<Time>
-) {
-    let mut spheres = Vec::new();
-
-    let tf = rm_camera.transform
-        .clone()
-        .translate_forward(1.0)
-        .clone();
-
-    let tr = rm_camera.transform
-        .clone()
-        .translate_right(1.0)
-        .clone();
-
-    let tu = rm_camera.transform
-        .clone()
-        .translate_up(1.0)
-        .clone();
-
-    {
-        let trans = HypTransform::default()
-            .translate(Vec3::new(2.0, -1.0, 0.5), 2.0)
-            .clone();
-
-        let mat = Mat3::from_rotation_x(time.elapsed_secs() * 0.5)
-            .mul_mat3(&Mat3::from_rotation_y(time.elapsed_secs() * 0.1))
-            .mul_mat3(&Mat3::from_rotation_z(time.elapsed_secs() * 0.05));
-
-        let flow = |v: Vec3| {
-            trans.clone()
-                .translate(v, 0.5)
-                .clone()
-                .translation
+impl PreparedRMMaterial {
+    fn key(&self) -> [u32; 16] {
+        let mut key = [0; 16];
+        let floats = self.base_color.to_array().into_iter()
+            .chain(self.secondary_color.to_array())
+            .chain(self.emissive.to_array())
+            .chain([self.metallic, self.roughness, self.pattern_scale]);
+        for (k, f) in key.iter_mut().zip(floats) {
+            *k = f.to_bits();
+        }
+        key[15] = self.pattern;
+        key
+    }
+}
+
+#[derive(Clone, Debug, Default, ShaderType)]
+pub struct PreparedRMMaterials {
+    #[size(runtime)]
+    pub materials: Vec<PreparedRMMaterial>,
+}
+
+/// Deduplicates materials into the slots referenced by `PreparedRMShape::material_id`.
+#[derive(Debug, Clone)]
+pub struct RMMaterialTable {
+    materials: Vec<PreparedRMMaterial>,
+    slots: HashMap<[u32; 16], u32>,
+}
+
+impl Default for RMMaterialTable {
+    fn default() -> Self {
+        let mut table = Self {
+            materials: Vec::new(),
+            slots: HashMap::new(),
         };
+        table.slot(&FLOOR_MATERIAL);
+        table
+    }
+}
 
-        let c1 = flow(mat.x_axis + mat.y_axis + mat.z_axis);
-        let c2 = flow(mat.x_axis + mat.y_axis - mat.z_axis);
-        let c3 = flow(mat.x_axis - mat.y_axis + mat.z_axis);
-        let c4 = flow(mat.x_axis - mat.y_axis - mat.z_axis);
-        let c5 = flow(- mat.x_axis + mat.y_axis + mat.z_axis);
-        let c6 = flow(- mat.x_axis + mat.y_axis - mat.z_axis);
-        let c7 = flow(- mat.x_axis - mat.y_axis + mat.z_axis);
-        let c8 = flow(- mat.x_axis - mat.y_axis - mat.z_axis);
-
-        for c in [c1, c2, c3, c4, c5, c6, c7, c8] {
-            spheres.push(PreparedRMSphere {
-                centre: c,
-                radius: 0.075,
-                material_id: 3,
-            })
-        }
+impl RMMaterialTable {
+    pub fn slot(&mut self, material: &RMMaterial) -> u32 {
+        let prepared = PreparedRMMaterial::from(material);
+        let materials = &mut self.materials;
+
+        *self.slots.entry(prepared.key()).or_insert_with(|| {
+            materials.push(prepared);
+            materials.len() as u32 - 1
+        })
     }
+}
 
-    spheres.push(PreparedRMSphere {
-        centre: tf.translation,
-        radius: 0.05,
-        material_id: 4,
-    });
+impl From<RMMaterialTable> for PreparedRMMaterials {
+    fn from(table: RMMaterialTable) -> Self {
+        Self {
+            materials: table.materials,
+        }
+    }
+}
 
-    spheres.push(PreparedRMSphere {
-        centre: tr.translation,
-        radius: 0.05,
-        material_id: 5,
-    });
+/// Everything the shader reads from storage buffers, in the form the CPU reference renderer consumes.
+#[derive(Clone, Debug)]
+pub struct PreparedRMScene {
+    pub shapes: PreparedRMShapes,
+    pub bvh: PreparedRMBvh,
+    pub materials: PreparedRMMaterials,
+    pub lighting: PreparedRMLighting,
+    pub lights: PreparedRMLights,
+    pub honeycomb: PreparedRMHoneycomb,
+    pub quotient: PreparedRMQuotient,
+    pub geometry: Geometry,
+}
 
-    spheres.push(PreparedRMSphere {
-        centre: tu.translation,
-        radius: 0.05,
-        material_id: 6,
-    });
+impl Default for PreparedRMScene {
+    fn default() -> Self {
+        let lighting = RMLighting::default();
+        Self {
+            geometry: Geometry::default(),
+            shapes: PreparedRMShapes::default(),
+            bvh: PreparedRMBvh::default(),
+            materials: RMMaterialTable::default().into(),
+            lighting: (&lighting).into(),
+            lights: (&lighting).into(),
+            honeycomb: PreparedRMHoneycomb::default(),
+            quotient: PreparedRMQuotient::default(),
+        }
+    }
+}
 
-    for (transform, renderable) in renderables.iter() {
-        if !renderable.visible {
+/// Switches to another geometry, carrying the camera, entities and point lights over with it.
+/// Writing the `Geometry` resource directly switches without moving anything.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ChangeGeometry(pub Geometry);
+
+fn change_geometry(
+    mut events: EventReader<ChangeGeometry>,
+    mut geometry: ResMut<Geometry>,
+    mut rm_camera: ResMut<RMCamera>,
+    mut lighting: ResMut<RMLighting>,
+    mut transforms: Query<&mut HypTransform>,
+) {
+    for &ChangeGeometry(to) in events.read() {
+        let from = *geometry;
+        if from == to {
             continue;
         }
-        match renderable.shape {
-            RMShape::Sphere { radius } => spheres.push(PreparedRMSphere {
-                centre: transform.translation,
-                radius,
-                material_id: 1,
-            }),
+
+        rm_camera.transform.reinterpret(from, to);
+        for mut transform in transforms.iter_mut() {
+            transform.reinterpret(from, to);
+        }
+        for light in lighting.point_lights.iter_mut() {
+            light.position = from.transfer_point(to, light.position);
         }
+        *geometry = to;
+    }
+}
+
+/// Time driving scene animation. It follows `Time` unless `fixed` is set, which lets exports
+/// step it by exact frame intervals regardless of how long each frame takes to render.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct RMClock {
+    pub elapsed: f32,
+    pub fixed: bool,
+}
+
+fn advance_clock(mut clock: ResMut<RMClock>, time: Res<Time>) {
+    if !clock.fixed {
+        clock.elapsed += time.delta_secs();
     }
+}
+
+fn update_material(
+    mut rm_mats: ResMut<Assets<RayMarchingMaterial>>,
+    mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
+    rm_camera: Res<RMCamera>,
+    lighting: Res<RMLighting>,
+    honeycomb: Res<RMHoneycomb>,
+    domain: Res<RMFundamentalDomain>,
+    model_view: Res<RMModelView>,
+    geometry: Res<Geometry>,
+    renderables: Query<(&HypGlobalTransform, &RMRenderable)>,
+) {
+    let scene = prepare_scene(
+        &lighting,
+        &honeycomb,
+        &domain,
+        *geometry,
+        renderables.iter().map(|(global, renderable)| (global.transform(), renderable)),
+    );
+
     for (_, rm_mat) in rm_mats.iter_mut() {
+        rm_mat.geometry = scene.geometry;
         rm_mat.camera = (&*rm_camera).into();
-        buffers.get_mut(&rm_mat.spheres)
+        rm_mat.lighting = scene.lighting.clone();
+        rm_mat.honeycomb = scene.honeycomb.clone();
+        rm_mat.model_view = PreparedRMModelView::new(&model_view, &rm_camera, scene.geometry);
+        buffers.get_mut(&rm_mat.lights)
+            .expect("buffer must exist")
+            .set_data(scene.lights.clone());
+        buffers.get_mut(&rm_mat.shapes)
+            .expect("buffer must exist")
+            .set_data(scene.shapes.clone());
+        buffers.get_mut(&rm_mat.bvh)
+            .expect("buffer must exist")
+            .set_data(scene.bvh.clone());
+        buffers.get_mut(&rm_mat.materials)
             .expect("buffer must exist")
-            .set_data(PreparedRMSpheres {
-                spheres: spheres.clone(),
-            });
+            .set_data(scene.materials.clone());
+        buffers.get_mut(&rm_mat.quotient)
+            .expect("buffer must exist")
+            .set_data(scene.quotient.clone());
+    }
+}
+
+/// Builds the storage buffers exactly as they are uploaded to the GPU, so the CPU reference
+/// renderer can consume the same data.
+pub fn prepare_scene<'a>(
+    lighting: &RMLighting,
+    honeycomb: &RMHoneycomb,
+    domain: &RMFundamentalDomain,
+    geometry: Geometry,
+    renderables: impl Iterator<Item = (&'a HypTransform, &'a RMRenderable)>,
+) -> PreparedRMScene {
+    let mut shapes = Vec::new();
+    let mut materials = RMMaterialTable::default();
+
+    for (transform, renderable) in renderables {
+        if !renderable.visible {
+            continue;
+        }
+        let material_id = materials.slot(&renderable.material);
+        shapes.push(PreparedRMShape::new(transform, &renderable.shape, material_id));
+    }
+
+    let (shapes, bvh) = bvh::build(geometry, shapes);
+    let honeycomb = PreparedRMHoneycomb::new(honeycomb, geometry, &mut materials);
+
+    PreparedRMScene {
+        shapes: PreparedRMShapes { shapes },
+        bvh,
+        materials: materials.into(),
+        lighting: lighting.into(),
+        lights: lighting.into(),
+        honeycomb,
+        quotient: domain.into(),
+        geometry,
     }
 }
 
 //New material created to setup custom shader
 #[derive(AsBindGroup, Debug, Clone, TypePath, Asset)]
+#[bind_group_data(RayMarchingMaterialKey)]
 pub struct RayMarchingMaterial {
     //Set the uniform at binding 0 to have the following information - connects to Camera struct in ray_marching_material.wgsl
     #[uniform(0)]
     camera: PreparedRMCamera,
     #[storage(1, read_only)]
-    spheres: Handle<ShaderStorageBuffer>,
+    shapes: Handle<ShaderStorageBuffer>,
+    #[storage(2, read_only)]
+    materials: Handle<ShaderStorageBuffer>,
+    #[uniform(3)]
+    lighting: PreparedRMLighting,
+    #[storage(4, read_only)]
+    lights: Handle<ShaderStorageBuffer>,
+    #[storage(5, read_only)]
+    bvh: Handle<ShaderStorageBuffer>,
+    #[uniform(6)]
+    honeycomb: PreparedRMHoneycomb,
+    #[storage(7, read_only)]
+    quotient: Handle<ShaderStorageBuffer>,
+    #[uniform(8)]
+    model_view: PreparedRMModelView,
+    geometry: Geometry,
+}
+
+/// Pipeline key, switching geometry recompiles the shader with a different `GEOMETRY_*` def.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RayMarchingMaterialKey {
+    geometry: Geometry,
+}
+
+impl From<&RayMarchingMaterial> for RayMarchingMaterialKey {
+    fn from(material: &RayMarchingMaterial) -> Self {
+        Self {
+            geometry: material.geometry,
+        }
+    }
 }
 
 impl RayMarchingMaterial {
     pub fn from_buffers(mut buffers: ResMut<Assets<ShaderStorageBuffer>>) -> Self {
-        let spheres = buffers.add(ShaderStorageBuffer::from(PreparedRMSpheres::default()));
+        let shapes = buffers.add(ShaderStorageBuffer::from(PreparedRMShapes::default()));
+        let materials = buffers.add(ShaderStorageBuffer::from(PreparedRMMaterials::from(
+            RMMaterialTable::default(),
+        )));
+        let lighting = RMLighting::default();
+        let lights = buffers.add(ShaderStorageBuffer::from(PreparedRMLights::from(&lighting)));
+        let bvh = buffers.add(ShaderStorageBuffer::from(PreparedRMBvh::default()));
+        let quotient = buffers.add(ShaderStorageBuffer::from(PreparedRMQuotient::default()));
 
         RayMarchingMaterial {
             camera: RMCamera::default().into(),
-            spheres,
+            shapes,
+            materials,
+            lighting: (&lighting).into(),
+            lights,
+            bvh,
+            honeycomb: PreparedRMHoneycomb::default(),
+            quotient,
+            model_view: PreparedRMModelView::default(),
+            geometry: Geometry::default(),
         }
     }
 }
@@ -350,4 +871,62 @@ impl Material2d for RayMarchingMaterial {
     fn fragment_shader() -> ShaderRef {
         "shaders/ray_marching_material.wgsl".into()
     }
+
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let def = key.bind_group_data.geometry.shader_def();
+        descriptor.vertex.shader_defs.push(def.into());
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            fragment.shader_defs.push(def.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pitch_limits() {
+        let mut orient = LocalOrient::default();
+        orient.set_pitch(1.0);
+
+        orient.set_pitch_limits(-0.5, 0.5);
+        assert_eq!(orient.pitch(), 0.5);
+        orient.add_mut_pitch(-2.0);
+        assert_eq!(orient.pitch(), -0.5);
+
+        // Never past straight up or down, and never inverted.
+        orient.set_pitch_limits(0.3, -4.0);
+        assert_eq!(orient.pitch_limits(), (0.3, 0.3));
+        orient.set_pitch_limits(-4.0, 4.0);
+        assert_eq!(orient.pitch_limits(), PITCH_LIMITS);
+    }
+
+    #[test]
+    fn test_every_shape_listed_in_kind_order() {
+        for (i, shape) in RMShape::ALL.iter().enumerate() {
+            assert_eq!(shape.kind(), i as u32, "{}", shape.name());
+        }
+    }
+
+    #[test]
+    fn test_material_slots_are_deduplicated() {
+        let mut table = RMMaterialTable::default();
+
+        let blue = table.slot(&RMMaterial::Flat(LinearRgba::BLUE));
+        let red = table.slot(&RMMaterial::Flat(LinearRgba::RED));
+
+        assert_eq!(table.slot(&FLOOR_MATERIAL), 0);
+        assert_ne!(blue, red);
+        assert_eq!(table.slot(&RMMaterial::Flat(LinearRgba::BLUE)), blue);
+
+        let prepared = PreparedRMMaterials::from(table);
+        assert_eq!(prepared.materials.len(), 3);
+        assert_eq!(prepared.materials[blue as usize].base_color, Vec4::new(0.0, 0.0, 1.0, 1.0));
+    }
 }
@@ -0,0 +1,407 @@
+// Scene files describe the renderables, camera and geometry of a world in RON. They are loaded as
+// assets, so with the `file_watcher` feature editing the file on disk updates the running app.
+
+use bevy::{
+    asset::{
+        io::{file::FileAssetReader, Reader},
+        AssetLoader, LoadContext,
+    },
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    geometries::{Geometry, HypTransform},
+    honeycomb::RMHoneycomb,
+    input::{RMAction, RMActions},
+    quotient::RMQuotient,
+    ray_marching_material::{
+        into_global_orient, RMCamera, RMCameraSettings, RMMaterial, RMRenderable, RMShape,
+    },
+};
+
+pub const DEFAULT_SCENE: &str = "scenes/default.rmscene.ron";
+
+/// Loads the scene at `path`, relative to the assets folder.
+pub struct RMScenePlugin {
+    pub path: String,
+}
+
+impl Default for RMScenePlugin {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_SCENE.to_string(),
+        }
+    }
+}
+
+impl Plugin for RMScenePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(RMScenePath(self.path.clone()))
+            .init_asset::<RMScene>()
+            .init_asset_loader::<RMSceneLoader>()
+            .add_event::<SaveRMScene>()
+            .add_systems(Startup, load_scene)
+            // Headless renders have no input to read.
+            .add_systems(Update, save_scene_shortcut.run_if(resource_exists::<RMActions>))
+            .add_systems(Update, (apply_scene, save_scene));
+    }
+}
+
+/// Where an entity sits. Scenes written by hand will usually use `Geodesic`, saved scenes use
+/// `Transform` so nothing is lost to rounding.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RMPose {
+    Transform(HypTransform),
+    /// Travel `distance` from the origin along `direction` (in the default frame), then rotate
+    /// the frame by the YXZ Euler angles in `rotation`.
+    Geodesic {
+        direction: Vec3,
+        distance: f32,
+        #[serde(default)]
+        rotation: Vec3,
+    },
+}
+
+impl Default for RMPose {
+    fn default() -> Self {
+        RMPose::Transform(HypTransform::default())
+    }
+}
+
+impl RMPose {
+    pub fn to_transform(&self, geometry: Geometry) -> HypTransform {
+        match self {
+            RMPose::Transform(transform) => transform.clone(),
+            &RMPose::Geodesic { direction, distance, rotation } => {
+                let mut transform = HypTransform::default();
+                if distance != 0.0 {
+                    transform.translate_in(geometry, direction, distance);
+                }
+
+                let rotation = Mat3::from_euler(EulerRot::YXZ, rotation.x, rotation.y, rotation.z);
+                let [right, up, forward] = into_global_orient(rotation, &transform);
+                transform.right = right;
+                transform.up = up;
+                transform.forward = forward;
+                transform
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RMSceneRenderable {
+    pub shape: RMShape,
+    pub material: RMMaterial,
+    #[serde(default)]
+    pub pose: RMPose,
+    #[serde(default = "visible_default")]
+    pub visible: bool,
+}
+
+fn visible_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RMSceneCamera {
+    pub pose: RMPose,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub settings: RMCameraSettings,
+}
+
+/// Contents of a `.rmscene.ron` file. Poses are interpreted in `geometry`.
+#[derive(Asset, TypePath, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RMScene {
+    pub geometry: Geometry,
+    pub camera: RMSceneCamera,
+    pub renderables: Vec<RMSceneRenderable>,
+    pub honeycomb: RMHoneycomb,
+    /// Only applies in H^3.
+    pub quotient: RMQuotient,
+}
+
+impl RMScene {
+    /// Snapshot of the running app, in the form `save_scene` writes out.
+    pub fn capture<'a>(
+        geometry: Geometry,
+        rm_camera: &RMCamera,
+        honeycomb: &RMHoneycomb,
+        quotient: &RMQuotient,
+        renderables: impl Iterator<Item = (&'a HypTransform, &'a RMRenderable)>,
+    ) -> Self {
+        Self {
+            geometry,
+            camera: RMSceneCamera {
+                pose: RMPose::Transform(rm_camera.transform.clone()),
+                yaw: rm_camera.orient.yaw(),
+                pitch: rm_camera.orient.pitch(),
+                settings: rm_camera.settings.clone(),
+            },
+            renderables: renderables
+                .map(|(transform, renderable)| RMSceneRenderable {
+                    shape: renderable.shape.clone(),
+                    material: renderable.material.clone(),
+                    pose: RMPose::Transform(transform.clone()),
+                    visible: renderable.visible,
+                })
+                .collect(),
+            honeycomb: honeycomb.clone(),
+            quotient: quotient.clone(),
+        }
+    }
+
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(text)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RMSceneLoaderError {
+    #[error("could not read scene: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse scene: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+#[derive(Default)]
+pub struct RMSceneLoader;
+
+impl AssetLoader for RMSceneLoader {
+    type Asset = RMScene;
+    type Settings = ();
+    type Error = RMSceneLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["rmscene.ron"]
+    }
+}
+
+/// The scene currently shown, and the asset path `save_scene` writes back to.
+#[derive(Resource, Debug, Clone)]
+pub struct RMSceneHandle {
+    pub handle: Handle<RMScene>,
+    pub path: String,
+}
+
+/// Marks entities spawned from the scene file, which are replaced when it is reloaded.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct RMSceneEntity;
+
+/// Writes the running scene back to the file it was loaded from.
+#[derive(Event, Debug, Clone, Default)]
+pub struct SaveRMScene;
+
+#[derive(Resource, Debug, Clone)]
+struct RMScenePath(String);
+
+fn load_scene(mut commands: Commands, asset_server: Res<AssetServer>, path: Res<RMScenePath>) {
+    commands.insert_resource(RMSceneHandle {
+        handle: asset_server.load(path.0.clone()),
+        path: path.0.clone(),
+    });
+}
+
+/// Spawns the scene when it first loads and respawns its entities whenever the file changes.
+/// The camera pose is only applied on the first load, so editing the file doesn't teleport you.
+fn apply_scene(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<RMScene>>,
+    scenes: Res<Assets<RMScene>>,
+    scene_handle: Option<Res<RMSceneHandle>>,
+    mut rm_camera: ResMut<RMCamera>,
+    mut geometry: ResMut<Geometry>,
+    mut honeycomb: ResMut<RMHoneycomb>,
+    mut quotient: ResMut<RMQuotient>,
+    spawned: Query<Entity, With<RMSceneEntity>>,
+    mut others: Query<&mut HypTransform, Without<RMSceneEntity>>,
+) {
+    let Some(scene_handle) = scene_handle else {
+        return;
+    };
+
+    for event in events.read() {
+        let first_load = event.is_loaded_with_dependencies(&scene_handle.handle);
+        if !first_load && !event.is_modified(&scene_handle.handle) {
+            continue;
+        }
+        let Some(scene) = scenes.get(&scene_handle.handle) else {
+            continue;
+        };
+
+        for entity in spawned.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        // Entities from elsewhere, like the demo, move into the scene's geometry with everything.
+        if *geometry != scene.geometry {
+            for mut transform in others.iter_mut() {
+                transform.reinterpret(*geometry, scene.geometry);
+            }
+        }
+        *geometry = scene.geometry;
+        *honeycomb = scene.honeycomb.clone();
+        quotient.set_if_neq(scene.quotient.clone());
+
+        let aspect_ratio = rm_camera.settings.aspect_ratio;
+        rm_camera.settings = RMCameraSettings {
+            aspect_ratio,
+            ..scene.camera.settings.clone()
+        };
+        if first_load {
+            rm_camera.transform = scene.camera.pose.to_transform(scene.geometry);
+            rm_camera.orient
+                .set_yaw(scene.camera.yaw)
+                .set_pitch(scene.camera.pitch);
+        }
+
+        for renderable in scene.renderables.iter() {
+            let mut rm_renderable = RMRenderable::new(renderable.shape.clone(), renderable.material.clone());
+            rm_renderable.set_visibility(renderable.visible);
+            commands.spawn((
+                rm_renderable,
+                renderable.pose.to_transform(scene.geometry),
+                RMSceneEntity,
+            ));
+        }
+    }
+}
+
+fn save_scene_shortcut(actions: Res<RMActions>, mut save: EventWriter<SaveRMScene>) {
+    if actions.just_pressed(RMAction::SaveScene) {
+        save.send(SaveRMScene);
+    }
+}
+
+fn save_scene(
+    mut events: EventReader<SaveRMScene>,
+    scene_handle: Option<Res<RMSceneHandle>>,
+    geometry: Res<Geometry>,
+    rm_camera: Res<RMCamera>,
+    honeycomb: Res<RMHoneycomb>,
+    quotient: Res<RMQuotient>,
+    renderables: Query<(&HypTransform, &RMRenderable), With<RMSceneEntity>>,
+) {
+    if events.read().count() == 0 {
+        return;
+    }
+    let Some(scene_handle) = scene_handle else {
+        return;
+    };
+
+    let scene = RMScene::capture(*geometry, &rm_camera, &honeycomb, &quotient, renderables.iter());
+    // Found the way the asset server finds the scene, not relative to the working directory.
+    let path = FileAssetReader::get_base_path().join("assets").join(&scene_handle.path);
+
+    match scene.to_ron() {
+        Ok(text) => match std::fs::write(&path, text) {
+            Ok(()) => info!("saved scene to {}", path.display()),
+            Err(err) => error!("could not save scene to {}: {err}", path.display()),
+        },
+        Err(err) => error!("could not serialize scene: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scene_round_trips_through_ron() {
+        let scene = RMScene {
+            geometry: Geometry::Spherical,
+            camera: RMSceneCamera {
+                yaw: 0.5,
+                ..default()
+            },
+            renderables: vec![RMSceneRenderable {
+                shape: RMShape::Torus { major_radius: 0.3, minor_radius: 0.05 },
+                material: RMMaterial::Flat(LinearRgba::RED),
+                pose: RMPose::Geodesic {
+                    direction: Vec3::new(-1.0, 1.0, 1.0),
+                    distance: 0.8,
+                    rotation: Vec3::ZERO,
+                },
+                visible: false,
+            }],
+            honeycomb: RMHoneycomb {
+                visible: true,
+                schlafli: [3, 3, 5],
+                ..default()
+            },
+            quotient: RMQuotient::SeifertWeber,
+        };
+
+        let parsed = RMScene::from_ron(&scene.to_ron().unwrap()).unwrap();
+
+        assert_eq!(parsed.geometry, Geometry::Spherical);
+        assert_eq!(parsed.camera.yaw, 0.5);
+        assert_eq!(parsed.renderables.len(), 1);
+        assert!(!parsed.renderables[0].visible);
+        assert_eq!(parsed.honeycomb.schlafli, [3, 3, 5]);
+        assert_eq!(parsed.quotient, RMQuotient::SeifertWeber);
+    }
+
+    #[test]
+    fn test_default_scene_parses() {
+        let text = include_str!("../assets/scenes/default.rmscene.ron");
+        let scene = RMScene::from_ron(text).unwrap();
+
+        assert_eq!(scene.geometry, Geometry::Hyperbolic);
+        assert!(!scene.renderables.is_empty());
+    }
+
+    #[test]
+    fn test_honeycomb_scene_parses() {
+        let text = include_str!("../assets/scenes/honeycomb.rmscene.ron");
+        let scene = RMScene::from_ron(text).unwrap();
+
+        assert!(scene.honeycomb.visible);
+        assert_eq!(crate::honeycomb::honeycomb_geometry(scene.honeycomb.schlafli), Some(scene.geometry));
+    }
+
+    #[test]
+    fn test_seifert_weber_scene_parses() {
+        let text = include_str!("../assets/scenes/seifert_weber.rmscene.ron");
+        let scene = RMScene::from_ron(text).unwrap();
+
+        assert_eq!(scene.quotient, RMQuotient::SeifertWeber);
+        assert_eq!(scene.geometry, Geometry::Hyperbolic);
+    }
+
+    #[test]
+    fn test_geodesic_pose_matches_translate() {
+        let pose = RMPose::Geodesic {
+            direction: Vec3::new(0.0, 1.0, 1.0),
+            distance: 0.5,
+            rotation: Vec3::ZERO,
+        };
+        let expected = HypTransform::default()
+            .translate(Vec3::new(0.0, 1.0, 1.0), 0.5)
+            .clone();
+
+        let transform = pose.to_transform(Geometry::Hyperbolic);
+
+        assert!((transform.translation - expected.translation).length() < 1e-6);
+        assert!((transform.forward - expected.forward).length() < 1e-6);
+    }
+}
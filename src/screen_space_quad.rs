@@ -22,10 +22,10 @@ impl ScreenSpaceQuad {
 //Note: the normal and uv attribute had to be included for this to work. Seems to be some Bevy limitation
 impl From<ScreenSpaceQuad> for Mesh {
     fn from(screen_space_quad: ScreenSpaceQuad) -> Self {
-        let vertices = vec![[-1.0*screen_space_quad.scale.x, -1.0*screen_space_quad.scale.y, 0.0],
-                            [-1.0*screen_space_quad.scale.x,  1.0*screen_space_quad.scale.y, 0.0],
-                            [ 1.0*screen_space_quad.scale.x, -1.0*screen_space_quad.scale.y, 0.0],
-                            [ 1.0*screen_space_quad.scale.x,  1.0*screen_space_quad.scale.y, 0.0]];
+        let vertices = vec![[-screen_space_quad.scale.x, -screen_space_quad.scale.y, 0.0],
+                            [-screen_space_quad.scale.x,  screen_space_quad.scale.y, 0.0],
+                            [ screen_space_quad.scale.x, -screen_space_quad.scale.y, 0.0],
+                            [ screen_space_quad.scale.x,  screen_space_quad.scale.y, 0.0]];
 
         let indices = Indices::U32(vec![0, 2, 1, 2, 3, 1]);
 
@@ -1,4 +1,23 @@
-use crate::ray_marching_material::RMCamera;
+use std::{
+    f32::consts::{FRAC_PI_2, PI},
+    ops::RangeInclusive,
+};
+
+use crate::{
+    camera_path::RMCameraPathPlayer,
+    geometries::{to_upper_half_space, Geometry, HypTransform},
+    honeycomb::{honeycomb_geometry, schlafli_name, RMHoneycomb, SCHLAFLI_SYMBOLS},
+    input::{bindings_path, RMAction, RMBindings, RMRebinding, RMStickSettings},
+    look::RMLookSettings,
+    model_view::{RMHyperbolicModel, RMModelView},
+    navigation::{RMNavigation, RMNavigationMode},
+    picking::RMSelection,
+    quotient::RMQuotient,
+    ray_marching_material::{
+        ChangeGeometry, RMCamera, RMMaterial, RMProjection, RMRenderable, RMShape,
+    },
+    scene::{RMSceneEntity, SaveRMScene},
+};
 use bevy::prelude::*;
 
 use bevy_egui::{egui, EguiContexts};
@@ -10,7 +29,7 @@ impl Plugin for UIPlugin {
     fn build(&self, app: &mut App) {
         app
             // .add_systems(Startup, init_ui)
-            .add_systems(Update, uniform_update_ui_system);
+            .add_systems(Update, (uniform_update_ui_system, scene_editor_ui_system, controls_ui_system));
     }
 }
 
@@ -27,12 +46,80 @@ impl Plugin for UIPlugin {
 fn uniform_update_ui_system(
     mut ctx: EguiContexts,
     mut rm_camera: ResMut<RMCamera>,
+    geometry: Res<Geometry>,
+    mut change_geometry: EventWriter<ChangeGeometry>,
+    mut save_scene: EventWriter<SaveRMScene>,
+    mut player: ResMut<RMCameraPathPlayer>,
+    mut honeycomb: ResMut<RMHoneycomb>,
+    mut quotient: ResMut<RMQuotient>,
+    mut loaded_polyhedron: Local<Option<RMQuotient>>,
+    mut navigation: ResMut<RMNavigation>,
+    mut look: ResMut<RMLookSettings>,
+    mut model_view: ResMut<RMModelView>,
+    selection: Res<RMSelection>,
 ) {
     let context = ctx.ctx_mut();
     egui::Window::new("Update Uniforms").show(context, |ui| {
+        let position = rm_camera.transform.translation;
+        if *geometry == Geometry::Hyperbolic {
+            ui.horizontal(|ui| {
+                ui.label(format!("Hyperboloid: [{position}]"));
+            });
+            // Height above the boundary plane, the floor is at 1.
+            ui.horizontal(|ui| {
+                ui.label(format!("Upper Half-Space: [{}]", to_upper_half_space(position)));
+            });
+        } else {
+            ui.horizontal(|ui| {
+                ui.label(format!("Coords: [{position}]"));
+            });
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Save Scene").on_hover_text("Ctrl+S").clicked() {
+                save_scene.send(SaveRMScene);
+            }
+        });
         ui.horizontal(|ui| {
-            ui.label(format!("Coords: [{}]", rm_camera.transform.translation));
+            ui.label("Geometry:");
+            let mut selected = *geometry;
+            egui::ComboBox::from_id_salt("geometry")
+                .selected_text(selected.name())
+                .show_ui(ui, |ui| {
+                    for g in Geometry::ALL {
+                        ui.selectable_value(&mut selected, g, g.name());
+                    }
+                });
+            if selected != *geometry {
+                change_geometry.send(ChangeGeometry(selected));
+            }
         });
+        ui.horizontal(|ui| {
+            ui.label("View:");
+            let name = |model: Option<RMHyperbolicModel>| model.map_or("First Person", |model| model.name());
+            let mut selected = model_view.model;
+            // Models of H^3 only, other geometries are always seen in first person.
+            ui.add_enabled_ui(*geometry == Geometry::Hyperbolic, |ui| {
+                egui::ComboBox::from_id_salt("model_view")
+                    .selected_text(name(selected))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut selected, None, name(None));
+                        for model in RMHyperbolicModel::ALL {
+                            ui.selectable_value(&mut selected, Some(model), name(Some(model)));
+                        }
+                    });
+            });
+            if selected != model_view.model {
+                model_view.model = selected;
+            }
+        });
+        if model_view.active(*geometry).is_some() {
+            slider(ui, "Model Yaw:", &mut model_view.yaw, -PI..=PI);
+            slider(ui, "Model Pitch:", &mut model_view.pitch, -FRAC_PI_2..=FRAC_PI_2);
+            slider(ui, "Model Distance:", &mut model_view.distance, 1.1..=10.0);
+            ui.checkbox(&mut model_view.show_camera, "Show Camera");
+            slider(ui, "Camera Marker Radius:", &mut model_view.camera_radius, 0.01..=0.5);
+            slider(ui, "Frustum Length:", &mut model_view.frustum_length, 0.1..=5.0);
+        }
         ui.horizontal(|ui| {
             ui.label("Max Iterations:");
             ui.add(egui::Slider::new(
@@ -55,11 +142,496 @@ fn uniform_update_ui_system(
             ));
         });
         ui.horizontal(|ui| {
-            ui.label("Mandelbulb TanFov:");
-            ui.add(egui::Slider::new(
-                &mut rm_camera.settings.tan_fov,
-                1.0..=100.0,
+            ui.label("Projection:");
+            let mut selected = rm_camera.settings.projection;
+            egui::ComboBox::from_id_salt("projection")
+                .selected_text(selected.name())
+                .show_ui(ui, |ui| {
+                    for projection in RMProjection::ALL {
+                        ui.selectable_value(&mut selected, projection, projection.name());
+                    }
+                });
+            if selected != rm_camera.settings.projection {
+                rm_camera.settings.projection = selected;
+            }
+        });
+        match rm_camera.settings.projection {
+            RMProjection::Perspective => {
+                // Edited as the full vertical angle, tan_fov blows up towards half a turn.
+                let mut fov = 2.0 * rm_camera.settings.tan_fov.atan().to_degrees();
+                ui.horizontal(|ui| {
+                    ui.label("Field of View:");
+                    if ui.add(egui::Slider::new(&mut fov, 10.0..=170.0).suffix("°")).changed() {
+                        rm_camera.settings.tan_fov = (0.5 * fov).to_radians().tan();
+                    }
+                });
+            }
+            RMProjection::Fisheye => {
+                let mut fov = 2.0 * rm_camera.settings.fisheye_angle.to_degrees();
+                ui.horizontal(|ui| {
+                    ui.label("Field of View:");
+                    if ui.add(egui::Slider::new(&mut fov, 90.0..=360.0).suffix("°")).changed() {
+                        rm_camera.settings.fisheye_angle = (0.5 * fov).to_radians();
+                    }
+                });
+            }
+            // Panoramas always cover every direction.
+            RMProjection::Equirectangular | RMProjection::Cubemap => {}
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Navigation:");
+            let mut selected = navigation.mode;
+            egui::ComboBox::from_id_salt("navigation")
+                .selected_text(selected.name())
+                .show_ui(ui, |ui| {
+                    for mode in RMNavigationMode::ALL {
+                        ui.selectable_value(&mut selected, mode, mode.name());
+                    }
+                });
+            if selected != navigation.mode {
+                navigation.mode = selected;
+            }
+        });
+        match navigation.mode {
+            RMNavigationMode::Walk => {
+                ui.horizontal(|ui| {
+                    ui.label("Walk Speed:");
+                    ui.add(egui::Slider::new(&mut navigation.walk_speed, 0.01..=2.0));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Jump Speed:");
+                    ui.add(egui::Slider::new(&mut navigation.jump_speed, 0.0..=2.0));
+                });
+            }
+            RMNavigationMode::FreeFly => {
+                ui.horizontal(|ui| {
+                    ui.label("Fly Speed:");
+                    ui.add(egui::Slider::new(&mut navigation.fly_speed, 0.01..=5.0));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Roll Speed:");
+                    ui.add(egui::Slider::new(&mut navigation.roll_speed, 0.0..=5.0));
+                });
+            }
+            RMNavigationMode::Orbit => {
+                ui.horizontal(|ui| {
+                    let target = navigation.orbit_target.map_or("None".to_string(), |entity| entity.to_string());
+                    ui.label(format!("Target: {target}"));
+                    if ui.add_enabled(selection.0.is_some(), egui::Button::new("Orbit Selected")).clicked() {
+                        navigation.orbit_target = selection.0;
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Orbit Distance:");
+                    ui.add(egui::Slider::new(&mut navigation.orbit_distance, 0.1..=10.0));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Orbit Speed:");
+                    ui.add(egui::Slider::new(&mut navigation.orbit_speed, 0.0..=5.0));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Zoom Speed:");
+                    ui.add(egui::Slider::new(&mut navigation.zoom_speed, 0.0..=5.0));
+                });
+            }
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Look Sensitivity:");
+            ui.add(egui::Slider::new(&mut look.sensitivity, 0.0001..=0.02).logarithmic(true));
+        });
+        slider(ui, "Look Smoothing:", &mut look.smoothing, 0.0..=0.5);
+        ui.checkbox(&mut look.invert_y, "Invert Y");
+        let (mut min, mut max) = rm_camera.orient.pitch_limits();
+        ui.horizontal(|ui| {
+            ui.label("Pitch Limits:");
+            ui.add(egui::Slider::new(&mut min, -90.0_f32.to_radians()..=0.0));
+            ui.add(egui::Slider::new(&mut max, 0.0..=90.0_f32.to_radians()));
+        });
+        if (min, max) != rm_camera.orient.pitch_limits() {
+            rm_camera.orient.set_pitch_limits(min, max);
+        }
+        ui.checkbox(&mut look.wheel_roll, "Scroll Wheel Rolls In Free Fly");
+        slider(ui, "Wheel Roll Sensitivity:", &mut look.roll_sensitivity, 0.0..=0.5);
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "Camera Path: {} keyframes, {:.1}s",
+                player.path.keyframes.len(),
+                player.path.duration(),
             ));
         });
+        ui.horizontal(|ui| {
+            ui.label("Keyframe Spacing:");
+            ui.add(egui::Slider::new(&mut player.keyframe_spacing, 0.1..=10.0).suffix("s"));
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Add Keyframe").on_hover_text("K").clicked() {
+                player.add_keyframe(&rm_camera, *geometry);
+            }
+            if ui.button("Clear").clicked() {
+                player.stop();
+                player.path.keyframes.clear();
+            }
+        });
+        ui.horizontal(|ui| {
+            let label = if player.playing { "Stop" } else { "Play" };
+            if ui.button(label).on_hover_text("P").clicked() {
+                if player.playing {
+                    player.stop();
+                } else {
+                    player.play(*geometry);
+                }
+            }
+            ui.checkbox(&mut player.looping, "Loop");
+            if ui.button("Save Path").clicked() {
+                player.save();
+            }
+            if ui.button("Load Path").clicked() {
+                player.load();
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut honeycomb.visible, "Honeycomb");
+            egui::ComboBox::from_id_salt("honeycomb")
+                .selected_text(schlafli_name(honeycomb.schlafli))
+                .show_ui(ui, |ui| {
+                    for symbol in SCHLAFLI_SYMBOLS {
+                        if honeycomb_geometry(symbol) == Some(*geometry) {
+                            ui.selectable_value(&mut honeycomb.schlafli, symbol, schlafli_name(symbol));
+                        }
+                    }
+                });
+        });
+        if let Some(needed) = honeycomb_geometry(honeycomb.schlafli).filter(|g| g != &*geometry) {
+            ui.label(format!("{} only tiles {} space", schlafli_name(honeycomb.schlafli), needed.name()));
+        }
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut honeycomb.edges, "Edges");
+            ui.checkbox(&mut honeycomb.vertices, "Vertices");
+            ui.checkbox(&mut honeycomb.cells, "Cells");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Edge Radius:");
+            ui.add(egui::Slider::new(&mut honeycomb.edge_radius, 0.001..=0.2));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Vertex Radius:");
+            ui.add(egui::Slider::new(&mut honeycomb.vertex_radius, 0.001..=0.3));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Cell Gap:");
+            ui.add(egui::Slider::new(&mut honeycomb.cell_gap, 0.0..=0.5));
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Quotient:");
+            // Polyhedra only come from scene files, so keep the last one to switch back to.
+            if matches!(*quotient, RMQuotient::Polyhedron(_)) {
+                *loaded_polyhedron = Some(quotient.clone());
+            }
+            let mut selected = quotient.clone();
+            egui::ComboBox::from_id_salt("quotient")
+                .selected_text(selected.name())
+                .show_ui(ui, |ui| {
+                    let quotients = [RMQuotient::None, RMQuotient::SeifertWeber];
+                    for q in quotients.into_iter().chain(loaded_polyhedron.clone()) {
+                        let name = q.name();
+                        ui.selectable_value(&mut selected, q, name);
+                    }
+                });
+            quotient.set_if_neq(selected);
+        });
+        if *quotient != RMQuotient::None && *geometry != Geometry::Hyperbolic {
+            ui.label("Quotients only apply in Hyperbolic space");
+        }
+    });
+}
+
+/// Lists every renderable, and edits the one selected here or by picking it.
+fn scene_editor_ui_system(
+    mut commands: Commands,
+    mut ctx: EguiContexts,
+    rm_camera: Res<RMCamera>,
+    geometry: Res<Geometry>,
+    mut selection: ResMut<RMSelection>,
+    mut new_shape: Local<usize>,
+    mut renderables: Query<(Entity, &mut RMRenderable, &mut HypTransform, Option<&Name>)>,
+) {
+    let geometry = *geometry;
+    let context = ctx.ctx_mut();
+    egui::Window::new("Scene").show(context, |ui| {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("new_shape")
+                .selected_text(RMShape::ALL[*new_shape].name())
+                .show_ui(ui, |ui| {
+                    for (i, shape) in RMShape::ALL.iter().enumerate() {
+                        ui.selectable_value(&mut *new_shape, i, shape.name());
+                    }
+                });
+            if ui.button("Add").clicked() {
+                // A unit ahead of wherever the camera is looking.
+                let ahead = rm_camera.orient.mat3() * Vec3::Z;
+                let transform = rm_camera.transform.clone()
+                    .translate_in(geometry, ahead, 1.0)
+                    .clone();
+                let renderable = RMRenderable::new(RMShape::ALL[*new_shape].clone(), RMMaterial::ALL[0].clone());
+                selection.0 = Some(commands.spawn((renderable, transform, RMSceneEntity)).id());
+            }
+        });
+
+        ui.separator();
+        let mut entities: Vec<Entity> = renderables.iter().map(|(entity, ..)| entity).collect();
+        entities.sort();
+        let mut removed = None;
+        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+            for &entity in &entities {
+                let Ok((_, mut renderable, _, name)) = renderables.get_mut(entity) else {
+                    continue;
+                };
+                ui.horizontal(|ui| {
+                    let mut visible = renderable.visible;
+                    if ui.checkbox(&mut visible, "").on_hover_text("Visible").changed() {
+                        renderable.set_visibility(visible);
+                    }
+                    let label = name.map_or_else(
+                        || format!("{} {entity}", renderable.shape.name()),
+                        |name| name.to_string(),
+                    );
+                    if ui.selectable_label(selection.0 == Some(entity), label).clicked() {
+                        selection.0 = Some(entity);
+                    }
+                    if ui.small_button("Remove").clicked() {
+                        removed = Some(entity);
+                    }
+                });
+            }
+        });
+        if let Some(entity) = removed {
+            commands.entity(entity).despawn_recursive();
+            if selection.0 == Some(entity) {
+                selection.0 = None;
+            }
+        }
+
+        let Some(Ok((_, mut renderable, mut transform, _))) =
+            selection.0.map(|entity| renderables.get_mut(entity))
+        else {
+            return;
+        };
+        ui.separator();
+        shape_editor(ui, &mut renderable.shape);
+        ui.separator();
+        material_editor(ui, &mut renderable.material);
+        ui.separator();
+        transform_gizmo(ui, geometry, &mut transform);
+    });
+}
+
+/// Every action's bindings. Clicking a binding removes it, `+` binds whatever is pressed next.
+fn controls_ui_system(
+    mut ctx: EguiContexts,
+    mut bindings: ResMut<RMBindings>,
+    mut rebinding: ResMut<RMRebinding>,
+) {
+    let context = ctx.ctx_mut();
+    egui::Window::new("Controls").default_open(false).show(context, |ui| {
+        egui::Grid::new("bindings").striped(true).show(ui, |ui| {
+            for action in RMAction::ALL {
+                ui.label(action.name());
+                ui.horizontal(|ui| {
+                    let mut removed = None;
+                    for (i, binding) in bindings.get(action).iter().enumerate() {
+                        if ui.small_button(binding.name()).on_hover_text("Remove").clicked() {
+                            removed = Some(i);
+                        }
+                    }
+                    if let Some(i) = removed {
+                        bindings.actions.entry(action).or_default().remove(i);
+                    }
+
+                    let waiting = rebinding.0 == Some(action);
+                    let label = if waiting { "Press a key or button..." } else { "+" };
+                    if ui.small_button(label).clicked() {
+                        rebinding.0 = if waiting { None } else { Some(action) };
+                    }
+                });
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        ui.label("Move Stick:");
+        stick_editor(ui, &mut bindings.move_stick, 0.0..=2.0);
+        ui.label("Look Stick:");
+        stick_editor(ui, &mut bindings.look_stick, 0.0..=10.0);
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            let path = bindings_path();
+            if ui.button("Save").clicked() {
+                match bindings.save(&path) {
+                    Ok(()) => info!("saved bindings to {}", path.display()),
+                    Err(err) => error!("could not save bindings to {}: {err}", path.display()),
+                }
+            }
+            if ui.button("Load").clicked() {
+                match RMBindings::load(&path) {
+                    Ok(loaded) => *bindings = loaded,
+                    Err(err) => error!("could not load bindings from {}: {err}", path.display()),
+                }
+            }
+            if ui.button("Reset").clicked() {
+                *bindings = RMBindings::default();
+            }
+        });
+    });
+}
+
+fn stick_editor(ui: &mut egui::Ui, stick: &mut RMStickSettings, sensitivity: RangeInclusive<f32>) {
+    slider(ui, "Dead Zone:", &mut stick.dead_zone, 0.0..=0.9);
+    slider(ui, "Exponent:", &mut stick.exponent, 0.5..=4.0);
+    slider(ui, "Sensitivity:", &mut stick.sensitivity, sensitivity);
+}
+
+fn slider<Num: egui::emath::Numeric>(ui: &mut egui::Ui, label: &str, value: &mut Num, range: RangeInclusive<Num>) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(egui::Slider::new(value, range));
+    });
+}
+
+fn color_edit(ui: &mut egui::Ui, label: &str, color: &mut LinearRgba) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let mut rgba = egui::Rgba::from_rgba_unmultiplied(color.red, color.green, color.blue, color.alpha);
+        if egui::color_picker::color_edit_button_rgba(ui, &mut rgba, egui::color_picker::Alpha::Opaque).changed() {
+            *color = LinearRgba::new(rgba.r(), rgba.g(), rgba.b(), rgba.a());
+        }
+    });
+}
+
+/// How far the value was dragged since last frame. `DragValue` reports the total since the drag
+/// began, so that is kept between frames and shown until the drag ends, when it goes back to zero.
+fn drag_delta(ui: &mut egui::Ui, label: &str, speed: f64) -> f32 {
+    let id = ui.id().with(("drag_delta", label));
+    let previous = ui.data(|data| data.get_temp::<f32>(id)).unwrap_or(0.0);
+    let mut total = previous;
+    let response = ui.add(egui::DragValue::new(&mut total).speed(speed).prefix(label));
+
+    if response.dragged() || response.has_focus() {
+        ui.data_mut(|data| data.insert_temp(id, total));
+    } else {
+        ui.data_mut(|data| data.remove::<f32>(id));
+    }
+    total - previous
+}
+
+fn shape_editor(ui: &mut egui::Ui, shape: &mut RMShape) {
+    ui.horizontal(|ui| {
+        ui.label("Shape:");
+        let mut selected = shape.name();
+        egui::ComboBox::from_id_salt("shape")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for s in RMShape::ALL.iter() {
+                    ui.selectable_value(&mut selected, s.name(), s.name());
+                }
+            });
+        if let Some(s) = RMShape::ALL.iter().find(|s| s.name() == selected && selected != shape.name()) {
+            *shape = s.clone();
+        }
+    });
+
+    match shape {
+        RMShape::Sphere { radius } => slider(ui, "Radius:", radius, 0.01..=2.0),
+        RMShape::Box { half_extents } => {
+            slider(ui, "Half Width:", &mut half_extents.x, 0.01..=2.0);
+            slider(ui, "Half Height:", &mut half_extents.y, 0.01..=2.0);
+            slider(ui, "Half Depth:", &mut half_extents.z, 0.01..=2.0);
+        }
+        RMShape::Cylinder { radius, half_length } => {
+            slider(ui, "Radius:", radius, 0.01..=2.0);
+            slider(ui, "Half Length:", half_length, 0.01..=2.0);
+        }
+        RMShape::Torus { major_radius, minor_radius } => {
+            slider(ui, "Major Radius:", major_radius, 0.01..=2.0);
+            slider(ui, "Minor Radius:", minor_radius, 0.01..=1.0);
+        }
+        RMShape::Plane | RMShape::Horosphere => {}
+        RMShape::Equidistant { distance } => slider(ui, "Distance:", distance, 0.0..=2.0),
+        RMShape::Mandelbulb { power, iterations, bailout, scale } => {
+            slider(ui, "Mandelbulb Power:", power, 1.0..=16.0);
+            slider(ui, "Mandelbulb Iterations:", iterations, 1..=64);
+            slider(ui, "Mandelbulb Bailout:", bailout, 1.0..=16.0);
+            slider(ui, "Mandelbulb Scale:", scale, 0.01..=2.0);
+        }
+    }
+}
+
+fn material_editor(ui: &mut egui::Ui, material: &mut RMMaterial) {
+    ui.horizontal(|ui| {
+        ui.label("Material:");
+        let mut selected = material.name();
+        egui::ComboBox::from_id_salt("material")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for m in RMMaterial::ALL.iter() {
+                    ui.selectable_value(&mut selected, m.name(), m.name());
+                }
+            });
+        if let Some(m) = RMMaterial::ALL.iter().find(|m| m.name() == selected && selected != material.name()) {
+            *material = m.clone();
+        }
+    });
+
+    match material {
+        RMMaterial::Flat(color) => color_edit(ui, "Color:", color),
+        RMMaterial::Emissive { color, intensity } => {
+            color_edit(ui, "Color:", color);
+            slider(ui, "Intensity:", intensity, 0.0..=20.0);
+        }
+        RMMaterial::Pbr { base_color, metallic, roughness } => {
+            color_edit(ui, "Base Color:", base_color);
+            slider(ui, "Metallic:", metallic, 0.0..=1.0);
+            slider(ui, "Roughness:", roughness, 0.0..=1.0);
+        }
+        RMMaterial::Checker { a, b, scale } | RMMaterial::Stripes { a, b, scale } => {
+            color_edit(ui, "Color A:", a);
+            color_edit(ui, "Color B:", b);
+            slider(ui, "Scale:", scale, 0.1..=20.0);
+        }
+        RMMaterial::Rainbow { scale } => slider(ui, "Scale:", scale, 0.1..=20.0),
+    }
+}
+
+/// Dragging a move handle slides the entity along the geodesic its local axis points down,
+/// dragging a rotate handle turns it about its own position. Both act in the parent's frame.
+fn transform_gizmo(ui: &mut egui::Ui, geometry: Geometry, transform: &mut Mut<HypTransform>) {
+    ui.label(format!("Position: [{}]", transform.translation));
+    ui.horizontal(|ui| {
+        ui.label("Move:");
+        for (axis, label) in [(Vec3::X, "Right: "), (Vec3::Y, "Up: "), (Vec3::Z, "Forward: ")] {
+            let t = drag_delta(ui, label, 0.01);
+            if t != 0.0 {
+                transform.translate_in(geometry, axis, t);
+            }
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Rotate:");
+        let pitch = drag_delta(ui, "Pitch: ", 0.01);
+        let yaw = drag_delta(ui, "Yaw: ", 0.01);
+        let roll = drag_delta(ui, "Roll: ", 0.01);
+        if pitch != 0.0 || yaw != 0.0 || roll != 0.0 {
+            transform.rotate_local_x(pitch).rotate_local_y(yaw).rotate_local_z(roll);
+        }
     });
 }